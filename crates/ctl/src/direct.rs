@@ -0,0 +1,126 @@
+//! Serving commands without a daemon.
+//!
+//! Switching starts the profile EXE, which stops its siblings itself; off runs the
+//! off EXE. Both go through the daemon's [`Controller`], so replies look the same
+//! whichever side answered. What is running is looked up with the runner's own
+//! sibling rules, as the daemon does ([`daemon::scan`]).
+
+use anyhow::Result;
+use daemon::controller::Controller;
+use daemon::launcher::ProcessLauncher;
+use daemon::protocol::{Command, ProfileInfo, Reply};
+use daemon::scan::{current_of, running};
+use family::Manifest;
+use std::path::{Path, PathBuf};
+
+/// Image name the sibling rules see for us; never part of a family.
+#[cfg_attr(not(windows), allow(dead_code))]
+const SELF_EXE: &str = "awcc-ctl.exe";
+
+pub struct Direct {
+    dir: PathBuf,
+    controller: Controller<ProcessLauncher>,
+}
+
+impl Direct {
+    pub fn new(dir: &Path, manifest: Manifest) -> Self {
+        Direct { dir: dir.to_path_buf(), controller: Controller::new(dir, manifest, ProcessLauncher) }
+    }
+
+    pub fn manifest(&self) -> &Manifest {
+        self.controller.manifest()
+    }
+
+    pub fn handle(&mut self, cmd: &Command) -> Result<Reply> {
+        match cmd {
+            // Without an off EXE there is nothing to launch; stop the profiles ourselves
+            Command::Off if self.manifest().off().is_none() => stop_all(&self.dir, self.manifest()),
+            Command::Switch { .. } | Command::Off => Ok(self.controller.handle(cmd)),
+            Command::Status => {
+                let found = running(&self.dir, self.manifest())?;
+                let current = current_of(&found, self.manifest());
+                let mut reply = Reply::ok(current.as_ref().map(|(name, _)| name.clone()));
+                reply.pid = current.map(|(_, pid)| pid);
+                Ok(reply)
+            }
+            Command::List => {
+                // The list is still useful when running processes cannot be looked up
+                let found = running(&self.dir, self.manifest()).unwrap_or_default();
+                let mut reply = Reply::ok(current_of(&found, self.manifest()).map(|(name, _)| name));
+                reply.profiles = Some(self.manifest().profiles().map(ProfileInfo::from).collect());
+                Ok(reply)
+            }
+        }
+    }
+}
+
+/// What the off EXE does, for families generated without `off_name`.
+#[cfg(windows)]
+fn stop_all(dir: &Path, manifest: &Manifest) -> Result<Reply> {
+    use daemon::controller::off_reply;
+    use family::siblings::{stop_siblings, OffStatus, SiblingRules};
+    use family::win32::{self, Win32ProcessTable};
+    use std::time::Duration;
+    let pid = std::process::id();
+    let mut rules = SiblingRules::for_dir(manifest, SELF_EXE, pid, dir).with_session(win32::session_of(pid));
+    rules.exit_wait = Duration::from_millis(manifest.runner.off_wait_ms as u64);
+    let outcomes = stop_siblings(&mut Win32ProcessTable, &rules);
+    Ok(off_reply(OffStatus::from_outcomes(&outcomes)))
+}
+
+#[cfg(not(windows))]
+fn stop_all(_dir: &Path, _manifest: &Manifest) -> Result<Reply> {
+    anyhow::bail!("this family has no off EXE; stopping profiles directly needs Windows")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use daemon::protocol::ErrorCode;
+    use family::manifest::MANIFEST_VERSION;
+    use family::{Entry, Role};
+
+    fn manifest() -> Manifest {
+        Manifest {
+            version: MANIFEST_VERSION,
+            generator_version: None,
+            profiles: vec![
+                Entry::new("red", "red.exe", Role::Profile),
+                Entry::new("navy", "navy.exe", Role::Profile),
+                Entry::new("off", "off.exe", Role::Off),
+            ],
+            awcc: None,
+            runner: Default::default(),
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn switch_and_off_work_without_a_daemon() {
+        use std::fs;
+        use std::os::unix::fs::PermissionsExt;
+        use std::time::{SystemTime, UNIX_EPOCH};
+
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos();
+        let dir = std::env::temp_dir().join(format!("awcc-ctl-test-{}-{}", std::process::id(), nanos));
+        fs::create_dir_all(&dir).unwrap();
+        // The off EXE reports a profile that would not stop
+        for (exe, body) in [("red.exe", "exit 0"), ("off.exe", "exit 3")] {
+            let path = dir.join(exe);
+            fs::write(&path, format!("#!/bin/sh\n{}\n", body)).unwrap();
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        }
+
+        let mut direct = Direct::new(&dir, manifest());
+        let reply = direct.handle(&Command::Switch { name: "Red".into() }).unwrap();
+        assert_eq!(reply, Reply::ok(Some("red".into())));
+        let reply = direct.handle(&Command::Switch { name: "navy".into() }).unwrap();
+        assert_eq!(reply.error.unwrap().code, ErrorCode::Spawn);
+        let reply = direct.handle(&Command::Off).unwrap();
+        assert_eq!(reply.error.unwrap().code, ErrorCode::StillRunning);
+        let list = direct.handle(&Command::List).unwrap();
+        assert_eq!(list.profiles.unwrap().len(), 2);
+        assert!(direct.handle(&Command::Status).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
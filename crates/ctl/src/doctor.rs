@@ -0,0 +1,185 @@
+//! `awcc-ctl doctor`: checks on a dist folder, each reported as ok, warning or error.
+
+use daemon::protocol::Reply;
+use family::manifest::MANIFEST_FILE;
+use family::siblings::Sibling;
+use family::switch::SEQUENCE_FILE;
+use family::Manifest;
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Level {
+    Ok,
+    Warn,
+    Error,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Check {
+    pub name: &'static str,
+    pub level: Level,
+    pub detail: String,
+}
+
+impl Check {
+    fn new(name: &'static str, level: Level, detail: impl Into<String>) -> Self {
+        Check { name, level, detail: detail.into() }
+    }
+}
+
+/// What doctor learns from outside the dist folder.
+pub struct Probe {
+    /// Endpoint and `status` reply of a daemon serving the folder, if one answered.
+    pub daemon: Option<(String, Reply)>,
+    /// Running profile EXEs, or why they could not be looked up.
+    pub running: Result<Vec<Sibling>, String>,
+}
+
+pub fn diagnose(dir: &Path, probe: &Probe) -> Vec<Check> {
+    let mut checks = Vec::new();
+    let manifest = match Manifest::load(dir) {
+        Ok(Some(m)) if dir.join(MANIFEST_FILE).exists() => {
+            checks.push(Check::new("manifest", Level::Ok, format!("{}, {} profiles", MANIFEST_FILE, m.profiles().count())));
+            Some(m)
+        }
+        Ok(Some(m)) => {
+            checks.push(Check::new("manifest", Level::Warn, "old family.txt/off.txt layout; run the generator again"));
+            Some(m)
+        }
+        Ok(None) => {
+            checks.push(Check::new("manifest", Level::Error, format!("no {} in {}; run the generator", MANIFEST_FILE, dir.display())));
+            None
+        }
+        Err(e) => {
+            checks.push(Check::new("manifest", Level::Error, e.to_string()));
+            None
+        }
+    };
+
+    if let Some(m) = &manifest {
+        let missing: Vec<&str> = m.profiles.iter().map(|e| e.exe.as_str()).filter(|exe| !dir.join(exe).is_file()).collect();
+        checks.push(if missing.is_empty() {
+            Check::new("exes", Level::Ok, format!("all {} EXEs present", m.profiles.len()))
+        } else {
+            Check::new("exes", Level::Error, format!("missing: {}", missing.join(", ")))
+        });
+
+        match m.off() {
+            Some(off) => checks.push(Check::new("off", Level::Ok, off.exe.clone())),
+            None => checks.push(Check::new("off", Level::Warn, "no off EXE (`off_name`); `off` stops profiles directly")),
+        }
+
+        if let Some(awcc) = &m.awcc {
+            if !Path::new(&awcc.path).is_file() {
+                checks.push(Check::new("awcc", Level::Warn, format!("AWCC not found at {}", awcc.path)));
+            }
+        }
+    }
+
+    let seq = dir.join(SEQUENCE_FILE);
+    if let Ok(text) = fs::read_to_string(&seq) {
+        if text.trim().parse::<u64>().is_err() {
+            checks.push(Check::new("switch_seq", Level::Warn, format!("{} is not a number; the next switch starts over at 1", SEQUENCE_FILE)));
+        }
+    }
+
+    checks.push(match &probe.daemon {
+        Some((endpoint, reply)) => Check::new(
+            "daemon",
+            Level::Ok,
+            format!("listening on {}, current: {}", endpoint, reply.current.as_deref().unwrap_or("none")),
+        ),
+        None => Check::new("daemon", Level::Ok, "not running; commands manage the EXEs directly"),
+    });
+
+    checks.push(match &probe.running {
+        Ok(found) if found.is_empty() => Check::new("running", Level::Ok, "no profile running"),
+        Ok(found) => {
+            let list: Vec<String> = found.iter().map(|s| format!("{} (pid {})", s.image_name, s.pid)).collect();
+            let level = if found.len() > 1 { Level::Warn } else { Level::Ok };
+            Check::new("running", level, list.join(", "))
+        }
+        Err(why) => Check::new("running", Level::Warn, why.clone()),
+    });
+    checks
+}
+
+/// Worst level among `checks`.
+pub fn worst(checks: &[Check]) -> Level {
+    checks.iter().map(|c| c.level).max().unwrap_or(Level::Ok)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use family::manifest::MANIFEST_VERSION;
+    use family::{Entry, Role};
+    use std::path::PathBuf;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    fn temp_dir(tag: &str) -> PathBuf {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos();
+        let dir = std::env::temp_dir().join(format!("awcc-doctor-test-{}-{}-{}", tag, std::process::id(), nanos));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn idle() -> Probe {
+        Probe { daemon: None, running: Ok(Vec::new()) }
+    }
+
+    fn levels(checks: &[Check]) -> Vec<(&str, Level)> {
+        checks.iter().map(|c| (c.name, c.level)).collect()
+    }
+
+    #[test]
+    fn healthy_folder_passes() {
+        let dir = temp_dir("ok");
+        let manifest = Manifest {
+            version: MANIFEST_VERSION,
+            generator_version: None,
+            profiles: vec![Entry::new("red", "red.exe", Role::Profile), Entry::new("off", "off.exe", Role::Off)],
+            awcc: None,
+            runner: Default::default(),
+        };
+        fs::write(dir.join(MANIFEST_FILE), manifest.to_json()).unwrap();
+        fs::write(dir.join("red.exe"), "").unwrap();
+        fs::write(dir.join("off.exe"), "").unwrap();
+
+        let checks = diagnose(&dir, &idle());
+        assert_eq!(
+            levels(&checks),
+            [("manifest", Level::Ok), ("exes", Level::Ok), ("off", Level::Ok), ("daemon", Level::Ok), ("running", Level::Ok)]
+        );
+        assert_eq!(worst(&checks), Level::Ok);
+
+        // Missing EXE, garbled sequence file, two profiles at once
+        fs::remove_file(dir.join("red.exe")).unwrap();
+        fs::write(dir.join(SEQUENCE_FILE), "x").unwrap();
+        let sib = |pid, name: &str| Sibling { pid, image_name: name.to_string(), created: None };
+        let probe = Probe { daemon: None, running: Ok(vec![sib(1, "red.exe"), sib(2, "navy.exe")]) };
+        let checks = diagnose(&dir, &probe);
+        let by_name = |n: &str| checks.iter().find(|c| c.name == n).unwrap().clone();
+        assert_eq!(by_name("exes").detail, "missing: red.exe");
+        assert_eq!(by_name("switch_seq").level, Level::Warn);
+        assert_eq!(by_name("running").detail, "red.exe (pid 1), navy.exe (pid 2)");
+        assert_eq!(worst(&checks), Level::Error);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn missing_or_legacy_manifest_is_reported() {
+        let dir = temp_dir("none");
+        let checks = diagnose(&dir, &Probe { daemon: None, running: Err("needs Windows".into()) });
+        assert_eq!(levels(&checks), [("manifest", Level::Error), ("daemon", Level::Ok), ("running", Level::Warn)]);
+
+        fs::write(dir.join("family.txt"), "red.exe\n").unwrap();
+        let checks = diagnose(&dir, &idle());
+        assert_eq!(checks[0].level, Level::Warn);
+        assert_eq!(checks[2], Check::new("off", Level::Warn, "no off EXE (`off_name`); `off` stops profiles directly"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,156 @@
+use anyhow::{Context, Result};
+use clap::{Parser, Subcommand};
+use daemon::protocol::{Command, Reply};
+use daemon::transport;
+use family::manifest::{LEGACY_FAMILY_FILE, LEGACY_OFF_FILE, MANIFEST_FILE};
+use family::Manifest;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+mod direct;
+mod doctor;
+
+use direct::Direct;
+use doctor::{Check, Level, Probe};
+
+#[derive(Debug, Parser)]
+#[command(name = "awcc-ctl", about = "Switch the profiles of a generated dist folder")]
+struct Opts {
+    #[command(subcommand)]
+    command: Cmd,
+    #[arg(long, global = true, help = "Dist folder (default: this EXE's folder if it holds a manifest, else ./dist)")]
+    dist: Option<PathBuf>,
+    #[arg(long, global = true, help = "Print JSON instead of text")]
+    json: bool,
+}
+
+#[derive(Debug, Subcommand)]
+enum Cmd {
+    /// Make NAME the active profile
+    Switch { name: String },
+    /// Stop the active profile (System Default)
+    Off,
+    /// Print the active profile
+    Current,
+    /// List the profiles, marking the active one
+    List,
+    /// Check the dist folder and report problems (exit code 1 on errors)
+    Doctor,
+}
+
+fn main() {
+    let opts = Opts::parse();
+    match run(&opts) {
+        Ok(code) => std::process::exit(code),
+        Err(e) => {
+            eprintln!("awcc-ctl: {:#}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn run(opts: &Opts) -> Result<i32> {
+    let dir = dist_dir(opts.dist.as_deref())?;
+    let cmd = match &opts.command {
+        Cmd::Switch { name } => Command::Switch { name: name.clone() },
+        Cmd::Off => Command::Off,
+        Cmd::Current => Command::Status,
+        Cmd::List => Command::List,
+        Cmd::Doctor => return doctor(&dir, opts.json),
+    };
+    let reply = ask(&dir, &cmd)?;
+    if opts.json {
+        print!("{}", reply.to_line());
+    } else if let Some(err) = &reply.error {
+        eprintln!("awcc-ctl: {}", err);
+    } else {
+        print_reply(&opts.command, &reply);
+    }
+    Ok(if reply.ok { 0 } else { 1 })
+}
+
+/// The daemon serving `dir` if there is one, otherwise the EXEs directly.
+fn ask(dir: &Path, cmd: &Command) -> Result<Reply> {
+    if let Ok(conn) = transport::connect(dir) {
+        return transport::request(&conn, cmd)
+            .with_context(|| format!("no answer from awcc-ctrld on {}", transport::endpoint(dir)));
+    }
+    Direct::new(dir, load_manifest(dir)?).handle(cmd)
+}
+
+fn load_manifest(dir: &Path) -> Result<Manifest> {
+    Manifest::load(dir)
+        .with_context(|| format!("cannot read manifest in {}", dir.display()))?
+        .with_context(|| format!("no {} in {}; run the generator first", MANIFEST_FILE, dir.display()))
+}
+
+fn print_reply(cmd: &Cmd, reply: &Reply) {
+    let current = reply.current.as_deref();
+    match cmd {
+        Cmd::Switch { .. } => println!("switched to {}", current.unwrap_or("off")),
+        Cmd::Off => println!("off"),
+        Cmd::Current => println!("{}", current.unwrap_or("(none)")),
+        Cmd::List => {
+            let profiles = reply.profiles.as_deref().unwrap_or_default();
+            let width = profiles.iter().map(|p| p.name.len()).max().unwrap_or(0);
+            for p in profiles {
+                let mark = if Some(p.name.as_str()) == current { '*' } else { ' ' };
+                let mut line = format!("{} {:<width$}  {}", mark, p.name, p.label, width = width);
+                if let Some(color) = &p.color {
+                    line.push_str(&format!("  {}", color));
+                }
+                if let Some(group) = &p.group {
+                    line.push_str(&format!("  [{}]", group));
+                }
+                println!("{}", line.trim_end());
+            }
+        }
+        Cmd::Doctor => {}
+    }
+}
+
+#[derive(Serialize)]
+struct DoctorReport<'a> {
+    ok: bool,
+    checks: &'a [Check],
+}
+
+fn doctor(dir: &Path, json: bool) -> Result<i32> {
+    let daemon = transport::connect(dir)
+        .ok()
+        .and_then(|conn| transport::request(&conn, &Command::Status).ok())
+        .map(|reply| (transport::endpoint(dir), reply));
+    let running = match load_manifest(dir) {
+        Ok(m) => daemon::scan::running(dir, &m).map_err(|e| e.to_string()),
+        Err(_) => Ok(Vec::new()),
+    };
+    let checks = doctor::diagnose(dir, &Probe { daemon, running });
+    let ok = doctor::worst(&checks) < Level::Error;
+    if json {
+        println!("{}", serde_json::to_string(&DoctorReport { ok, checks: &checks })?);
+    } else {
+        for c in &checks {
+            let level = match c.level {
+                Level::Ok => "ok",
+                Level::Warn => "warn",
+                Level::Error => "error",
+            };
+            println!("{:<5}  {:<10}  {}", level, c.name, c.detail);
+        }
+    }
+    Ok(if ok { 0 } else { 1 })
+}
+
+/// `--dist`, or the folder of this EXE when it was copied into a dist folder.
+fn dist_dir(arg: Option<&Path>) -> Result<PathBuf> {
+    let dir = match arg {
+        Some(d) => d.to_path_buf(),
+        None => std::env::current_exe()
+            .ok()
+            .and_then(|p| p.parent().map(Path::to_path_buf))
+            .filter(|d| [MANIFEST_FILE, LEGACY_FAMILY_FILE, LEGACY_OFF_FILE].iter().any(|f| d.join(f).exists()))
+            .unwrap_or_else(|| PathBuf::from("dist")),
+    };
+    // The daemon's endpoint is derived from the canonical path
+    dir.canonicalize().with_context(|| format!("dist folder not found: {}", dir.display()))
+}
@@ -0,0 +1,354 @@
+//! The daemon's state: which profile is active and the process running it.
+//!
+//! Starting, stopping and finding EXEs goes through [`Launcher`] so the switching
+//! rules can be tested without real runners.
+
+use crate::protocol::{Command, ErrorCode, ProfileInfo, Reply};
+use crate::scan;
+use family::siblings::{OffStatus, Sibling};
+use family::{HandoffOrder, Manifest, Role};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Extra time the off EXE gets beyond its own close and wait budget.
+const OFF_MARGIN: Duration = Duration::from_secs(2);
+
+pub trait Launcher {
+    type Child;
+
+    fn spawn(&mut self, exe: &Path) -> io::Result<Self::Child>;
+    fn pid(&self, child: &Self::Child) -> u32;
+    fn is_running(&mut self, child: &mut Self::Child) -> bool;
+    /// Ask `child` to close, force it after `grace`.
+    fn stop(&mut self, child: Self::Child, grace: Duration);
+    /// Run `exe` to completion; its exit code, or `None` if it outlived `timeout`.
+    fn run(&mut self, exe: &Path, timeout: Duration) -> io::Result<Option<i32>>;
+    /// Running profile EXEs of the family in `dir`, oldest first (see [`scan::running`]).
+    fn running(&mut self, dir: &Path, manifest: &Manifest) -> io::Result<Vec<Sibling>>;
+}
+
+struct Active<C> {
+    name: String,
+    child: C,
+}
+
+pub struct Controller<L: Launcher> {
+    dir: PathBuf,
+    manifest: Manifest,
+    launcher: L,
+    current: Option<Active<L::Child>>,
+}
+
+impl<L: Launcher> Controller<L> {
+    pub fn new(dir: &Path, manifest: Manifest, launcher: L) -> Self {
+        Controller { dir: dir.to_path_buf(), manifest, launcher, current: None }
+    }
+
+    pub fn manifest(&self) -> &Manifest {
+        &self.manifest
+    }
+
+    pub fn handle(&mut self, cmd: &Command) -> Reply {
+        match cmd {
+            Command::Switch { name } => self.switch(name),
+            Command::Off => self.off(),
+            Command::Status => {
+                let active = self.active();
+                let mut reply = Reply::ok(active.as_ref().map(|(name, _)| name.clone()));
+                reply.pid = active.map(|(_, pid)| pid);
+                reply
+            }
+            Command::List => {
+                let mut reply = Reply::ok(self.current());
+                reply.profiles = Some(self.manifest.profiles().map(ProfileInfo::from).collect());
+                reply
+            }
+        }
+    }
+
+    /// Active profile, see [`Controller::active`].
+    pub fn current(&mut self) -> Option<String> {
+        self.active().map(|(name, _)| name)
+    }
+
+    /// Active profile and its PID: the process we started while it runs, otherwise
+    /// the newest running member of the family. Ours is forgotten once it exits
+    /// (closed from the tray, or stopped by a runner launched elsewhere).
+    fn active(&mut self) -> Option<(String, u32)> {
+        if let Some(active) = self.current.as_mut() {
+            if self.launcher.is_running(&mut active.child) {
+                return Some((active.name.clone(), self.launcher.pid(&active.child)));
+            }
+            self.current = None;
+        }
+        let found = self.launcher.running(&self.dir, &self.manifest).ok()?;
+        scan::current_of(&found, &self.manifest)
+    }
+
+    fn switch(&mut self, name: &str) -> Reply {
+        let Some(entry) = self.manifest.entry_by_name(name) else {
+            return Reply::error(self.current(), ErrorCode::UnknownProfile, format!("no profile named \"{}\"", name));
+        };
+        if entry.role == Role::Off {
+            return self.off();
+        }
+        let (name, exe) = (entry.name.clone(), self.dir.join(&entry.exe));
+        if self.current().as_deref() == Some(name.as_str()) {
+            return Reply::ok(Some(name));
+        }
+        let child = match self.launcher.spawn(&exe) {
+            Ok(c) => c,
+            Err(e) => {
+                return Reply::error(self.current(), ErrorCode::Spawn, format!("cannot start {}: {}", exe.display(), e))
+            }
+        };
+        // New profile first, so there is no gap without a matching EXE. With
+        // start_first the new runner stops the old one itself after the overlap;
+        // waiting for that here would hold up every other caller of the controller.
+        if let Some(old) = self.current.replace(Active { name: name.clone(), child }) {
+            if self.manifest.runner.handoff.order != HandoffOrder::StartFirst {
+                self.launcher.stop(old.child, self.close_timeout());
+            }
+        }
+        Reply::ok(Some(name))
+    }
+
+    fn off(&mut self) -> Reply {
+        if let Some(old) = self.current.take() {
+            self.launcher.stop(old.child, self.close_timeout());
+        }
+        // The off EXE also sweeps siblings started outside the daemon
+        let Some(off) = self.manifest.off() else { return Reply::ok(None) };
+        let exe = self.dir.join(&off.exe);
+        let timeout = self.close_timeout() + Duration::from_millis(self.manifest.runner.off_wait_ms as u64) + OFF_MARGIN;
+        match self.launcher.run(&exe, timeout) {
+            Err(e) => Reply::error(None, ErrorCode::Spawn, format!("cannot run {}: {}", exe.display(), e)),
+            Ok(None) => Reply::error(None, ErrorCode::StillRunning, format!("{} did not finish", off.exe)),
+            Ok(Some(code)) => match OffStatus::from_exit_code(code) {
+                Some(status) => off_reply(status),
+                None => Reply::error(None, ErrorCode::Spawn, format!("{} exited with code {}", off.exe, code)),
+            },
+        }
+    }
+
+    fn close_timeout(&self) -> Duration {
+        Duration::from_millis(self.manifest.runner.close_timeout_ms as u64)
+    }
+}
+
+/// Reply for an off request that ended with `status`.
+pub fn off_reply(status: OffStatus) -> Reply {
+    match status {
+        OffStatus::SomeSurvived => Reply::error(None, ErrorCode::StillRunning, "some profiles are still running"),
+        OffStatus::AccessDenied => Reply::error(None, ErrorCode::AccessDenied, "not allowed to stop a running profile"),
+        // Superseded: a newer switch took over, which is what off asked for anyway
+        OffStatus::AllStopped | OffStatus::NothingRunning | OffStatus::Superseded => Reply::ok(None),
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod fake {
+    use super::*;
+    use family::manifest::MANIFEST_VERSION;
+    use family::Entry;
+    use std::sync::{Arc, Mutex};
+
+    /// Two profiles, one whose EXE cannot be started, and off.
+    pub fn manifest() -> Manifest {
+        let mut red = Entry::new("red", "red.exe", Role::Profile);
+        red.display_name = Some("Meeting red".to_string());
+        red.color = Some("#ff0000".to_string());
+        Manifest {
+            version: MANIFEST_VERSION,
+            generator_version: None,
+            profiles: vec![
+                red,
+                Entry::new("navy", "navy.exe", Role::Profile),
+                Entry::new("broken", "missing.exe", Role::Profile),
+                Entry::new("off", "off.exe", Role::Off),
+            ],
+            awcc: None,
+            runner: Default::default(),
+        }
+    }
+
+    /// What the fake launcher was asked to do, in order.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum Event {
+        Spawn(String),
+        Stop(String),
+        Run(String),
+    }
+
+    #[derive(Debug, Clone, Default)]
+    pub struct FakeLauncher {
+        pub events: Arc<Mutex<Vec<Event>>>,
+        /// Exit code the off EXE reports.
+        pub off_code: Option<i32>,
+        /// Names whose process has exited on its own.
+        pub exited: Arc<Mutex<Vec<String>>>,
+        /// What the sibling lookup finds (profiles started without the daemon).
+        pub found: Arc<Mutex<Vec<Sibling>>>,
+        next_pid: u32,
+    }
+
+    impl FakeLauncher {
+        pub fn new(off_code: Option<i32>) -> Self {
+            FakeLauncher { off_code, ..Default::default() }
+        }
+    }
+
+    pub struct FakeChild {
+        name: String,
+        pid: u32,
+    }
+
+    fn file_name(exe: &Path) -> String {
+        exe.file_name().unwrap().to_string_lossy().into_owned()
+    }
+
+    impl Launcher for FakeLauncher {
+        type Child = FakeChild;
+
+        fn spawn(&mut self, exe: &Path) -> io::Result<FakeChild> {
+            let name = file_name(exe);
+            if name.starts_with("missing") {
+                return Err(io::Error::new(io::ErrorKind::NotFound, "not found"));
+            }
+            self.next_pid += 1;
+            self.events.lock().unwrap().push(Event::Spawn(name.clone()));
+            Ok(FakeChild { name, pid: 1000 + self.next_pid })
+        }
+
+        fn pid(&self, child: &FakeChild) -> u32 {
+            child.pid
+        }
+
+        fn is_running(&mut self, child: &mut FakeChild) -> bool {
+            !self.exited.lock().unwrap().contains(&child.name)
+        }
+
+        fn stop(&mut self, child: FakeChild, _grace: Duration) {
+            self.events.lock().unwrap().push(Event::Stop(child.name));
+        }
+
+        fn run(&mut self, exe: &Path, _timeout: Duration) -> io::Result<Option<i32>> {
+            self.events.lock().unwrap().push(Event::Run(file_name(exe)));
+            Ok(self.off_code)
+        }
+
+        fn running(&mut self, _dir: &Path, _manifest: &Manifest) -> io::Result<Vec<Sibling>> {
+            Ok(self.found.lock().unwrap().clone())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::fake::{manifest, Event, FakeLauncher};
+    use super::*;
+
+    fn controller(off_code: Option<i32>) -> (Controller<FakeLauncher>, FakeLauncher) {
+        let launcher = FakeLauncher::new(off_code);
+        (Controller::new(Path::new("/dist"), manifest(), launcher.clone()), launcher)
+    }
+
+    fn switch(name: &str) -> Command {
+        Command::Switch { name: name.to_string() }
+    }
+
+    #[test]
+    fn switch_starts_new_profile_before_stopping_old() {
+        let (mut ctl, launcher) = controller(Some(0));
+        assert_eq!(ctl.handle(&switch("red")), Reply::ok(Some("red".into())));
+        assert_eq!(ctl.handle(&switch("NAVY")), Reply::ok(Some("navy".into())));
+        // Already active: nothing to do
+        assert_eq!(ctl.handle(&switch("navy")), Reply::ok(Some("navy".into())));
+        assert_eq!(
+            *launcher.events.lock().unwrap(),
+            [Event::Spawn("red.exe".into()), Event::Spawn("navy.exe".into()), Event::Stop("red.exe".into())]
+        );
+        let status = ctl.handle(&Command::Status);
+        assert_eq!((status.current.as_deref(), status.pid), (Some("navy"), Some(1002)));
+    }
+
+    #[test]
+    fn start_first_leaves_the_handoff_to_the_runner() {
+        let launcher = FakeLauncher::new(Some(0));
+        let mut m = manifest();
+        m.runner.handoff.order = HandoffOrder::StartFirst;
+        m.runner.handoff.overlap_ms = 60_000;
+        let mut ctl = Controller::new(Path::new("/dist"), m, launcher.clone());
+        ctl.handle(&switch("red"));
+        assert_eq!(ctl.handle(&switch("navy")), Reply::ok(Some("navy".into())));
+        assert_eq!(*launcher.events.lock().unwrap(), [Event::Spawn("red.exe".into()), Event::Spawn("navy.exe".into())]);
+    }
+
+    #[test]
+    fn off_stops_current_and_runs_off_exe() {
+        let (mut ctl, launcher) = controller(Some(OffStatus::AllStopped.exit_code()));
+        ctl.handle(&switch("red"));
+        assert_eq!(ctl.handle(&switch("off")), Reply::ok(None));
+        assert_eq!(
+            *launcher.events.lock().unwrap(),
+            [Event::Spawn("red.exe".into()), Event::Stop("red.exe".into()), Event::Run("off.exe".into())]
+        );
+        assert_eq!(ctl.handle(&Command::Status).current, None);
+
+        let (mut ctl, _) = controller(Some(OffStatus::SomeSurvived.exit_code()));
+        assert_eq!(ctl.handle(&Command::Off).error.unwrap().code, ErrorCode::StillRunning);
+        let (mut ctl, _) = controller(None);
+        assert_eq!(ctl.handle(&Command::Off).error.unwrap().code, ErrorCode::StillRunning);
+        let (mut ctl, _) = controller(Some(OffStatus::AccessDenied.exit_code()));
+        assert_eq!(ctl.handle(&Command::Off).error.unwrap().code, ErrorCode::AccessDenied);
+    }
+
+    #[test]
+    fn errors_keep_the_current_profile() {
+        let (mut ctl, _) = controller(Some(0));
+        ctl.handle(&switch("red"));
+        let reply = ctl.handle(&switch("bleu"));
+        assert_eq!(reply.error.as_ref().unwrap().code, ErrorCode::UnknownProfile);
+        assert_eq!(reply.current.as_deref(), Some("red"));
+        let reply = ctl.handle(&switch("broken"));
+        assert_eq!(reply.error.as_ref().unwrap().code, ErrorCode::Spawn);
+        assert_eq!(reply.current.as_deref(), Some("red"));
+    }
+
+    #[test]
+    fn exited_profile_is_forgotten() {
+        let (mut ctl, launcher) = controller(Some(0));
+        ctl.handle(&switch("red"));
+        launcher.exited.lock().unwrap().push("red.exe".into());
+        assert_eq!(ctl.handle(&Command::Status), Reply::ok(None));
+    }
+
+    #[test]
+    fn profiles_started_elsewhere_are_reported() {
+        let (mut ctl, launcher) = controller(Some(0));
+        let sibling = |pid, exe: &str| Sibling { pid, image_name: exe.to_string(), created: Some(pid as u64) };
+        launcher.found.lock().unwrap().push(sibling(50, "navy.exe"));
+        let status = ctl.handle(&Command::Status);
+        assert_eq!((status.current.as_deref(), status.pid), (Some("navy"), Some(50)));
+
+        // Our own child wins while it runs; once the tray switched away from it, the lookup does
+        ctl.handle(&switch("red"));
+        assert_eq!(ctl.current().as_deref(), Some("red"));
+        launcher.exited.lock().unwrap().push("red.exe".into());
+        launcher.found.lock().unwrap().push(sibling(60, "navy.exe"));
+        let status = ctl.handle(&Command::Status);
+        assert_eq!((status.current.as_deref(), status.pid), (Some("navy"), Some(60)));
+        assert_eq!(ctl.handle(&Command::List).current.as_deref(), Some("navy"));
+    }
+
+    #[test]
+    fn list_reports_profiles_without_off() {
+        let (mut ctl, _) = controller(Some(0));
+        let profiles = ctl.handle(&Command::List).profiles.unwrap();
+        let names: Vec<&str> = profiles.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["red", "navy", "broken"]);
+        assert_eq!(profiles[0].label, "Meeting red");
+        assert_eq!(profiles[0].color.as_deref(), Some("#ff0000"));
+    }
+}
@@ -0,0 +1,289 @@
+//! Home Assistant over MQTT: the active profile as a `select` entity.
+//!
+//! ```text
+//! <prefix>/<node>/state                      active profile name or "off" (retained)
+//! <prefix>/<node>/set                        profile name or "off" to switch
+//! <prefix>/<node>/availability               "online", or "offline" through the will (retained)
+//! <discovery>/select/<node>/profile/config   discovery payload (retained)
+//! ```
+//!
+//! Home Assistant announces its own restarts as "online" on `<discovery>/status`;
+//! discovery and state are sent again then, and after every reconnect.
+
+use crate::controller::{Controller, Launcher};
+use crate::mqtt::{ConnectOptions, Connection, Message, Packet};
+use crate::protocol::Command;
+use family::Manifest;
+use serde_json::json;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+/// Option and state value meaning no profile is running.
+pub const OFF: &str = "off";
+const ONLINE: &str = "online";
+const OFFLINE: &str = "offline";
+/// Longest wait between reconnect attempts.
+const MAX_RETRY: Duration = Duration::from_secs(60);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// How often the state is compared with what was last published; also catches profiles exiting on their own.
+const STATE_POLL: Duration = Duration::from_millis(250);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Config {
+    /// Broker as `host:port`.
+    pub broker: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub topic_prefix: String,
+    pub discovery_prefix: String,
+    /// Identifies this dist folder in topics, the client ID and the device.
+    pub node_id: String,
+    pub keep_alive: Duration,
+    /// First wait before reconnecting; doubles on each failure up to a minute.
+    pub retry: Duration,
+}
+
+impl Config {
+    /// Defaults for the dist folder `dir`: prefixes `awcc` and `homeassistant`,
+    /// and a node ID that stays the same as long as the folder does.
+    pub fn new(broker: &str, dir: &Path) -> Self {
+        let key = family::instance::family_key(&dir.to_string_lossy());
+        let hash = key.rsplit('.').next().unwrap_or(&key);
+        Config {
+            broker: broker.to_string(),
+            username: None,
+            password: None,
+            topic_prefix: "awcc".to_string(),
+            discovery_prefix: "homeassistant".to_string(),
+            node_id: format!("awcc_{}", hash),
+            keep_alive: Duration::from_secs(30),
+            retry: Duration::from_secs(1),
+        }
+    }
+
+    fn topic(&self, leaf: &str) -> String {
+        format!("{}/{}/{}", self.topic_prefix, self.node_id, leaf)
+    }
+
+    pub fn state_topic(&self) -> String {
+        self.topic("state")
+    }
+
+    pub fn command_topic(&self) -> String {
+        self.topic("set")
+    }
+
+    pub fn availability_topic(&self) -> String {
+        self.topic("availability")
+    }
+
+    pub fn discovery_topic(&self) -> String {
+        format!("{}/select/{}/profile/config", self.discovery_prefix, self.node_id)
+    }
+
+    fn connect_options(&self) -> ConnectOptions {
+        ConnectOptions {
+            client_id: self.node_id.clone(),
+            keep_alive: self.keep_alive,
+            username: self.username.clone(),
+            password: self.password.clone(),
+            will: Some(Message::new(&self.availability_topic(), OFFLINE, true)),
+        }
+    }
+}
+
+/// Retained discovery message: a `select` whose options are every profile plus "off".
+pub fn discovery(cfg: &Config, manifest: &Manifest) -> Message {
+    let mut options: Vec<&str> = manifest.profiles().map(|e| e.name.as_str()).collect();
+    if !options.contains(&OFF) {
+        options.push(OFF);
+    }
+    let payload = json!({
+        "name": "Lighting profile",
+        "unique_id": format!("{}_profile", cfg.node_id),
+        "icon": "mdi:led-strip-variant",
+        "state_topic": cfg.state_topic(),
+        "command_topic": cfg.command_topic(),
+        "availability_topic": cfg.availability_topic(),
+        "options": options,
+        "device": {
+            "identifiers": [cfg.node_id],
+            "name": "AWCC lighting",
+            "manufacturer": "awcc-ctrl-exe-moc",
+            "sw_version": manifest.generator_version.as_deref().unwrap_or(env!("CARGO_PKG_VERSION")),
+        },
+    });
+    Message::new(&cfg.discovery_topic(), payload.to_string(), true)
+}
+
+/// Command for a payload received on the command topic.
+pub fn command_for(payload: &[u8]) -> Command {
+    let name = String::from_utf8_lossy(payload).trim().to_string();
+    if name.eq_ignore_ascii_case(OFF) {
+        Command::Off
+    } else {
+        Command::Switch { name }
+    }
+}
+
+/// Stay connected to the broker forever, reconnecting with backoff whenever the
+/// connection fails.
+pub fn run<L: Launcher>(cfg: &Config, ctl: Arc<Mutex<Controller<L>>>) -> ! {
+    let mut wait = cfg.retry;
+    loop {
+        match Connection::connect(&cfg.broker, &cfg.connect_options(), CONNECT_TIMEOUT) {
+            Ok(conn) => {
+                eprintln!("awcc-ctrld: mqtt: connected to {}", cfg.broker);
+                wait = cfg.retry;
+                let Err(e) = session(conn, cfg, &ctl);
+                eprintln!("awcc-ctrld: mqtt: {}; reconnecting", e);
+            }
+            Err(e) => eprintln!("awcc-ctrld: mqtt: cannot reach {}: {}; retrying in {}s", cfg.broker, e, wait.as_secs()),
+        }
+        std::thread::sleep(wait);
+        wait = (wait * 2).min(MAX_RETRY);
+    }
+}
+
+/// Serve one connection until it fails.
+fn session<L: Launcher>(mut conn: Connection, cfg: &Config, ctl: &Mutex<Controller<L>>) -> io::Result<std::convert::Infallible> {
+    let (state_topic, command_topic) = (cfg.state_topic(), cfg.command_topic());
+    let ha_status = format!("{}/status", cfg.discovery_prefix);
+    let announce = |conn: &mut Connection| -> io::Result<()> {
+        let m = discovery(cfg, lock(ctl).manifest());
+        conn.send(&Packet::Publish(m))?;
+        conn.publish(&cfg.availability_topic(), ONLINE, true)
+    };
+    conn.subscribe(&[&command_topic, &ha_status])?;
+    announce(&mut conn)?;
+    let mut published: Option<String> = None;
+    loop {
+        let state = lock(ctl).current().unwrap_or_else(|| OFF.to_string());
+        if published.as_ref() != Some(&state) {
+            conn.publish(&state_topic, state.as_bytes(), true)?;
+            published = Some(state);
+        }
+        match conn.poll(STATE_POLL)? {
+            Some(Packet::Publish(m)) if m.topic == command_topic => {
+                let reply = lock(ctl).handle(&command_for(&m.payload));
+                if let Some(err) = reply.error {
+                    eprintln!("awcc-ctrld: mqtt: {}", err);
+                }
+            }
+            Some(Packet::Publish(m)) if m.topic == ha_status && m.payload == ONLINE.as_bytes() => {
+                announce(&mut conn)?;
+                published = None;
+            }
+            _ => {}
+        }
+    }
+}
+
+fn lock<L: Launcher>(ctl: &Mutex<Controller<L>>) -> MutexGuard<'_, Controller<L>> {
+    ctl.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::controller::fake::{manifest, FakeLauncher};
+    use crate::mqtt::fake::Broker;
+    use std::path::PathBuf;
+
+    fn config(broker: &Broker) -> Config {
+        let mut cfg = Config::new(&broker.addr.to_string(), Path::new("C:/dist"));
+        cfg.retry = Duration::from_millis(50);
+        cfg
+    }
+
+    /// A client watching `topics`, returning the next message on any of them.
+    fn watch(broker: &Broker, topics: &[&str]) -> impl FnMut() -> Message {
+        let opts = ConnectOptions {
+            client_id: "watcher".into(),
+            keep_alive: Duration::from_secs(30),
+            username: None,
+            password: None,
+            will: None,
+        };
+        let mut conn = Connection::connect(&broker.addr.to_string(), &opts, Duration::from_secs(5)).unwrap();
+        conn.subscribe(topics).unwrap();
+        move || loop {
+            match conn.poll(Duration::from_secs(5)).unwrap() {
+                Some(Packet::Publish(m)) => return m,
+                Some(_) => {}
+                None => panic!("no message within 5s"),
+            }
+        }
+    }
+
+    fn wait_for(mut next: impl FnMut() -> Message, topic: &str, payload: &str) {
+        loop {
+            let m = next();
+            if m.topic == topic && m.payload == payload.as_bytes() {
+                return;
+            }
+        }
+    }
+
+    #[test]
+    fn discovery_lists_profiles_and_off() {
+        let cfg = Config::new("localhost:1883", Path::new("C:/dist"));
+        assert!(cfg.node_id.starts_with("awcc_") && cfg.node_id.len() == 5 + 16);
+        assert_eq!(cfg, Config::new("localhost:1883", &PathBuf::from("c:\\DIST")), "same folder, same node");
+
+        let m = discovery(&cfg, &manifest());
+        assert_eq!(m.topic, format!("homeassistant/select/{}/profile/config", cfg.node_id));
+        assert!(m.retain);
+        let v: serde_json::Value = serde_json::from_slice(&m.payload).unwrap();
+        assert_eq!(v["options"], json!(["red", "navy", "broken", "off"]));
+        assert_eq!(v["command_topic"], format!("awcc/{}/set", cfg.node_id));
+        assert_eq!(v["unique_id"], format!("{}_profile", cfg.node_id));
+
+        assert_eq!(command_for(b" Off\n"), Command::Off);
+        assert_eq!(command_for(b"navy"), Command::Switch { name: "navy".into() });
+    }
+
+    #[test]
+    fn bridge_follows_commands_and_reconnects() {
+        let broker = Broker::start();
+        let cfg = config(&broker);
+        let ctl = Arc::new(Mutex::new(Controller::new(Path::new("dist"), manifest(), FakeLauncher::new(Some(0)))));
+        let (state, availability) = (cfg.state_topic(), cfg.availability_topic());
+        let mut next = watch(&broker, &[&state, &availability]);
+        {
+            let (cfg, ctl) = (cfg.clone(), ctl.clone());
+            std::thread::spawn(move || run(&cfg, ctl));
+        }
+        wait_for(&mut next, &state, "off");
+        assert!(broker.retained(&cfg.discovery_topic()).is_some());
+        assert_eq!(broker.retained(&availability).unwrap().payload, b"online");
+
+        let mut commander = Connection::connect(
+            &broker.addr.to_string(),
+            &ConnectOptions { client_id: "ha".into(), keep_alive: Duration::ZERO, username: None, password: None, will: None },
+            Duration::from_secs(5),
+        )
+        .unwrap();
+        // A typo is reported and changes nothing
+        commander.publish(&cfg.command_topic(), "nope", false).unwrap();
+        commander.publish(&cfg.command_topic(), "navy", false).unwrap();
+        wait_for(&mut next, &state, "navy");
+        assert_eq!(ctl.lock().unwrap().current().as_deref(), Some("navy"));
+
+        // Broker restart: the will marks us offline, then the bridge comes back
+        broker.drop_clients();
+        let mut next = watch(&broker, &[&state, &availability]);
+        wait_for(&mut next, &availability, "online");
+        let mut commander = Connection::connect(
+            &broker.addr.to_string(),
+            &ConnectOptions { client_id: "ha".into(), keep_alive: Duration::ZERO, username: None, password: None, will: None },
+            Duration::from_secs(5),
+        )
+        .unwrap();
+        commander.publish(&cfg.command_topic(), "off", false).unwrap();
+        wait_for(&mut next, &state, "off");
+        assert!(broker.published().iter().any(|m| m.topic == availability && m.payload == b"offline"));
+    }
+}
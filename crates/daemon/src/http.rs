@@ -0,0 +1,475 @@
+//! Optional HTTP API on 127.0.0.1, for tools that can only make web requests.
+//!
+//! ```text
+//! GET  /                 dashboard page (one button per profile)
+//! GET  /events           server-sent events: the `list` reply whenever it changes
+//! GET  /profiles         list (same body as the `list` reply)
+//! GET  /current          active profile (same body as the `status` reply)
+//! POST /profiles/{name}  switch
+//! POST /off              off
+//! ```
+//!
+//! Bodies are the JSON replies of the pipe protocol; failures map their error
+//! code to an HTTP status. With a token configured every request except the
+//! page itself needs `Authorization: Bearer <token>` or `?access_token=<token>`
+//! (`EventSource` cannot send headers).
+//!
+//! Other web pages open in the user's browser must not be able to drive the API:
+//! the `Host` must be an IP address or `localhost` (no DNS rebinding), and a
+//! browser's `Origin`, if sent, must be this server.
+
+use crate::controller::{Controller, Launcher};
+use crate::protocol::{Command, ErrorCode, Reply};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{IpAddr, SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// The dashboard, bundled so it works without network access.
+const DASHBOARD: &str = include_str!("dashboard.html");
+
+/// Longest request head (request line plus headers) accepted.
+const MAX_HEAD: usize = 8 * 1024;
+/// Largest request body read (and discarded); the API takes no bodies.
+const MAX_BODY: usize = 64 * 1024;
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+/// How often an event stream looks for a new state; also catches profiles exiting on their own.
+const EVENT_POLL: Duration = Duration::from_millis(250);
+/// Comment line sent on idle event streams so proxies and phones keep them open.
+const EVENT_KEEPALIVE: Duration = Duration::from_secs(15);
+
+pub fn bind(addr: IpAddr, port: u16) -> io::Result<TcpListener> {
+    TcpListener::bind(SocketAddr::from((addr, port)))
+}
+
+/// Accept HTTP clients forever, one thread each, sharing the pipe server's controller.
+pub fn listen<L>(listener: TcpListener, ctl: Arc<Mutex<Controller<L>>>, token: Option<String>) -> io::Result<()>
+where
+    L: Launcher + Send + 'static,
+    L::Child: Send,
+{
+    let token: Arc<Option<String>> = Arc::new(token);
+    loop {
+        let (stream, _) = listener.accept()?;
+        let (ctl, token) = (ctl.clone(), token.clone());
+        std::thread::spawn(move || {
+            let _ = serve_connection(stream, &ctl, token.as_deref());
+        });
+    }
+}
+
+/// Answer one request; connections are not kept alive.
+fn serve_connection<L: Launcher>(stream: TcpStream, ctl: &Mutex<Controller<L>>, token: Option<&str>) -> io::Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut reader = BufReader::new(&stream);
+    let response = match read_request(&mut reader) {
+        Ok(req) if req.method == "GET" && req.path == "/events" => match authorize(&req, token) {
+            Ok(()) => return stream_events(&stream, ctl),
+            Err(resp) => resp,
+        },
+        Ok(req) => respond(&req, ctl, token),
+        Err(resp) => resp,
+    };
+    (&stream).write_all(&response.to_bytes())
+}
+
+/// Push the `list` reply as a `state` event now and whenever it changes, until the client leaves.
+fn stream_events<L: Launcher>(mut stream: &TcpStream, ctl: &Mutex<Controller<L>>) -> io::Result<()> {
+    stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-store\r\n\r\n")?;
+    let mut last = String::new();
+    let mut idle = Duration::ZERO;
+    loop {
+        let state = ctl.lock().unwrap_or_else(|e| e.into_inner()).handle(&Command::List).to_line();
+        if state != last {
+            stream.write_all(format!("event: state\ndata: {}\n\n", state.trim_end()).as_bytes())?;
+            last = state;
+            idle = Duration::ZERO;
+        } else if idle >= EVENT_KEEPALIVE {
+            stream.write_all(b": keepalive\n\n")?;
+            idle = Duration::ZERO;
+        }
+        std::thread::sleep(EVENT_POLL);
+        idle += EVENT_POLL;
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Request {
+    pub method: String,
+    /// Path without the query string.
+    pub path: String,
+    pub query: String,
+    /// Header names lowercased.
+    pub headers: Vec<(String, String)>,
+}
+
+impl Request {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)).map(|(_, v)| v.as_str())
+    }
+
+    /// Decoded value of the query parameter `name`.
+    pub fn query_param(&self, name: &str) -> Option<String> {
+        self.query.split('&').find_map(|kv| {
+            let (k, v) = kv.split_once('=')?;
+            (k == name).then(|| percent_decode(&v.replace('+', " "))).flatten()
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Response {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub headers: Vec<(&'static str, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    pub fn json(status: u16, reply: &Reply) -> Self {
+        Response {
+            status,
+            content_type: "application/json",
+            headers: Vec::new(),
+            body: reply.to_line().into_bytes(),
+        }
+    }
+
+    fn error(status: u16, code: ErrorCode, message: impl Into<String>) -> Self {
+        Response::json(status, &Reply::error(None, code, message))
+    }
+
+    fn html(body: &str) -> Self {
+        Response { status: 200, content_type: "text/html; charset=utf-8", headers: Vec::new(), body: body.as_bytes().to_vec() }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut head = format!(
+            "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n",
+            self.status,
+            reason(self.status),
+            self.content_type,
+            self.body.len()
+        );
+        for (name, value) in &self.headers {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        head.push_str("\r\n");
+        let mut out = head.into_bytes();
+        out.extend_from_slice(&self.body);
+        out
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        413 => "Payload Too Large",
+        431 => "Request Header Fields Too Large",
+        _ => "Internal Server Error",
+    }
+}
+
+/// Read the request head and skip its body. Errors are returned as the response to send.
+pub fn read_request(reader: &mut impl BufRead) -> Result<Request, Response> {
+    let bad = |msg: &str| Response::error(400, ErrorCode::BadRequest, msg);
+    let mut head_len = 0;
+    let mut read_line = |reader: &mut dyn BufRead| -> Result<String, Response> {
+        let mut line = Vec::new();
+        let n = reader
+            .take((MAX_HEAD - head_len + 1) as u64)
+            .read_until(b'\n', &mut line)
+            .map_err(|_| bad("connection error"))?;
+        head_len += n;
+        if head_len > MAX_HEAD {
+            return Err(Response::error(431, ErrorCode::BadRequest, "request head too large"));
+        }
+        if n == 0 {
+            return Err(bad("incomplete request"));
+        }
+        String::from_utf8(line).map(|s| s.trim_end().to_string()).map_err(|_| bad("request head is not UTF-8"))
+    };
+
+    let request_line = read_line(reader)?;
+    let mut parts = request_line.split(' ');
+    let (Some(method), Some(target), Some(version)) = (parts.next(), parts.next(), parts.next()) else {
+        return Err(bad("malformed request line"));
+    };
+    if !version.starts_with("HTTP/1.") {
+        return Err(bad("only HTTP/1.x is supported"));
+    }
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+
+    let mut headers = Vec::new();
+    loop {
+        let line = read_line(reader)?;
+        if line.is_empty() {
+            break;
+        }
+        let Some((name, value)) = line.split_once(':') else { return Err(bad("malformed header")) };
+        headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+    }
+    let req = Request { method: method.to_string(), path: path.to_string(), query: query.to_string(), headers };
+
+    let length: usize = match req.header("content-length") {
+        None => 0,
+        Some(v) => v.parse().map_err(|_| bad("invalid Content-Length"))?,
+    };
+    if length > MAX_BODY {
+        return Err(Response::error(413, ErrorCode::BadRequest, "request body too large"));
+    }
+    if req.header("transfer-encoding").is_some() {
+        return Err(bad("chunked bodies are not supported"));
+    }
+    io::copy(&mut reader.take(length as u64), &mut io::sink()).map_err(|_| bad("connection error"))?;
+    Ok(req)
+}
+
+/// Route `req` to the dashboard or the controller.
+pub fn respond<L: Launcher>(req: &Request, ctl: &Mutex<Controller<L>>, token: Option<&str>) -> Response {
+    if req.method == "GET" && req.path == "/" {
+        // The page holds no state; it asks for the token itself
+        return check_origin(req).map_or_else(|resp| resp, |()| Response::html(DASHBOARD));
+    }
+    if let Err(resp) = authorize(req, token) {
+        return resp;
+    }
+    let cmd = match route(&req.method, &req.path) {
+        Ok(cmd) => cmd,
+        Err(resp) => return resp,
+    };
+    let reply = ctl.lock().unwrap_or_else(|e| e.into_inner()).handle(&cmd);
+    let status = match reply.error.as_ref().map(|e| e.code) {
+        None => 200,
+        Some(ErrorCode::BadRequest | ErrorCode::UnsupportedVersion) => 400,
+        Some(ErrorCode::UnknownProfile) => 404,
+        Some(ErrorCode::StillRunning) => 409,
+        Some(ErrorCode::AccessDenied) => 403,
+        Some(ErrorCode::Spawn) => 500,
+    };
+    Response::json(status, &reply)
+}
+
+fn route(method: &str, path: &str) -> Result<Command, Response> {
+    let path = path.trim_end_matches('/');
+    let (allowed, cmd) = match path.split('/').collect::<Vec<_>>().as_slice() {
+        ["", "profiles"] => ("GET", Command::List),
+        ["", "current"] => ("GET", Command::Status),
+        ["", "off"] => ("POST", Command::Off),
+        ["", "profiles", name] => {
+            let name = percent_decode(name).ok_or_else(|| Response::error(400, ErrorCode::BadRequest, "invalid profile name"))?;
+            ("POST", Command::Switch { name })
+        }
+        _ => return Err(Response::error(404, ErrorCode::BadRequest, format!("no such endpoint: {}", path))),
+    };
+    if method != allowed {
+        let mut resp = Response::error(405, ErrorCode::BadRequest, format!("use {} for {}", allowed, path));
+        resp.headers.push(("Allow", allowed.to_string()));
+        return Err(resp);
+    }
+    Ok(cmd)
+}
+
+/// Origin check, then the bearer token if one is configured.
+fn authorize(req: &Request, token: Option<&str>) -> Result<(), Response> {
+    check_origin(req)?;
+    let Some(token) = token else { return Ok(()) };
+    let given = match req.header("authorization") {
+        Some(v) => v.strip_prefix("Bearer ").map(|t| t.trim().to_string()),
+        None => req.query_param("access_token"),
+    };
+    if given.is_some_and(|g| same_secret(&g, token)) {
+        return Ok(());
+    }
+    let mut resp = Response::error(401, ErrorCode::BadRequest, "missing or wrong bearer token");
+    resp.headers.push(("WWW-Authenticate", "Bearer".to_string()));
+    Err(resp)
+}
+
+fn check_origin(req: &Request) -> Result<(), Response> {
+    let host = req.header("host").unwrap_or("");
+    let same_origin = req.header("origin").is_none_or(|o| o == format!("http://{}", host));
+    if names_address(host) && same_origin {
+        Ok(())
+    } else {
+        Err(Response::error(403, ErrorCode::BadRequest, "requests from other sites are refused"))
+    }
+}
+
+/// `Host` value that is an IP address or `localhost` (any port), not a DNS name
+/// an attacker could point at us.
+fn names_address(host: &str) -> bool {
+    let name = match host.strip_prefix('[') {
+        Some(v6) => v6.split(']').next().unwrap_or(""),
+        None => host.rsplit_once(':').filter(|(_, port)| port.chars().all(|c| c.is_ascii_digit())).map_or(host, |(h, _)| h),
+    };
+    name == "localhost" || name.parse::<IpAddr>().is_ok()
+}
+
+/// Compare without returning early on the first differing byte.
+fn same_secret(a: &str, b: &str) -> bool {
+    a.len() == b.len() && a.bytes().zip(b.bytes()).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn percent_decode(s: &str) -> Option<String> {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+            out.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(out).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::controller::fake::{manifest, FakeLauncher};
+    use std::net::SocketAddr;
+    use std::path::Path;
+
+    /// Start a server on an ephemeral port.
+    fn server(token: Option<&str>) -> SocketAddr {
+        let listener = bind(IpAddr::from([127, 0, 0, 1]), 0).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let ctl = Arc::new(Mutex::new(Controller::new(Path::new("/dist"), manifest(), FakeLauncher::new(Some(0)))));
+        let token = token.map(str::to_string);
+        std::thread::spawn(move || listen(listener, ctl, token));
+        addr
+    }
+
+    /// Minimal HTTP/1.1 client: status code and body.
+    fn call(addr: SocketAddr, method: &str, path: &str, headers: &[&str]) -> (u16, String) {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let mut req = format!("{} {} HTTP/1.1\r\n", method, path);
+        if !headers.iter().any(|h| h.starts_with("Host:")) {
+            req.push_str(&format!("Host: {}\r\n", addr));
+        }
+        for h in headers {
+            req.push_str(h);
+            req.push_str("\r\n");
+        }
+        req.push_str("\r\n");
+        stream.write_all(req.as_bytes()).unwrap();
+        let mut raw = String::new();
+        stream.read_to_string(&mut raw).unwrap();
+        let (head, body) = raw.split_once("\r\n\r\n").unwrap();
+        let status = head.split(' ').nth(1).unwrap().parse().unwrap();
+        (status, body.to_string())
+    }
+
+    fn reply(body: &str) -> Reply {
+        serde_json::from_str(body).unwrap()
+    }
+
+    #[test]
+    fn endpoints_drive_the_controller() {
+        let addr = server(None);
+        let (status, body) = call(addr, "GET", "/profiles", &[]);
+        assert_eq!(status, 200);
+        let names: Vec<String> = reply(&body).profiles.unwrap().into_iter().map(|p| p.name).collect();
+        assert_eq!(names, ["red", "navy", "broken"]);
+
+        assert_eq!(call(addr, "POST", "/profiles/Navy", &["Content-Length: 0"]).0, 200);
+        let (status, body) = call(addr, "GET", "/current?x=1", &[]);
+        assert_eq!((status, reply(&body).current.as_deref()), (200, Some("navy")));
+
+        let (status, body) = call(addr, "POST", "/profiles/bl%65u", &[]);
+        assert_eq!(status, 404);
+        assert_eq!(reply(&body).error.unwrap().message, "no profile named \"bleu\"");
+        assert_eq!(call(addr, "POST", "/profiles/broken", &[]).0, 500);
+
+        let (status, body) = call(addr, "POST", "/off", &[]);
+        assert_eq!((status, reply(&body)), (200, Reply::ok(None)));
+    }
+
+    #[test]
+    fn bad_routes_and_methods() {
+        let addr = server(None);
+        assert_eq!(call(addr, "POST", "/profiles", &[]).0, 405);
+        assert_eq!(call(addr, "GET", "/off", &[]).0, 405);
+        assert_eq!(call(addr, "GET", "/nope", &[]).0, 404);
+        assert_eq!(call(addr, "GET", "/profiles/red/x", &[]).0, 404);
+    }
+
+    #[test]
+    fn bearer_token_is_required_when_set() {
+        let addr = server(Some("s3cret"));
+        assert_eq!(call(addr, "GET", "/current", &[]).0, 401);
+        assert_eq!(call(addr, "GET", "/current", &["Authorization: Bearer wrong!"]).0, 401);
+        assert_eq!(call(addr, "GET", "/current", &["Authorization: Bearer s3cret"]).0, 200);
+    }
+
+    #[test]
+    fn other_sites_are_refused() {
+        let addr = server(None);
+        assert_eq!(call(addr, "POST", "/off", &["Origin: https://evil.example"]).0, 403);
+        assert_eq!(call(addr, "POST", "/off", &["Origin: http://localhost:8080"]).0, 403);
+        assert_eq!(call(addr, "POST", "/off", &[&format!("Origin: http://{}", addr)]).0, 200);
+        // DNS rebinding: a hostile name resolving to us
+        assert_eq!(call(addr, "GET", "/current", &["Host: evil.example:8765"]).0, 403);
+        assert_eq!(call(addr, "GET", "/", &["Host: evil.example"]).0, 403);
+        assert!(names_address("192.168.1.20:8765") && names_address("[::1]:80") && names_address("localhost"));
+        assert!(!names_address("127.0.0.1.evil.example:80"));
+    }
+
+    #[test]
+    fn dashboard_is_served_without_token() {
+        let addr = server(Some("s3cret"));
+        let (status, body) = call(addr, "GET", "/", &[]);
+        assert_eq!(status, 200);
+        assert!(body.contains("new EventSource(\"/events\""), "{}", body);
+        // Bundled: nothing is fetched from elsewhere
+        assert!(!body.contains("https://") && !body.contains("http://"));
+        assert_eq!(call(addr, "GET", "/profiles?access_token=s3cret", &[]).0, 200);
+        assert_eq!(call(addr, "GET", "/profiles?access_token=nope", &[]).0, 401);
+    }
+
+    #[test]
+    fn events_push_state_changes() {
+        let addr = server(Some("tok en"));
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        write!(stream, "GET /events?access_token=tok%20en HTTP/1.1\r\nHost: {}\r\n\r\n", addr).unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut next_state = || -> Reply {
+            let mut line = String::new();
+            loop {
+                line.clear();
+                reader.read_line(&mut line).unwrap();
+                if let Some(data) = line.strip_prefix("data: ") {
+                    return serde_json::from_str(data).unwrap();
+                }
+            }
+        };
+        let first = next_state();
+        assert_eq!((first.current, first.profiles.unwrap().len()), (None, 3));
+        assert_eq!(call(addr, "POST", "/profiles/red", &["Authorization: Bearer tok en"]).0, 200);
+        assert_eq!(next_state().current.as_deref(), Some("red"));
+
+        let (status, _) = call(addr, "GET", "/events", &[]);
+        assert_eq!(status, 401);
+    }
+
+    #[test]
+    fn oversized_heads_are_rejected() {
+        let mut input = b"GET /current HTTP/1.1\r\nX-Pad: ".to_vec();
+        input.extend(std::iter::repeat_n(b'a', MAX_HEAD));
+        input.extend_from_slice(b"\r\n\r\n");
+        assert_eq!(read_request(&mut &input[..]).unwrap_err().status, 431);
+        assert_eq!(read_request(&mut &b"GARBAGE\r\n\r\n"[..]).unwrap_err().status, 400);
+    }
+}
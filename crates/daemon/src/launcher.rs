@@ -0,0 +1,84 @@
+//! [`Launcher`] backed by real processes.
+
+use crate::controller::Launcher;
+use crate::scan;
+use family::siblings::Sibling;
+use family::Manifest;
+use std::io;
+use std::path::Path;
+use std::process::{Child, Command};
+use std::time::{Duration, Instant};
+
+const POLL: Duration = Duration::from_millis(50);
+
+#[derive(Debug, Default)]
+pub struct ProcessLauncher;
+
+impl Launcher for ProcessLauncher {
+    type Child = Child;
+
+    fn spawn(&mut self, exe: &Path) -> io::Result<Child> {
+        let mut cmd = Command::new(exe);
+        if let Some(dir) = exe.parent() {
+            cmd.current_dir(dir);
+        }
+        cmd.spawn()
+    }
+
+    fn pid(&self, child: &Child) -> u32 {
+        child.id()
+    }
+
+    fn is_running(&mut self, child: &mut Child) -> bool {
+        matches!(child.try_wait(), Ok(None))
+    }
+
+    fn stop(&mut self, mut child: Child, grace: Duration) {
+        if request_close(child.id()) && wait(&mut child, grace).is_some() {
+            return;
+        }
+        let _ = child.kill();
+        let _ = child.wait();
+    }
+
+    fn run(&mut self, exe: &Path, timeout: Duration) -> io::Result<Option<i32>> {
+        let mut child = self.spawn(exe)?;
+        match wait(&mut child, timeout) {
+            // Killed by a signal (Unix only): report as a failure code
+            Some(code) => Ok(Some(code.unwrap_or(-1))),
+            None => {
+                let _ = child.kill();
+                let _ = child.wait();
+                Ok(None)
+            }
+        }
+    }
+
+    fn running(&mut self, dir: &Path, manifest: &Manifest) -> io::Result<Vec<Sibling>> {
+        scan::running(dir, manifest)
+    }
+}
+
+/// Exit code of `child` once it exits within `timeout`.
+fn wait(child: &mut Child, timeout: Duration) -> Option<Option<i32>> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) => return Some(status.code()),
+            Ok(None) if Instant::now() < deadline => std::thread::sleep(POLL),
+            _ => return None,
+        }
+    }
+}
+
+/// Post WM_CLOSE to the runner's hidden window, as the runner does for siblings.
+#[cfg(windows)]
+fn request_close(pid: u32) -> bool {
+    use family::siblings::ProcessTable;
+    family::win32::Win32ProcessTable.request_close(pid)
+}
+
+#[cfg(not(windows))]
+fn request_close(_pid: u32) -> bool {
+    false
+}
@@ -0,0 +1,12 @@
+//! Control daemon: owns profile switching for one dist folder and takes
+//! commands over a local pipe (see [`protocol`]).
+
+pub mod controller;
+pub mod homeassistant;
+pub mod http;
+pub mod launcher;
+pub mod mqtt;
+pub mod protocol;
+pub mod scan;
+pub mod server;
+pub mod transport;
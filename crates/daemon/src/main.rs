@@ -0,0 +1,87 @@
+use anyhow::{bail, Context, Result};
+use clap::Parser;
+use daemon::controller::Controller;
+use daemon::homeassistant;
+use daemon::http;
+use daemon::launcher::ProcessLauncher;
+use daemon::transport::{self, Listener};
+use family::Manifest;
+use std::fs;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug, Parser)]
+#[command(name = "awcc-ctrld", about = "Switch profiles of a dist folder on request over a local pipe")]
+struct Opts {
+    #[arg(long, default_value = "dist", help = "Folder holding manifest.json and the profile EXEs")]
+    dist: PathBuf,
+    #[arg(long, value_name = "PORT", help = "Also serve the HTTP API and dashboard on PORT")]
+    http: Option<u16>,
+    #[arg(long, requires = "http", value_name = "IP", default_value = "127.0.0.1", help = "Address for --http; other than loopback requires a token")]
+    http_bind: IpAddr,
+    #[arg(long, requires = "http", conflicts_with = "token_file", help = "Require `Authorization: Bearer TOKEN` on HTTP requests")]
+    token: Option<String>,
+    #[arg(long, requires = "http", value_name = "PATH", help = "Like --token, read from a file (not visible in the process list)")]
+    token_file: Option<PathBuf>,
+    #[arg(long, value_name = "HOST:PORT", help = "Publish the active profile to this MQTT broker (Home Assistant discovery)")]
+    mqtt: Option<String>,
+    #[arg(long, requires = "mqtt", value_name = "NAME", help = "MQTT user name")]
+    mqtt_user: Option<String>,
+    #[arg(long, requires = "mqtt_user", value_name = "PATH", help = "File holding the MQTT password")]
+    mqtt_password_file: Option<PathBuf>,
+    #[arg(long, requires = "mqtt", value_name = "PREFIX", default_value = "awcc", help = "First level of the state and command topics")]
+    mqtt_prefix: String,
+    #[arg(long, requires = "mqtt", value_name = "PREFIX", default_value = "homeassistant", help = "Home Assistant discovery prefix")]
+    mqtt_discovery_prefix: String,
+}
+
+fn main() -> Result<()> {
+    let opts = Opts::parse();
+    let dir = opts.dist.canonicalize().with_context(|| format!("dist folder not found: {}", opts.dist.display()))?;
+    let manifest = Manifest::load(&dir)
+        .with_context(|| format!("cannot read manifest in {}", dir.display()))?
+        .with_context(|| format!("no manifest.json in {}; run the generator first", dir.display()))?;
+    let token = match &opts.token_file {
+        Some(path) => Some(
+            fs::read_to_string(path).with_context(|| format!("cannot read {}", path.display()))?.trim().to_string(),
+        ),
+        None => opts.token.clone(),
+    };
+    if opts.http.is_some() && !opts.http_bind.is_loopback() && token.is_none() {
+        bail!("--http-bind {} exposes the API to the network; set --token or --token-file", opts.http_bind);
+    }
+    let mqtt = match &opts.mqtt {
+        Some(broker) => {
+            let mut cfg = homeassistant::Config::new(broker, &dir);
+            cfg.username = opts.mqtt_user.clone();
+            cfg.password = match &opts.mqtt_password_file {
+                Some(path) => Some(
+                    fs::read_to_string(path).with_context(|| format!("cannot read {}", path.display()))?.trim().to_string(),
+                ),
+                None => None,
+            };
+            cfg.topic_prefix = opts.mqtt_prefix.clone();
+            cfg.discovery_prefix = opts.mqtt_discovery_prefix.clone();
+            Some(cfg)
+        }
+        None => None,
+    };
+    let listener = Listener::bind(&dir)?;
+    println!("awcc-ctrld: listening on {}", transport::endpoint(&dir));
+    let ctl = Arc::new(Mutex::new(Controller::new(&dir, manifest, ProcessLauncher)));
+    if let Some(port) = opts.http {
+        let http = http::bind(opts.http_bind, port)
+            .with_context(|| format!("cannot listen on {}:{}", opts.http_bind, port))?;
+        println!("awcc-ctrld: dashboard and HTTP API on http://{}/", http.local_addr()?);
+        let ctl = ctl.clone();
+        std::thread::spawn(move || http::listen(http, ctl, token));
+    }
+    if let Some(cfg) = mqtt {
+        println!("awcc-ctrld: MQTT state on {} via {}", cfg.state_topic(), cfg.broker);
+        let ctl = ctl.clone();
+        std::thread::spawn(move || homeassistant::run(&cfg, ctl));
+    }
+    daemon::server::listen(listener, ctl)?;
+    Ok(())
+}
@@ -0,0 +1,551 @@
+//! Minimal MQTT 3.1.1 client: the packets a QoS 0 publisher/subscriber needs.
+//!
+//! Reads go through an internal buffer so [`Connection::poll`] can wait with a
+//! timeout without losing half-received packets.
+
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::{Duration, Instant};
+
+/// Largest packet accepted from the broker.
+const MAX_PACKET: usize = 256 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    pub topic: String,
+    pub payload: Vec<u8>,
+    pub retain: bool,
+}
+
+impl Message {
+    pub fn new(topic: &str, payload: impl Into<Vec<u8>>, retain: bool) -> Self {
+        Message { topic: topic.to_string(), payload: payload.into(), retain }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Packet {
+    Connect {
+        client_id: String,
+        keep_alive: u16,
+        username: Option<String>,
+        password: Option<String>,
+        /// Published by the broker if we vanish without `Disconnect`.
+        will: Option<Message>,
+    },
+    ConnAck {
+        code: u8,
+    },
+    Publish(Message),
+    Subscribe {
+        packet_id: u16,
+        topics: Vec<String>,
+    },
+    SubAck {
+        packet_id: u16,
+        codes: Vec<u8>,
+    },
+    PingReq,
+    PingResp,
+    Disconnect,
+}
+
+impl Packet {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut body = Vec::new();
+        let first = match self {
+            Packet::Connect { client_id, keep_alive, username, password, will } => {
+                put_str(&mut body, "MQTT");
+                body.push(4);
+                let mut flags = 0x02; // clean session
+                if let Some(w) = will {
+                    flags |= 0x04 | if w.retain { 0x20 } else { 0 };
+                }
+                if username.is_some() {
+                    flags |= 0x80;
+                }
+                if password.is_some() {
+                    flags |= 0x40;
+                }
+                body.push(flags);
+                body.extend_from_slice(&keep_alive.to_be_bytes());
+                put_str(&mut body, client_id);
+                if let Some(w) = will {
+                    put_str(&mut body, &w.topic);
+                    put_bytes(&mut body, &w.payload);
+                }
+                for s in [username, password].into_iter().flatten() {
+                    put_str(&mut body, s);
+                }
+                0x10
+            }
+            Packet::ConnAck { code } => {
+                body.extend_from_slice(&[0, *code]);
+                0x20
+            }
+            Packet::Publish(m) => {
+                put_str(&mut body, &m.topic);
+                body.extend_from_slice(&m.payload);
+                0x30 | m.retain as u8
+            }
+            Packet::Subscribe { packet_id, topics } => {
+                body.extend_from_slice(&packet_id.to_be_bytes());
+                for t in topics {
+                    put_str(&mut body, t);
+                    body.push(0);
+                }
+                0x82
+            }
+            Packet::SubAck { packet_id, codes } => {
+                body.extend_from_slice(&packet_id.to_be_bytes());
+                body.extend_from_slice(codes);
+                0x90
+            }
+            Packet::PingReq => 0xc0,
+            Packet::PingResp => 0xd0,
+            Packet::Disconnect => 0xe0,
+        };
+        let mut out = vec![first];
+        let mut len = body.len();
+        loop {
+            let mut byte = (len % 128) as u8;
+            len /= 128;
+            if len > 0 {
+                byte |= 0x80;
+            }
+            out.push(byte);
+            if len == 0 {
+                break;
+            }
+        }
+        out.extend_from_slice(&body);
+        out
+    }
+
+    /// Decode the first packet in `buf`: the packet and its length, or `None` if
+    /// more bytes are needed.
+    pub fn decode(buf: &[u8]) -> io::Result<Option<(Packet, usize)>> {
+        let Some(&first) = buf.first() else { return Ok(None) };
+        let mut len = 0usize;
+        let mut i = 1;
+        loop {
+            let Some(&b) = buf.get(i) else { return Ok(None) };
+            len |= ((b & 0x7f) as usize) << (7 * (i - 1));
+            i += 1;
+            if b & 0x80 == 0 {
+                break;
+            }
+            if i > 4 {
+                return Err(invalid("malformed remaining length"));
+            }
+        }
+        if len > MAX_PACKET {
+            return Err(invalid("packet too large"));
+        }
+        let Some(body) = buf.get(i..i + len) else { return Ok(None) };
+        let mut r = Reader(body);
+        let packet = match first >> 4 {
+            1 => {
+                if r.str()? != "MQTT" || r.u8()? != 4 {
+                    return Err(invalid("unsupported protocol"));
+                }
+                let flags = r.u8()?;
+                let keep_alive = r.u16()?;
+                let client_id = r.str()?;
+                let will = if flags & 0x04 != 0 {
+                    let topic = r.str()?;
+                    Some(Message { topic, payload: r.bytes()?, retain: flags & 0x20 != 0 })
+                } else {
+                    None
+                };
+                let username = if flags & 0x80 != 0 { Some(r.str()?) } else { None };
+                let password = if flags & 0x40 != 0 { Some(r.str()?) } else { None };
+                Packet::Connect { client_id, keep_alive, username, password, will }
+            }
+            2 => {
+                r.u8()?;
+                Packet::ConnAck { code: r.u8()? }
+            }
+            3 => {
+                let topic = r.str()?;
+                if first & 0x06 != 0 {
+                    r.u16()?; // packet id of QoS 1/2, which we never subscribe with
+                }
+                Packet::Publish(Message { topic, payload: r.0.to_vec(), retain: first & 0x01 != 0 })
+            }
+            8 => {
+                let packet_id = r.u16()?;
+                let mut topics = Vec::new();
+                while !r.0.is_empty() {
+                    topics.push(r.str()?);
+                    r.u8()?;
+                }
+                Packet::Subscribe { packet_id, topics }
+            }
+            9 => Packet::SubAck { packet_id: r.u16()?, codes: r.0.to_vec() },
+            12 => Packet::PingReq,
+            13 => Packet::PingResp,
+            14 => Packet::Disconnect,
+            t => return Err(invalid(&format!("unexpected packet type {}", t))),
+        };
+        Ok(Some((packet, i + len)))
+    }
+}
+
+fn put_bytes(out: &mut Vec<u8>, b: &[u8]) {
+    out.extend_from_slice(&(b.len() as u16).to_be_bytes());
+    out.extend_from_slice(b);
+}
+
+fn put_str(out: &mut Vec<u8>, s: &str) {
+    put_bytes(out, s.as_bytes());
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("mqtt: {}", msg))
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+    fn take(&mut self, n: usize) -> io::Result<&[u8]> {
+        if self.0.len() < n {
+            return Err(invalid("truncated packet"));
+        }
+        let (head, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Ok(head)
+    }
+
+    fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> io::Result<u16> {
+        let b = self.take(2)?;
+        Ok(u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    fn bytes(&mut self) -> io::Result<Vec<u8>> {
+        let n = self.u16()? as usize;
+        Ok(self.take(n)?.to_vec())
+    }
+
+    fn str(&mut self) -> io::Result<String> {
+        String::from_utf8(self.bytes()?).map_err(|_| invalid("string is not UTF-8"))
+    }
+}
+
+/// How to log in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectOptions {
+    pub client_id: String,
+    pub keep_alive: Duration,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub will: Option<Message>,
+}
+
+/// A logged-in session with a broker.
+pub struct Connection {
+    stream: TcpStream,
+    buf: Vec<u8>,
+    next_id: u16,
+    keep_alive: Duration,
+    last_sent: Instant,
+    /// When the unanswered PINGREQ went out.
+    ping_sent: Option<Instant>,
+}
+
+impl Connection {
+    pub fn connect(addr: &str, opts: &ConnectOptions, timeout: Duration) -> io::Result<Connection> {
+        let sock = addr
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("cannot resolve {}", addr)))?;
+        let stream = TcpStream::connect_timeout(&sock, timeout)?;
+        stream.set_nodelay(true)?;
+        let mut conn = Connection {
+            stream,
+            buf: Vec::new(),
+            next_id: 0,
+            keep_alive: opts.keep_alive,
+            last_sent: Instant::now(),
+            ping_sent: None,
+        };
+        conn.send(&Packet::Connect {
+            client_id: opts.client_id.clone(),
+            keep_alive: opts.keep_alive.as_secs().min(u16::MAX as u64) as u16,
+            username: opts.username.clone(),
+            password: opts.password.clone(),
+            will: opts.will.clone(),
+        })?;
+        let deadline = Instant::now() + timeout;
+        while Instant::now() < deadline {
+            match conn.poll(deadline - Instant::now())? {
+                Some(Packet::ConnAck { code: 0 }) => return Ok(conn),
+                Some(Packet::ConnAck { code }) => {
+                    return Err(io::Error::new(io::ErrorKind::ConnectionRefused, format!("broker refused login (code {})", code)))
+                }
+                _ => {}
+            }
+        }
+        Err(io::Error::new(io::ErrorKind::TimedOut, "no CONNACK from broker"))
+    }
+
+    pub fn send(&mut self, packet: &Packet) -> io::Result<()> {
+        self.stream.write_all(&packet.encode())?;
+        self.last_sent = Instant::now();
+        Ok(())
+    }
+
+    pub fn publish(&mut self, topic: &str, payload: impl Into<Vec<u8>>, retain: bool) -> io::Result<()> {
+        self.send(&Packet::Publish(Message::new(topic, payload, retain)))
+    }
+
+    /// Subscribe at QoS 0; the SUBACK arrives through [`Connection::poll`].
+    pub fn subscribe(&mut self, topics: &[&str]) -> io::Result<()> {
+        self.next_id = self.next_id.wrapping_add(1).max(1);
+        let topics = topics.iter().map(|t| t.to_string()).collect();
+        self.send(&Packet::Subscribe { packet_id: self.next_id, topics })
+    }
+
+    /// Next packet from the broker, waiting up to `wait`. Keeps the session alive
+    /// and fails once the broker stops answering pings.
+    pub fn poll(&mut self, wait: Duration) -> io::Result<Option<Packet>> {
+        if let Some(sent) = self.ping_sent {
+            if sent.elapsed() > self.keep_alive {
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "broker stopped answering"));
+            }
+        } else if !self.keep_alive.is_zero() && self.last_sent.elapsed() >= self.keep_alive / 2 {
+            self.send(&Packet::PingReq)?;
+            self.ping_sent = Some(Instant::now());
+        }
+
+        if let Some(p) = self.take_buffered()? {
+            return Ok(Some(p));
+        }
+        self.stream.set_read_timeout(Some(wait.max(Duration::from_millis(1))))?;
+        let mut chunk = [0u8; 4096];
+        match self.stream.read(&mut chunk) {
+            Ok(0) => Err(io::Error::new(io::ErrorKind::UnexpectedEof, "broker closed the connection")),
+            Ok(n) => {
+                self.buf.extend_from_slice(&chunk[..n]);
+                self.take_buffered()
+            }
+            Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn take_buffered(&mut self) -> io::Result<Option<Packet>> {
+        let Some((packet, used)) = Packet::decode(&self.buf)? else { return Ok(None) };
+        self.buf.drain(..used);
+        if packet == Packet::PingResp {
+            self.ping_sent = None;
+        }
+        Ok(Some(packet))
+    }
+
+    /// Say goodbye so the broker does not publish our will.
+    pub fn disconnect(mut self) {
+        let _ = self.send(&Packet::Disconnect);
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod fake {
+    //! In-process broker stand-in: exact-topic subscriptions, retained messages,
+    //! wills, and a switch to drop every client.
+
+    use super::*;
+    use std::collections::HashMap;
+    use std::net::{SocketAddr, TcpListener};
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Default)]
+    struct State {
+        retained: HashMap<String, Message>,
+        next_client: usize,
+        /// Per client: its stream and subscribed topics.
+        clients: HashMap<usize, (TcpStream, Vec<String>)>,
+        /// Every message published by a client, in order.
+        log: Vec<Message>,
+    }
+
+    #[derive(Clone)]
+    pub struct Broker {
+        pub addr: SocketAddr,
+        state: Arc<Mutex<State>>,
+    }
+
+    impl Broker {
+        pub fn start() -> Broker {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let broker = Broker { addr: listener.local_addr().unwrap(), state: Arc::default() };
+            let b = broker.clone();
+            std::thread::spawn(move || {
+                for stream in listener.incoming().flatten() {
+                    let b = b.clone();
+                    std::thread::spawn(move || b.serve(stream));
+                }
+            });
+            broker
+        }
+
+        /// Messages clients published, oldest first.
+        pub fn published(&self) -> Vec<Message> {
+            self.state.lock().unwrap().log.clone()
+        }
+
+        pub fn retained(&self, topic: &str) -> Option<Message> {
+            self.state.lock().unwrap().retained.get(topic).cloned()
+        }
+
+        /// Cut every client off, as a broker restart would.
+        pub fn drop_clients(&self) {
+            for (_, (s, _)) in self.state.lock().unwrap().clients.drain() {
+                let _ = s.shutdown(std::net::Shutdown::Both);
+            }
+        }
+
+        fn serve(&self, mut stream: TcpStream) {
+            let mut buf = Vec::new();
+            let mut will = None;
+            let mut chunk = [0u8; 4096];
+            let id = {
+                let mut st = self.state.lock().unwrap();
+                st.next_client += 1;
+                let id = st.next_client;
+                st.clients.insert(id, (stream.try_clone().unwrap(), Vec::new()));
+                id
+            };
+            'conn: loop {
+                let n = match stream.read(&mut chunk) {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => n,
+                };
+                buf.extend_from_slice(&chunk[..n]);
+                while let Ok(Some((packet, used))) = Packet::decode(&buf) {
+                    buf.drain(..used);
+                    match packet {
+                        Packet::Connect { will: w, .. } => {
+                            will = w;
+                            let _ = stream.write_all(&Packet::ConnAck { code: 0 }.encode());
+                        }
+                        Packet::Subscribe { packet_id, topics } => {
+                            let mut st = self.state.lock().unwrap();
+                            let retained: Vec<Message> =
+                                topics.iter().filter_map(|t| st.retained.get(t).cloned()).collect();
+                            let codes = vec![0; topics.len()];
+                            if let Some(c) = st.clients.get_mut(&id) {
+                                c.1.extend(topics);
+                            }
+                            let _ = stream.write_all(&Packet::SubAck { packet_id, codes }.encode());
+                            for m in retained {
+                                let _ = stream.write_all(&Packet::Publish(m).encode());
+                            }
+                        }
+                        Packet::Publish(m) => self.route(m),
+                        Packet::PingReq => {
+                            let _ = stream.write_all(&Packet::PingResp.encode());
+                        }
+                        Packet::Disconnect => {
+                            will = None;
+                            break 'conn;
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            self.state.lock().unwrap().clients.remove(&id);
+            if let Some(w) = will {
+                self.route(w);
+            }
+        }
+
+        fn route(&self, m: Message) {
+            let mut st = self.state.lock().unwrap();
+            st.log.push(m.clone());
+            if m.retain {
+                st.retained.insert(m.topic.clone(), m.clone());
+            }
+            let delivered = Message { retain: false, ..m };
+            for (s, topics) in st.clients.values() {
+                if topics.contains(&delivered.topic) {
+                    let _ = (&*s).write_all(&Packet::Publish(delivered.clone()).encode());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::fake::Broker;
+    use super::*;
+
+    #[test]
+    fn packets_round_trip() {
+        let packets = [
+            Packet::Connect {
+                client_id: "awcc".into(),
+                keep_alive: 30,
+                username: Some("ha".into()),
+                password: Some("pw".into()),
+                will: Some(Message::new("awcc/x/availability", "offline", true)),
+            },
+            Packet::Connect { client_id: "c".into(), keep_alive: 0, username: None, password: None, will: None },
+            Packet::ConnAck { code: 5 },
+            Packet::Publish(Message::new("a/b", vec![b'x'; 321], true)),
+            Packet::Subscribe { packet_id: 7, topics: vec!["a/b".into(), "c".into()] },
+            Packet::SubAck { packet_id: 7, codes: vec![0, 0x80] },
+            Packet::PingReq,
+            Packet::PingResp,
+            Packet::Disconnect,
+        ];
+        for p in packets {
+            let bytes = p.encode();
+            assert_eq!(Packet::decode(&bytes).unwrap(), Some((p.clone(), bytes.len())), "{:?}", p);
+            // Partial input asks for more instead of failing
+            assert_eq!(Packet::decode(&bytes[..bytes.len() - 1]).unwrap(), None);
+        }
+        // 321-byte payload needs a two-byte remaining length
+        let big = Packet::Publish(Message::new("a/b", vec![0; 321], false)).encode();
+        assert_eq!(&big[..3], &[0x30, 0xc6, 0x02]);
+        assert!(Packet::decode(&[0x30, 0xff, 0xff, 0xff, 0xff, 0x01]).is_err());
+    }
+
+    #[test]
+    fn publish_and_subscribe_through_broker() {
+        let broker = Broker::start();
+        let opts = |id: &str| ConnectOptions {
+            client_id: id.into(),
+            keep_alive: Duration::from_secs(30),
+            username: None,
+            password: None,
+            will: Some(Message::new("t/will", "gone", true)),
+        };
+        let addr = broker.addr.to_string();
+        let mut a = Connection::connect(&addr, &opts("a"), Duration::from_secs(5)).unwrap();
+        a.publish("t/retained", "kept", true).unwrap();
+
+        let mut b = Connection::connect(&addr, &opts("b"), Duration::from_secs(5)).unwrap();
+        b.subscribe(&["t/retained", "t/live", "t/will"]).unwrap();
+        let next = |b: &mut Connection| loop {
+            match b.poll(Duration::from_secs(5)).unwrap() {
+                Some(Packet::Publish(m)) => return m,
+                Some(_) => {}
+                None => panic!("timed out"),
+            }
+        };
+        assert_eq!(next(&mut b).payload, b"kept");
+        a.publish("t/live", "hello", false).unwrap();
+        assert_eq!(next(&mut b).payload, b"hello");
+
+        // Vanishing without DISCONNECT publishes the will
+        drop(a);
+        assert_eq!(next(&mut b), Message::new("t/will", "gone", false));
+        b.disconnect();
+    }
+}
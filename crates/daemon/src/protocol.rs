@@ -0,0 +1,195 @@
+//! Wire protocol of the control daemon.
+//!
+//! Line-delimited JSON over a local stream (named pipe on Windows, Unix socket
+//! elsewhere). Each request is one JSON object on one line; the daemon answers
+//! each with exactly one JSON line, in order. A connection may carry any number
+//! of requests.
+//!
+//! Requests carry the protocol version `v` and a `cmd`:
+//!
+//! ```text
+//! {"v":1,"cmd":"switch","name":"red"}
+//! {"v":1,"cmd":"off"}
+//! {"v":1,"cmd":"status"}
+//! {"v":1,"cmd":"list"}
+//! ```
+//!
+//! Replies always have `v`, `ok` and `current` (the active profile or `null`);
+//! `list` adds `profiles`, `status` adds `pid`, failures add `error`:
+//!
+//! ```text
+//! {"v":1,"ok":true,"current":"red","pid":4242}
+//! {"v":1,"ok":true,"current":"red","profiles":[{"name":"red","label":"Meeting red","color":"#ff0000"}]}
+//! {"v":1,"ok":false,"current":null,"error":{"code":"unknown_profile","message":"no profile named \"bleu\""}}
+//! ```
+//!
+//! A request with a newer `v` than [`PROTOCOL_VERSION`] is answered with
+//! `unsupported_version`; fields added within a version are optional, so older
+//! clients keep working.
+
+use family::Entry;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+pub const PROTOCOL_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Request {
+    pub v: u32,
+    #[serde(flatten)]
+    pub cmd: Command,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "cmd", rename_all = "snake_case")]
+pub enum Command {
+    /// Make `name` the active profile.
+    Switch { name: String },
+    /// Stop the active profile (System Default).
+    Off,
+    Status,
+    List,
+}
+
+impl Request {
+    pub fn new(cmd: Command) -> Self {
+        Request { v: PROTOCOL_VERSION, cmd }
+    }
+
+    pub fn to_line(&self) -> String {
+        let mut line = serde_json::to_string(self).expect("request serializes");
+        line.push('\n');
+        line
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Reply {
+    pub v: u32,
+    pub ok: bool,
+    pub current: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pid: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profiles: Option<Vec<ProfileInfo>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<ErrorInfo>,
+}
+
+impl Reply {
+    pub fn ok(current: Option<String>) -> Self {
+        Reply { v: PROTOCOL_VERSION, ok: true, current, pid: None, profiles: None, error: None }
+    }
+
+    pub fn error(current: Option<String>, code: ErrorCode, message: impl Into<String>) -> Self {
+        Reply { ok: false, error: Some(ErrorInfo { code, message: message.into() }), ..Reply::ok(current) }
+    }
+
+    pub fn to_line(&self) -> String {
+        let mut line = serde_json::to_string(self).expect("reply serializes");
+        line.push('\n');
+        line
+    }
+}
+
+/// One profile as listed to clients.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProfileInfo {
+    pub name: String,
+    /// `display_name`, or the name.
+    pub label: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+}
+
+impl From<&Entry> for ProfileInfo {
+    fn from(e: &Entry) -> Self {
+        ProfileInfo { name: e.name.clone(), label: e.label().to_string(), color: e.color.clone(), group: e.group.clone() }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ErrorInfo {
+    pub code: ErrorCode,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// The line is not a valid request.
+    BadRequest,
+    UnsupportedVersion,
+    UnknownProfile,
+    /// Starting or stopping an EXE failed.
+    Spawn,
+    /// A profile EXE survived `off`.
+    StillRunning,
+    /// A profile EXE runs under an account we may not stop.
+    AccessDenied,
+}
+
+impl fmt::Display for ErrorInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Parse one request line. Errors are returned as the reply to send back.
+pub fn parse_request(line: &str) -> Result<Request, Reply> {
+    #[derive(Deserialize)]
+    struct Probe {
+        v: Option<u32>,
+    }
+    let probe: Probe =
+        serde_json::from_str(line).map_err(|e| Reply::error(None, ErrorCode::BadRequest, e.to_string()))?;
+    match probe.v {
+        None => return Err(Reply::error(None, ErrorCode::BadRequest, "missing protocol version `v`")),
+        Some(v) if v == 0 || v > PROTOCOL_VERSION => {
+            return Err(Reply::error(
+                None,
+                ErrorCode::UnsupportedVersion,
+                format!("protocol version {} is not supported (daemon speaks {})", v, PROTOCOL_VERSION),
+            ))
+        }
+        Some(_) => {}
+    }
+    serde_json::from_str(line).map_err(|e| Reply::error(None, ErrorCode::BadRequest, e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requests_have_the_documented_shape() {
+        assert_eq!(Request::new(Command::Switch { name: "red".into() }).to_line(), "{\"v\":1,\"cmd\":\"switch\",\"name\":\"red\"}\n");
+        assert_eq!(Request::new(Command::Off).to_line(), "{\"v\":1,\"cmd\":\"off\"}\n");
+        assert_eq!(parse_request(r#"{"cmd":"list","v":1}"#), Ok(Request::new(Command::List)));
+        assert_eq!(parse_request(r#"{"v":1,"cmd":"status"}"#), Ok(Request::new(Command::Status)));
+    }
+
+    #[test]
+    fn replies_omit_unused_fields() {
+        assert_eq!(Reply::ok(None).to_line(), "{\"v\":1,\"ok\":true,\"current\":null}\n");
+        let err = Reply::error(Some("red".into()), ErrorCode::UnknownProfile, "no profile named \"bleu\"");
+        assert_eq!(
+            err.to_line(),
+            "{\"v\":1,\"ok\":false,\"current\":\"red\",\"error\":{\"code\":\"unknown_profile\",\"message\":\"no profile named \\\"bleu\\\"\"}}\n"
+        );
+        let parsed: Reply = serde_json::from_str(err.to_line().trim()).unwrap();
+        assert_eq!(parsed, err);
+    }
+
+    #[test]
+    fn bad_lines_get_error_replies() {
+        let code = |line: &str| parse_request(line).unwrap_err().error.unwrap().code;
+        assert_eq!(code("switch red"), ErrorCode::BadRequest);
+        assert_eq!(code(r#"{"cmd":"off"}"#), ErrorCode::BadRequest);
+        assert_eq!(code(r#"{"v":2,"cmd":"off"}"#), ErrorCode::UnsupportedVersion);
+        assert_eq!(code(r#"{"v":1,"cmd":"reboot"}"#), ErrorCode::BadRequest);
+        assert_eq!(code(r#"{"v":1,"cmd":"switch"}"#), ErrorCode::BadRequest);
+    }
+}
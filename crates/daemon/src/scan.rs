@@ -0,0 +1,70 @@
+//! What is running, looked up with the runner's own sibling rules
+//! ([`find_siblings`](family::siblings::find_siblings)).
+//!
+//! The daemon only knows the process it started itself; a switch from the tray,
+//! a Stream Deck Open action or a double-clicked EXE stops that one and starts
+//! another, so the active profile is looked up here as well. `awcc-ctl` uses the
+//! same lookup when no daemon is running.
+
+use family::siblings::Sibling;
+use family::Manifest;
+use std::io;
+use std::path::Path;
+
+/// Image name the sibling rules see for the process looking; never part of a family.
+#[cfg_attr(not(windows), allow(dead_code))]
+const OBSERVER_EXE: &str = "awcc-observer.exe";
+
+/// The active profile and its PID: the newest running one, as the last switch request wins.
+pub fn current_of(found: &[Sibling], manifest: &Manifest) -> Option<(String, u32)> {
+    found.iter().rev().find_map(|s| manifest.entry_by_exe(&s.image_name).map(|e| (e.name.clone(), s.pid)))
+}
+
+/// Running profile EXEs of the family in `dir`, oldest first.
+#[cfg(windows)]
+pub fn running(dir: &Path, manifest: &Manifest) -> io::Result<Vec<Sibling>> {
+    use family::siblings::{find_siblings, SiblingRules};
+    use family::win32::{self, Win32ProcessTable};
+    let pid = std::process::id();
+    let rules = SiblingRules::for_dir(manifest, OBSERVER_EXE, pid, dir).with_session(win32::session_of(pid));
+    Ok(find_siblings(&mut Win32ProcessTable, &rules))
+}
+
+#[cfg(not(windows))]
+pub fn running(_dir: &Path, _manifest: &Manifest) -> io::Result<Vec<Sibling>> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "looking up running profiles needs Windows"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use family::manifest::MANIFEST_VERSION;
+    use family::{Entry, Role};
+
+    fn manifest() -> Manifest {
+        Manifest {
+            version: MANIFEST_VERSION,
+            generator_version: None,
+            profiles: vec![
+                Entry::new("red", "red.exe", Role::Profile),
+                Entry::new("navy", "navy.exe", Role::Profile),
+                Entry::new("off", "off.exe", Role::Off),
+            ],
+            awcc: None,
+            runner: Default::default(),
+        }
+    }
+
+    fn sibling(pid: u32, image_name: &str, created: u64) -> Sibling {
+        Sibling { pid, image_name: image_name.to_string(), created: Some(created) }
+    }
+
+    #[test]
+    fn newest_running_profile_is_current() {
+        let m = manifest();
+        assert_eq!(current_of(&[], &m), None);
+        assert_eq!(current_of(&[sibling(10, "RED.EXE", 1)], &m), Some(("red".to_string(), 10)));
+        let found = [sibling(10, "red.exe", 1), sibling(11, "navy.exe", 2), sibling(12, "stale.exe", 3)];
+        assert_eq!(current_of(&found, &m), Some(("navy".to_string(), 11)));
+    }
+}
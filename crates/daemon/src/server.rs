@@ -0,0 +1,96 @@
+//! Serving one client connection.
+
+use crate::controller::{Controller, Launcher};
+use crate::protocol::{parse_request, ErrorCode, Reply};
+use crate::transport::Listener;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// Longest request line accepted; the connection is dropped after a longer one.
+pub const MAX_LINE: usize = 64 * 1024;
+
+/// Answer each request line from `reader` on `writer` until the client hangs up.
+pub fn serve_connection<L: Launcher>(reader: impl Read, mut writer: impl Write, ctl: &Mutex<Controller<L>>) -> io::Result<()> {
+    let mut reader = BufReader::new(reader);
+    let mut buf = Vec::new();
+    loop {
+        buf.clear();
+        let n = (&mut reader).take(MAX_LINE as u64 + 1).read_until(b'\n', &mut buf)?;
+        if n == 0 {
+            return Ok(());
+        }
+        if n > MAX_LINE && buf.last() != Some(&b'\n') {
+            let reply = Reply::error(None, ErrorCode::BadRequest, format!("request longer than {} bytes", MAX_LINE));
+            return writer.write_all(reply.to_line().as_bytes());
+        }
+        let line = String::from_utf8_lossy(&buf);
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let reply = match parse_request(line) {
+            Ok(req) => lock(ctl).handle(&req.cmd),
+            Err(mut reply) => {
+                reply.current = lock(ctl).current();
+                reply
+            }
+        };
+        writer.write_all(reply.to_line().as_bytes())?;
+        writer.flush()?;
+    }
+}
+
+/// Accept clients forever, one thread each. Commands are applied one at a time.
+pub fn listen<L>(mut listener: Listener, ctl: Arc<Mutex<Controller<L>>>) -> io::Result<()>
+where
+    L: Launcher + Send + 'static,
+    L::Child: Send,
+{
+    loop {
+        let conn = listener.accept()?;
+        let reader = conn.try_clone()?;
+        let ctl = ctl.clone();
+        std::thread::spawn(move || {
+            let _ = serve_connection(reader, conn, &ctl);
+        });
+    }
+}
+
+/// A panic in one connection must not take the daemon down with it.
+fn lock<L: Launcher>(ctl: &Mutex<Controller<L>>) -> MutexGuard<'_, Controller<L>> {
+    ctl.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::controller::fake::{manifest, FakeLauncher};
+    use std::path::Path;
+
+    fn serve(input: &[u8]) -> Vec<Reply> {
+        let ctl = Mutex::new(Controller::new(Path::new("/dist"), manifest(), FakeLauncher::default()));
+        let mut out = Vec::new();
+        serve_connection(input, &mut out, &ctl).unwrap();
+        String::from_utf8(out).unwrap().lines().map(|l| serde_json::from_str(l).unwrap()).collect()
+    }
+
+    #[test]
+    fn one_reply_per_request_line() {
+        let replies = serve(b"{\"v\":1,\"cmd\":\"switch\",\"name\":\"red\"}\n\n{\"v\":1,\"cmd\":\"bogus\"}\r\n{\"v\":1,\"cmd\":\"status\"}");
+        assert_eq!(replies.len(), 3);
+        assert_eq!(replies[0], Reply::ok(Some("red".into())));
+        assert_eq!(replies[1].error.as_ref().unwrap().code, ErrorCode::BadRequest);
+        // Errors still report the active profile
+        assert_eq!(replies[1].current.as_deref(), Some("red"));
+        assert_eq!(replies[2].pid, Some(1001));
+    }
+
+    #[test]
+    fn overlong_line_ends_the_connection() {
+        let mut input = vec![b'x'; MAX_LINE + 10];
+        input.extend_from_slice(b"\n{\"v\":1,\"cmd\":\"off\"}\n");
+        let replies = serve(&input);
+        assert_eq!(replies.len(), 1);
+        assert_eq!(replies[0].error.as_ref().unwrap().code, ErrorCode::BadRequest);
+    }
+}
@@ -0,0 +1,232 @@
+//! Local endpoint the daemon listens on, one per dist folder.
+//!
+//! Windows uses the named pipe `\\.\pipe\<family key>.ctl`, so every copy of a
+//! family gets its own daemon, like the runner's named mutexes. Elsewhere a Unix
+//! socket [`SOCKET_FILE`] in the dist folder plays the same role.
+
+use crate::protocol::{Command, Reply, Request};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+
+#[cfg(unix)]
+pub const SOCKET_FILE: &str = "awcc-ctrld.sock";
+
+#[cfg(unix)]
+pub type Connection = std::os::unix::net::UnixStream;
+#[cfg(windows)]
+pub type Connection = std::fs::File;
+
+/// Human-readable address of the endpoint for `dir`.
+pub fn endpoint(dir: &Path) -> String {
+    #[cfg(unix)]
+    return dir.join(SOCKET_FILE).display().to_string();
+    #[cfg(windows)]
+    return format!(r"\\.\pipe\{}.ctl", family::instance::family_key(&dir.to_string_lossy()));
+}
+
+fn already_running(dir: &Path) -> io::Error {
+    io::Error::new(io::ErrorKind::AddrInUse, format!("a daemon is already listening on {}", endpoint(dir)))
+}
+
+/// Send one command over `conn` and wait for its reply.
+pub fn request(mut conn: &Connection, cmd: &Command) -> io::Result<Reply> {
+    conn.write_all(Request::new(cmd.clone()).to_line().as_bytes())?;
+    let mut line = String::new();
+    BufReader::new(conn).read_line(&mut line)?;
+    if line.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "daemon closed the connection"));
+    }
+    serde_json::from_str(&line).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+#[cfg(unix)]
+pub struct Listener(std::os::unix::net::UnixListener);
+
+#[cfg(unix)]
+impl Listener {
+    /// Listen for `dir`; fails with `AddrInUse` if a daemon already does.
+    pub fn bind(dir: &Path) -> io::Result<Listener> {
+        use std::os::unix::net::UnixListener;
+        let path = dir.join(SOCKET_FILE);
+        match UnixListener::bind(&path) {
+            Err(e) if e.kind() == io::ErrorKind::AddrInUse => {
+                if connect(dir).is_ok() {
+                    return Err(already_running(dir));
+                }
+                // Left behind by a daemon that did not exit cleanly
+                std::fs::remove_file(&path)?;
+                UnixListener::bind(&path).map(Listener)
+            }
+            other => other.map(Listener),
+        }
+    }
+
+    pub fn accept(&mut self) -> io::Result<Connection> {
+        self.0.accept().map(|(stream, _)| stream)
+    }
+}
+
+#[cfg(unix)]
+pub fn connect(dir: &Path) -> io::Result<Connection> {
+    Connection::connect(dir.join(SOCKET_FILE))
+}
+
+#[cfg(windows)]
+pub struct Listener {
+    name: Vec<u16>,
+    /// Instance waiting for the next client.
+    next: std::fs::File,
+}
+
+#[cfg(windows)]
+impl Listener {
+    /// Listen for `dir`; fails with `AddrInUse` if a daemon already does.
+    pub fn bind(dir: &Path) -> io::Result<Listener> {
+        let name: Vec<u16> = endpoint(dir).encode_utf16().chain(Some(0)).collect();
+        let next = create_instance(&name, true).map_err(|e| {
+            if e.kind() == io::ErrorKind::PermissionDenied {
+                already_running(dir)
+            } else {
+                e
+            }
+        })?;
+        Ok(Listener { name, next })
+    }
+
+    pub fn accept(&mut self) -> io::Result<Connection> {
+        use std::os::windows::io::AsRawHandle;
+        use windows::Win32::Foundation::{ERROR_PIPE_CONNECTED, HANDLE, WIN32_ERROR};
+        use windows::Win32::System::Pipes::ConnectNamedPipe;
+        unsafe {
+            if let Err(e) = ConnectNamedPipe(HANDLE(self.next.as_raw_handle() as isize), None) {
+                // The client connected between creation and this call
+                if WIN32_ERROR::from_error(&e) != Some(ERROR_PIPE_CONNECTED) {
+                    return Err(io::Error::from_raw_os_error(e.code().0 & 0xffff));
+                }
+            }
+        }
+        let fresh = create_instance(&self.name, false)?;
+        Ok(std::mem::replace(&mut self.next, fresh))
+    }
+}
+
+#[cfg(windows)]
+fn create_instance(name: &[u16], first: bool) -> io::Result<std::fs::File> {
+    use std::os::windows::io::FromRawHandle;
+    use windows::core::PCWSTR;
+    use windows::Win32::Storage::FileSystem::{FILE_FLAG_FIRST_PIPE_INSTANCE, PIPE_ACCESS_DUPLEX};
+    use windows::Win32::System::Pipes::{
+        CreateNamedPipeW, PIPE_READMODE_BYTE, PIPE_REJECT_REMOTE_CLIENTS, PIPE_TYPE_BYTE, PIPE_UNLIMITED_INSTANCES,
+        PIPE_WAIT,
+    };
+    let mut open_mode = PIPE_ACCESS_DUPLEX;
+    if first {
+        open_mode = open_mode | FILE_FLAG_FIRST_PIPE_INSTANCE;
+    }
+    let pipe_mode = PIPE_TYPE_BYTE | PIPE_READMODE_BYTE | PIPE_WAIT | PIPE_REJECT_REMOTE_CLIENTS;
+    unsafe {
+        let h = CreateNamedPipeW(PCWSTR(name.as_ptr()), open_mode, pipe_mode, PIPE_UNLIMITED_INSTANCES, 4096, 4096, 0, None);
+        if h.is_invalid() {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(std::fs::File::from_raw_handle(h.0 as _))
+    }
+}
+
+#[cfg(windows)]
+pub fn connect(dir: &Path) -> io::Result<Connection> {
+    const ERROR_PIPE_BUSY: i32 = 231;
+    let name = endpoint(dir);
+    let mut attempts = 0;
+    loop {
+        match std::fs::OpenOptions::new().read(true).write(true).open(&name) {
+            // Every instance is serving a client; the daemon makes a new one right away
+            Err(e) if e.raw_os_error() == Some(ERROR_PIPE_BUSY) && attempts < 40 => {
+                attempts += 1;
+                std::thread::sleep(std::time::Duration::from_millis(50));
+            }
+            other => return other,
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::controller::Controller;
+    use crate::launcher::ProcessLauncher;
+    use crate::protocol::ErrorCode;
+    use family::manifest::MANIFEST_VERSION;
+    use family::{Entry, Manifest, Role};
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::PathBuf;
+    use std::sync::{Arc, Mutex};
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    /// Shell scripts named like the generated EXEs.
+    fn dist_with_scripts() -> PathBuf {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos();
+        let dir = std::env::temp_dir().join(format!("awcc-daemon-test-{}-{}", std::process::id(), nanos));
+        fs::create_dir_all(&dir).unwrap();
+        for (exe, body) in [("red.exe", "exec sleep 30"), ("navy.exe", "exec sleep 30"), ("off.exe", "exit 2")] {
+            let path = dir.join(exe);
+            fs::write(&path, format!("#!/bin/sh\n{}\n", body)).unwrap();
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        dir
+    }
+
+    fn manifest() -> Manifest {
+        Manifest {
+            version: MANIFEST_VERSION,
+            generator_version: None,
+            profiles: vec![
+                Entry::new("red", "red.exe", Role::Profile),
+                Entry::new("navy", "navy.exe", Role::Profile),
+                Entry::new("off", "off.exe", Role::Off),
+            ],
+            awcc: None,
+            runner: Default::default(),
+        }
+    }
+
+    fn ask(conn: &mut Connection, cmd: Command) -> Reply {
+        request(conn, &cmd).unwrap()
+    }
+
+    #[test]
+    fn socket_round_trip_with_real_processes() {
+        let dir = dist_with_scripts();
+        let ctl = Arc::new(Mutex::new(Controller::new(&dir, manifest(), ProcessLauncher)));
+        let listener = Listener::bind(&dir).unwrap();
+        std::thread::spawn(move || crate::server::listen(listener, ctl));
+        assert_eq!(Listener::bind(&dir).err().unwrap().kind(), io::ErrorKind::AddrInUse);
+
+        let mut conn = connect(&dir).unwrap();
+        assert_eq!(ask(&mut conn, Command::Switch { name: "red".into() }), Reply::ok(Some("red".into())));
+        let red_pid = ask(&mut conn, Command::Status).pid.unwrap();
+        assert_eq!(ask(&mut conn, Command::Switch { name: "navy".into() }), Reply::ok(Some("navy".into())));
+        // Stopped and reaped
+        assert!(!Path::new(&format!("/proc/{}", red_pid)).exists());
+
+        // A second client sees the same state
+        let mut other = connect(&dir).unwrap();
+        assert_eq!(ask(&mut other, Command::Status).current.as_deref(), Some("navy"));
+        let unknown = ask(&mut other, Command::Switch { name: "bleu".into() });
+        assert_eq!(unknown.error.unwrap().code, ErrorCode::UnknownProfile);
+
+        // off.exe reports "nothing running", which is success
+        assert_eq!(ask(&mut conn, Command::Off), Reply::ok(None));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn stale_socket_is_replaced() {
+        let dir = dist_with_scripts();
+        drop(Listener::bind(&dir).unwrap());
+        assert!(dir.join(SOCKET_FILE).exists());
+        assert!(Listener::bind(&dir).is_ok());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
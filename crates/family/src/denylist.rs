@@ -0,0 +1,124 @@
+//! Process names a profile must never be named after.
+//!
+//! The runner terminates every process whose image name matches a family entry,
+//! so a profile called `explorer` or `chrome` would take that program down on
+//! every switch.
+
+/// Windows components the runner refuses to terminate, whatever the manifest says.
+pub const SYSTEM_CRITICAL: &[&str] = &[
+    "system",
+    "registry",
+    "smss",
+    "csrss",
+    "wininit",
+    "winlogon",
+    "services",
+    "lsass",
+    "lsaiso",
+    "svchost",
+    "dwm",
+    "explorer",
+    "sihost",
+    "fontdrvhost",
+    "ctfmon",
+    "conhost",
+    "dllhost",
+    "taskhostw",
+    "runtimebroker",
+    "searchhost",
+    "startmenuexperiencehost",
+    "shellexperiencehost",
+    "spoolsv",
+    "audiodg",
+    "userinit",
+    "logonui",
+    "msmpeng",
+    "wudfhost",
+    "taskmgr",
+];
+
+/// Popular desktop applications; the generator refuses profile names that match.
+pub const WELL_KNOWN_APPS: &[&str] = &[
+    "chrome",
+    "msedge",
+    "firefox",
+    "opera",
+    "brave",
+    "iexplore",
+    "obs",
+    "obs32",
+    "obs64",
+    "steam",
+    "steamwebhelper",
+    "epicgameslauncher",
+    "eadesktop",
+    "battle.net",
+    "galaxyclient",
+    "discord",
+    "spotify",
+    "slack",
+    "teams",
+    "ms-teams",
+    "zoom",
+    "skype",
+    "code",
+    "devenv",
+    "notepad",
+    "winword",
+    "excel",
+    "powerpnt",
+    "outlook",
+    "onedrive",
+    "streamdeck",
+    "awcc",
+    "powershell",
+    "pwsh",
+    "cmd",
+    "windowsterminal",
+    "wt",
+    "vlc",
+];
+
+/// Strip a trailing `.exe` and lowercase, so `Explorer.EXE` and `explorer` compare equal.
+pub fn stem_key(name: &str) -> String {
+    let lower = name.trim().to_ascii_lowercase();
+    match lower.strip_suffix(".exe") {
+        Some(stem) => stem.to_string(),
+        None => lower,
+    }
+}
+
+/// True for Windows components the runner must never terminate.
+pub fn is_system_critical(image_name: &str) -> bool {
+    let key = stem_key(image_name);
+    SYSTEM_CRITICAL.iter().any(|n| *n == key)
+}
+
+pub fn is_well_known_app(name: &str) -> bool {
+    let key = stem_key(name);
+    WELL_KNOWN_APPS.iter().any(|n| *n == key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_with_or_without_exe_in_any_case() {
+        assert!(is_system_critical("explorer.exe"));
+        assert!(is_system_critical("CSRSS.EXE"));
+        assert!(is_system_critical("winlogon"));
+        assert!(!is_system_critical("navy.exe"));
+        assert!(is_well_known_app("Chrome.exe"));
+        assert!(is_well_known_app("obs64"));
+        assert!(!is_well_known_app("chromecast"));
+    }
+
+    #[test]
+    fn lists_are_lowercase_stems() {
+        for n in SYSTEM_CRITICAL.iter().chain(WELL_KNOWN_APPS) {
+            assert_eq!(*n, n.to_ascii_lowercase());
+            assert!(!n.ends_with(".exe"));
+        }
+    }
+}
@@ -0,0 +1,454 @@
+//! Profile icons: a rounded swatch of the profile colour with its label on it.
+//!
+//! Rendering uses only integer maths and a built-in 5×7 font, so the same
+//! profile gives the same bytes on every machine.
+
+use crate::instance::fnv1a;
+use crate::png;
+use crate::{Color, Entry};
+
+/// Sizes of the PNGs written per profile (Stream Deck keys and their @2x).
+pub const PNG_SIZES: [u32; 2] = [72, 144];
+/// Sizes inside the `.ico` (tray, Explorer views).
+pub const ICO_SIZES: [u32; 4] = [16, 32, 48, 256];
+
+/// File name of a profile's PNG of `size` pixels inside `dist/icons/`.
+pub fn png_file(name: &str, size: u32) -> String {
+    format!("{}-{}.png", name, size)
+}
+
+/// File name of a profile's icon inside `dist/icons/`.
+pub fn ico_file(name: &str) -> String {
+    format!("{}.ico", name)
+}
+
+/// Square RGBA image, rows top to bottom.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    pub size: u32,
+    pub rgba: Vec<u8>,
+}
+
+impl Image {
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        let i = ((y * self.size + x) * 4) as usize;
+        [self.rgba[i], self.rgba[i + 1], self.rgba[i + 2], self.rgba[i + 3]]
+    }
+
+    fn set(&mut self, x: u32, y: u32, rgba: [u8; 4]) {
+        let i = ((y * self.size + x) * 4) as usize;
+        self.rgba[i..i + 4].copy_from_slice(&rgba);
+    }
+
+    pub fn to_png(&self) -> Vec<u8> {
+        png::encode(self.size, self.size, &self.rgba)
+    }
+}
+
+/// Colour for a profile without one: a mid-tone hue picked from the name.
+pub fn fallback_color(name: &str) -> Color {
+    let hue = (fnv1a(name.as_bytes()) % 360) as u32;
+    // HSV with S = 0.65, V = 0.85, in integer steps of 1/255
+    let v = 217u32;
+    let min = v * (100 - 65) / 100;
+    let rise = |h: u32| min + (v - min) * h / 60;
+    let fall = |h: u32| v - (v - min) * h / 60;
+    let (r, g, b) = match hue / 60 {
+        0 => (v, rise(hue), min),
+        1 => (fall(hue - 60), v, min),
+        2 => (min, v, rise(hue - 120)),
+        3 => (min, fall(hue - 180), v),
+        4 => (rise(hue - 240), min, v),
+        _ => (v, min, fall(hue - 300)),
+    };
+    Color::rgb(r as u8, g as u8, b as u8)
+}
+
+/// Colour of a profile's icons: its manifest colour, else [`fallback_color`].
+pub fn profile_color(entry: &Entry) -> Color {
+    entry.parsed_color().unwrap_or_else(|| fallback_color(&entry.name))
+}
+
+/// Tray icon of `size` pixels (the small-icon metric) as BGRA rows, top row
+/// first, with straight alpha: the layout of a 32-bit top-down DIB section.
+pub fn tray_pixels(color: Color, label: &str, size: u32) -> Vec<u8> {
+    let mut pixels = render(color, label, size).rgba;
+    for px in pixels.chunks_exact_mut(4) {
+        px.swap(0, 2);
+    }
+    pixels
+}
+
+/// Swatch of `color` with `label` centred on it, in black or white, whichever
+/// reads better. Below 48 pixels only the first letter fits.
+pub fn render(color: Color, label: &str, size: u32) -> Image {
+    let mut img = Image { size, rgba: vec![0; (size * size * 4) as usize] };
+    let radius = size / 8;
+    for y in 0..size {
+        for x in 0..size {
+            let alpha = corner_coverage(x, y, size, radius);
+            if alpha > 0 {
+                img.set(x, y, [color.r, color.g, color.b, alpha]);
+            }
+        }
+    }
+
+    let ink = if color.luminance() > 0.179 { [0, 0, 0, 255] } else { [255, 255, 255, 255] };
+    let (lines, scale) = if size < 48 {
+        let initial = label.chars().find(|c| !c.is_whitespace()).map(|c| c.to_ascii_uppercase());
+        (initial.map(|c| vec![c.to_string()]).unwrap_or_default(), (size / 16).max(1))
+    } else {
+        layout(label, size - 2 * (size / 12).max(1), (size / 24).max(1))
+    };
+    let line_height = 8 * scale;
+    let height = (lines.len() as u32 * line_height).saturating_sub(scale);
+    let mut top = size.saturating_sub(height) / 2;
+    for line in &lines {
+        let width = text_width(line, scale);
+        let mut left = size.saturating_sub(width) / 2;
+        for c in line.chars() {
+            for (col, bits) in glyph(c).iter().enumerate() {
+                for row in 0..7 {
+                    if bits >> row & 1 == 0 {
+                        continue;
+                    }
+                    for dy in 0..scale {
+                        for dx in 0..scale {
+                            let (x, y) = (left + col as u32 * scale + dx, top + row * scale + dy);
+                            if x < size && y < size {
+                                img.set(x, y, ink);
+                            }
+                        }
+                    }
+                }
+            }
+            left += 6 * scale;
+        }
+        top += line_height;
+    }
+    img
+}
+
+/// PNG of [`render`].
+pub fn png(color: Color, label: &str, size: u32) -> Vec<u8> {
+    render(color, label, size).to_png()
+}
+
+/// Images for an icon with every size in [`ICO_SIZES`]: DIBs, and PNG at 256
+/// pixels, as Windows' own icons have them.
+pub fn ico_images(color: Color, label: &str) -> Vec<(u32, Vec<u8>)> {
+    ICO_SIZES
+        .iter()
+        .map(|&s| {
+            let img = render(color, label, s);
+            (s, if s >= 256 { img.to_png() } else { png::dib(s, &img.rgba) })
+        })
+        .collect()
+}
+
+/// `.ico` of [`ico_images`].
+pub fn ico(color: Color, label: &str) -> Vec<u8> {
+    png::ico(&ico_images(color, label))
+}
+
+/// Opacity of pixel (`x`, `y`) in a `size` square with corners of `radius`,
+/// from 4×4 samples.
+fn corner_coverage(x: u32, y: u32, size: u32, radius: u32) -> u8 {
+    let near = |v: u32| v < radius || v >= size - radius;
+    if radius == 0 || !(near(x) && near(y)) {
+        return 255;
+    }
+    // Work in quarter pixels, relative to the centre of the nearest corner circle
+    let (r, s) = (radius as i64 * 4, size as i64 * 4);
+    let centre = |v: i64| if v < r { r } else { s - r };
+    let mut hits = 0;
+    for sy in 0..4 {
+        for sx in 0..4 {
+            let (px, py) = (x as i64 * 4 + sx, y as i64 * 4 + sy);
+            let (dx, dy) = (2 * (px - centre(px)) + 1, 2 * (py - centre(py)) + 1);
+            if dx * dx + dy * dy <= 4 * r * r {
+                hits += 1;
+            }
+        }
+    }
+    (hits * 255 / 16) as u8
+}
+
+fn text_width(text: &str, scale: u32) -> u32 {
+    (text.chars().count() as u32 * 6 * scale).saturating_sub(scale)
+}
+
+/// Lines of `label` and the largest scale up to `max_scale` at which they fit
+/// in a `room` square, wrapping at spaces. Words are only split at scale 1,
+/// and there whatever does not fit is cut.
+fn layout(label: &str, room: u32, max_scale: u32) -> (Vec<String>, u32) {
+    for scale in (1..=max_scale).rev() {
+        let per_line = (room + scale) / (6 * scale);
+        let whole_words = scale == 1 || label.split_whitespace().all(|w| w.chars().count() as u32 <= per_line);
+        let lines = wrap(label, per_line);
+        if whole_words && lines.len() as u32 * 8 * scale <= room + scale {
+            return (lines, scale);
+        }
+    }
+    let per_line = ((room + 1) / 6) as usize;
+    let mut lines = wrap(label, per_line as u32);
+    lines.truncate(((room + 1) / 8) as usize);
+    (lines, 1)
+}
+
+/// Greedy word wrap to `width` characters; longer words are split.
+fn wrap(text: &str, width: u32) -> Vec<String> {
+    let width = width.max(1) as usize;
+    let mut lines: Vec<String> = Vec::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        let mut word: Vec<char> = word.chars().collect();
+        loop {
+            let used = line.chars().count();
+            let gap = usize::from(used > 0);
+            if used + gap + word.len() <= width {
+                if gap == 1 {
+                    line.push(' ');
+                }
+                line.extend(word.iter());
+                break;
+            }
+            if used > 0 {
+                lines.push(std::mem::take(&mut line));
+                continue;
+            }
+            let rest = word.split_off(width);
+            lines.push(word.into_iter().collect());
+            word = rest;
+        }
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+/// Columns of `c`, bit 0 at the top. Characters outside printable ASCII are a box.
+fn glyph(c: char) -> [u8; 5] {
+    match c {
+        ' '..='~' => FONT[c as usize - 0x20],
+        _ => [0x7f, 0x41, 0x41, 0x41, 0x7f],
+    }
+}
+
+/// Classic 5×7 LCD font for printable ASCII.
+const FONT: [[u8; 5]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00], // ' '
+    [0x00, 0x00, 0x5f, 0x00, 0x00], // !
+    [0x00, 0x07, 0x00, 0x07, 0x00], // "
+    [0x14, 0x7f, 0x14, 0x7f, 0x14], // #
+    [0x24, 0x2a, 0x7f, 0x2a, 0x12], // $
+    [0x23, 0x13, 0x08, 0x64, 0x62], // %
+    [0x36, 0x49, 0x55, 0x22, 0x50], // &
+    [0x00, 0x05, 0x03, 0x00, 0x00], // '
+    [0x00, 0x1c, 0x22, 0x41, 0x00], // (
+    [0x00, 0x41, 0x22, 0x1c, 0x00], // )
+    [0x08, 0x2a, 0x1c, 0x2a, 0x08], // *
+    [0x08, 0x08, 0x3e, 0x08, 0x08], // +
+    [0x00, 0x50, 0x30, 0x00, 0x00], // ,
+    [0x08, 0x08, 0x08, 0x08, 0x08], // -
+    [0x00, 0x60, 0x60, 0x00, 0x00], // .
+    [0x20, 0x10, 0x08, 0x04, 0x02], // /
+    [0x3e, 0x51, 0x49, 0x45, 0x3e], // 0
+    [0x00, 0x42, 0x7f, 0x40, 0x00], // 1
+    [0x42, 0x61, 0x51, 0x49, 0x46], // 2
+    [0x21, 0x41, 0x45, 0x4b, 0x31], // 3
+    [0x18, 0x14, 0x12, 0x7f, 0x10], // 4
+    [0x27, 0x45, 0x45, 0x45, 0x39], // 5
+    [0x3c, 0x4a, 0x49, 0x49, 0x30], // 6
+    [0x01, 0x71, 0x09, 0x05, 0x03], // 7
+    [0x36, 0x49, 0x49, 0x49, 0x36], // 8
+    [0x06, 0x49, 0x49, 0x29, 0x1e], // 9
+    [0x00, 0x36, 0x36, 0x00, 0x00], // :
+    [0x00, 0x56, 0x36, 0x00, 0x00], // ;
+    [0x08, 0x14, 0x22, 0x41, 0x00], // <
+    [0x14, 0x14, 0x14, 0x14, 0x14], // =
+    [0x00, 0x41, 0x22, 0x14, 0x08], // >
+    [0x02, 0x01, 0x51, 0x09, 0x06], // ?
+    [0x32, 0x49, 0x79, 0x41, 0x3e], // @
+    [0x7e, 0x11, 0x11, 0x11, 0x7e], // A
+    [0x7f, 0x49, 0x49, 0x49, 0x36], // B
+    [0x3e, 0x41, 0x41, 0x41, 0x22], // C
+    [0x7f, 0x41, 0x41, 0x22, 0x1c], // D
+    [0x7f, 0x49, 0x49, 0x49, 0x41], // E
+    [0x7f, 0x09, 0x09, 0x09, 0x01], // F
+    [0x3e, 0x41, 0x49, 0x49, 0x7a], // G
+    [0x7f, 0x08, 0x08, 0x08, 0x7f], // H
+    [0x00, 0x41, 0x7f, 0x41, 0x00], // I
+    [0x20, 0x40, 0x41, 0x3f, 0x01], // J
+    [0x7f, 0x08, 0x14, 0x22, 0x41], // K
+    [0x7f, 0x40, 0x40, 0x40, 0x40], // L
+    [0x7f, 0x02, 0x0c, 0x02, 0x7f], // M
+    [0x7f, 0x04, 0x08, 0x10, 0x7f], // N
+    [0x3e, 0x41, 0x41, 0x41, 0x3e], // O
+    [0x7f, 0x09, 0x09, 0x09, 0x06], // P
+    [0x3e, 0x41, 0x51, 0x21, 0x5e], // Q
+    [0x7f, 0x09, 0x19, 0x29, 0x46], // R
+    [0x46, 0x49, 0x49, 0x49, 0x31], // S
+    [0x01, 0x01, 0x7f, 0x01, 0x01], // T
+    [0x3f, 0x40, 0x40, 0x40, 0x3f], // U
+    [0x1f, 0x20, 0x40, 0x20, 0x1f], // V
+    [0x3f, 0x40, 0x38, 0x40, 0x3f], // W
+    [0x63, 0x14, 0x08, 0x14, 0x63], // X
+    [0x07, 0x08, 0x70, 0x08, 0x07], // Y
+    [0x61, 0x51, 0x49, 0x45, 0x43], // Z
+    [0x00, 0x7f, 0x41, 0x41, 0x00], // [
+    [0x02, 0x04, 0x08, 0x10, 0x20], // \
+    [0x00, 0x41, 0x41, 0x7f, 0x00], // ]
+    [0x04, 0x02, 0x01, 0x02, 0x04], // ^
+    [0x40, 0x40, 0x40, 0x40, 0x40], // _
+    [0x00, 0x01, 0x02, 0x04, 0x00], // `
+    [0x20, 0x54, 0x54, 0x54, 0x78], // a
+    [0x7f, 0x48, 0x44, 0x44, 0x38], // b
+    [0x38, 0x44, 0x44, 0x44, 0x20], // c
+    [0x38, 0x44, 0x44, 0x48, 0x7f], // d
+    [0x38, 0x54, 0x54, 0x54, 0x18], // e
+    [0x08, 0x7e, 0x09, 0x01, 0x02], // f
+    [0x0c, 0x52, 0x52, 0x52, 0x3e], // g
+    [0x7f, 0x08, 0x04, 0x04, 0x78], // h
+    [0x00, 0x44, 0x7d, 0x40, 0x00], // i
+    [0x20, 0x40, 0x44, 0x3d, 0x00], // j
+    [0x7f, 0x10, 0x28, 0x44, 0x00], // k
+    [0x00, 0x41, 0x7f, 0x40, 0x00], // l
+    [0x7c, 0x04, 0x18, 0x04, 0x78], // m
+    [0x7c, 0x08, 0x04, 0x04, 0x78], // n
+    [0x38, 0x44, 0x44, 0x44, 0x38], // o
+    [0x7c, 0x14, 0x14, 0x14, 0x08], // p
+    [0x08, 0x14, 0x14, 0x18, 0x7c], // q
+    [0x7c, 0x08, 0x04, 0x04, 0x08], // r
+    [0x48, 0x54, 0x54, 0x54, 0x20], // s
+    [0x04, 0x3f, 0x44, 0x40, 0x20], // t
+    [0x3c, 0x40, 0x40, 0x20, 0x7c], // u
+    [0x1c, 0x20, 0x40, 0x20, 0x1c], // v
+    [0x3c, 0x40, 0x30, 0x40, 0x3c], // w
+    [0x44, 0x28, 0x10, 0x28, 0x44], // x
+    [0x0c, 0x50, 0x50, 0x50, 0x3c], // y
+    [0x44, 0x64, 0x54, 0x4c, 0x44], // z
+    [0x00, 0x08, 0x36, 0x41, 0x00], // {
+    [0x00, 0x00, 0x7f, 0x00, 0x00], // |
+    [0x00, 0x41, 0x36, 0x08, 0x00], // }
+    [0x08, 0x04, 0x08, 0x10, 0x08], // ~
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::png::read;
+    use std::path::PathBuf;
+
+    /// Compare with `testdata/icons/<file>`; `UPDATE_SNAPSHOTS=1` rewrites it.
+    fn snapshot(file: &str, bytes: &[u8]) {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("testdata").join("icons").join(file);
+        if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, bytes).unwrap();
+        }
+        let expected = std::fs::read(&path).unwrap_or_else(|_| panic!("missing {}; run with UPDATE_SNAPSHOTS=1", path.display()));
+        assert!(expected == bytes, "{} differs from the snapshot; check and run with UPDATE_SNAPSHOTS=1", file);
+    }
+
+    /// Rows of `#` (ink), `.` (swatch), `+` (edge) and ` ` (transparent), for readable failures.
+    fn ascii(img: &Image, color: Color) -> Vec<String> {
+        (0..img.size)
+            .map(|y| {
+                (0..img.size)
+                    .map(|x| match img.pixel(x, y) {
+                        [_, _, _, 0] => ' ',
+                        [_, _, _, a] if a < 255 => '+',
+                        [r, g, b, _] if (r, g, b) == (color.r, color.g, color.b) => '.',
+                        _ => '#',
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn small_icons_show_the_initial() {
+        let navy = Color::parse("navy").unwrap();
+        let img = render(navy, "navy blue", 16);
+        assert_eq!(
+            ascii(&img, navy),
+            [
+                "++............++",
+                "+..............+",
+                "................",
+                "................",
+                ".....#...#......",
+                ".....#...#......",
+                ".....##..#......",
+                ".....#.#.#......",
+                ".....#..##......",
+                ".....#...#......",
+                ".....#...#......",
+                "................",
+                "................",
+                "................",
+                "+..............+",
+                "++............++",
+            ]
+        );
+        // Dark swatch, white ink; light swatch, black ink
+        assert_eq!(img.pixel(5, 4), [255, 255, 255, 255]);
+        assert_eq!(render(Color::parse("#ffff00").unwrap(), "y", 16).pixel(5, 4), [0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn labels_wrap_then_shrink_then_cut() {
+        assert_eq!(layout("red", 60, 3), (vec!["red".to_string()], 3));
+        assert_eq!(layout("Meeting red", 60, 3), (vec!["Meeting".to_string(), "red".to_string()], 1));
+        assert_eq!(layout("Meeting red", 132, 6), (vec!["Meeting".to_string(), "red".to_string()], 3));
+        assert_eq!(wrap("warm_white_extra", 10), ["warm_white", "_extra"]);
+        let (lines, scale) = layout("a b c d e f g h i j", 12, 1);
+        assert_eq!((lines.len(), scale), (1, 1));
+    }
+
+    #[test]
+    fn generated_icons_match_snapshots() {
+        let red = Color::parse("#ff0000").unwrap();
+        for size in PNG_SIZES {
+            let png = png(red, "Meeting red", size);
+            let (w, h, rgba) = read::decode(&png);
+            assert_eq!((w, h), (size, size));
+            assert_eq!(rgba, render(red, "Meeting red", size).rgba);
+            snapshot(&png_file("red", size), &png);
+        }
+        snapshot(&ico_file("red"), &ico(red, "Meeting red"));
+        assert_eq!(png(red, "Meeting red", 72), png(red, "Meeting red", 72), "deterministic");
+    }
+
+    #[test]
+    fn tray_pixels_match_golden_values() {
+        let navy = Color::parse("navy").unwrap();
+        let pixels = tray_pixels(navy, "navy blue", 16);
+        let at = |buf: &[u8], x: usize, y: usize| buf[(y * 16 + x) * 4..(y * 16 + x) * 4 + 4].to_vec();
+        assert_eq!(at(&pixels, 8, 1), [0x80, 0, 0, 255], "BGRA");
+        assert_eq!(at(&pixels, 5, 4), [255, 255, 255, 255], "ink");
+        assert_eq!(at(&pixels, 0, 0)[3], 95, "rounded corner");
+        // Golden checksums of the common small-icon sizes (100% to 200% scaling)
+        let golden: Vec<(u32, u32)> = [16, 20, 24, 32].iter().map(|&s| (s, png::crc32(&tray_pixels(navy, "navy blue", s)))).collect();
+        assert_eq!(golden, [(16, 0x03e0_f65d), (20, 0xfc05_dc12), (24, 0xccea_74ef), (32, 0xb832_cdb8)]);
+        snapshot("tray-navy-32.png", &png::encode(32, 32, &render(navy, "navy blue", 32).rgba));
+
+        let mut red = Entry::new("red", "red.exe", crate::Role::Profile);
+        assert_eq!(profile_color(&red), fallback_color("red"));
+        red.color = Some("#ff0000".to_string());
+        assert_eq!(profile_color(&red), Color::rgb(255, 0, 0));
+    }
+
+    #[test]
+    fn fallback_colors_are_stable_and_distinct() {
+        assert_eq!(fallback_color("red"), fallback_color("red"));
+        assert_ne!(fallback_color("red"), fallback_color("navy"));
+        for name in ["a", "b", "streaming", "warm_white"] {
+            let c = fallback_color(name);
+            let (hi, lo) = (c.r.max(c.g).max(c.b), c.r.min(c.g).min(c.b));
+            assert_eq!((hi, lo), (217, 75), "{}: {:?}", name, c);
+        }
+    }
+}
@@ -0,0 +1,221 @@
+//! Who a generated EXE is, recorded in the EXE itself.
+//!
+//! All EXEs of a family are copies of the same runner, so the file name used to
+//! be the only thing telling them apart: a renamed or hard-linked copy silently
+//! became another profile. The generator now appends an identity trailer to
+//! every copy (the profile name, the [`family_id`] and the [`manifest_hash`] of
+//! the manifest written alongside). Windows ignores data past the last section.
+//!
+//! Layout, at the very end of the file: the JSON payload, then its length
+//! (u32 LE), its FNV-1a checksum (u64 LE) and [`MAGIC`]. The checksum is an
+//! integrity check only: it catches a truncated or damaged trailer, but it is
+//! neither a signature nor a MAC, and anyone able to write the EXE can forge a
+//! trailer with a matching checksum. The identity is not tamper-proof.
+
+use crate::instance::fnv1a;
+use crate::manifest::{image_key, Entry, Manifest, MANIFEST_FILE};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+pub const MAGIC: &[u8; 8] = b"AWCCID01";
+const FOOTER_LEN: usize = 4 + 8 + MAGIC.len();
+/// Anything longer is not one of our trailers.
+const MAX_PAYLOAD: usize = 64 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Identity {
+    pub profile: String,
+    pub family_id: String,
+    pub manifest_hash: String,
+}
+
+impl Identity {
+    /// Identity of `profile` in `manifest`, whose written form is `manifest_json`.
+    pub fn new(manifest: &Manifest, manifest_json: &str, profile: &str) -> Identity {
+        Identity {
+            profile: profile.to_string(),
+            family_id: family_id(manifest),
+            manifest_hash: manifest_hash(manifest_json.as_bytes()),
+        }
+    }
+
+    /// Bytes to append to the EXE.
+    pub fn to_trailer(&self) -> Vec<u8> {
+        let mut out = serde_json::to_vec(self).expect("identity serializes");
+        let len = out.len() as u32;
+        let sum = fnv1a(&out);
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&sum.to_le_bytes());
+        out.extend_from_slice(MAGIC);
+        out
+    }
+
+    /// The identity at the end of `bytes`; `None` when there is no trailer.
+    pub fn from_trailer(bytes: &[u8]) -> io::Result<Option<Identity>> {
+        let Some(footer) = bytes.len().checked_sub(FOOTER_LEN).map(|at| &bytes[at..]) else { return Ok(None) };
+        if &footer[12..] != MAGIC {
+            return Ok(None);
+        }
+        let len = u32::from_le_bytes(footer[..4].try_into().unwrap()) as usize;
+        let sum = u64::from_le_bytes(footer[4..12].try_into().unwrap());
+        let start = (bytes.len() - FOOTER_LEN).checked_sub(len).filter(|_| len <= MAX_PAYLOAD).ok_or_else(|| corrupt("bad length"))?;
+        let payload = &bytes[start..bytes.len() - FOOTER_LEN];
+        if fnv1a(payload) != sum {
+            return Err(corrupt("checksum mismatch"));
+        }
+        serde_json::from_slice(payload).map(Some).map_err(|e| corrupt(&e.to_string()))
+    }
+
+    /// The identity appended to the file at `path`, reading only its tail.
+    pub fn read(path: &Path) -> io::Result<Option<Identity>> {
+        let mut file = File::open(path)?;
+        let size = file.metadata()?.len();
+        let tail = size.min((MAX_PAYLOAD + FOOTER_LEN) as u64);
+        file.seek(SeekFrom::Start(size - tail))?;
+        let mut bytes = Vec::with_capacity(tail as usize);
+        file.read_to_end(&mut bytes)?;
+        Identity::from_trailer(&bytes)
+    }
+}
+
+fn corrupt(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("identity trailer: {}", msg))
+}
+
+/// Stable ID of the set of EXEs `manifest` manages; it changes only when a
+/// profile is added, removed or renamed.
+pub fn family_id(manifest: &Manifest) -> String {
+    let mut exes: Vec<String> = manifest.managed_exes().map(image_key).collect();
+    exes.sort();
+    format!("{:016x}", fnv1a(exes.join("\n").as_bytes()))
+}
+
+pub fn manifest_hash(manifest_json: &[u8]) -> String {
+    format!("{:016x}", fnv1a(manifest_json))
+}
+
+/// Hash of `dir/manifest.json` as it is on disk; `None` without one.
+pub fn manifest_hash_in(dir: &Path) -> Option<String> {
+    fs::read(dir.join(MANIFEST_FILE)).ok().map(|bytes| manifest_hash(&bytes))
+}
+
+/// A way the running EXE disagrees with its embedded identity.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Mismatch {
+    /// The file was renamed or hard-linked; `expected` is the EXE the profile was generated as.
+    FileName { file: String, expected: String },
+    /// The EXE was generated for another family (or the profile is gone from it).
+    OtherFamily,
+    /// Same family, but the manifest was regenerated since this EXE was written.
+    StaleManifest,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Mismatch::FileName { file, expected } => {
+                write!(f, "{} was generated as {}; it was renamed or linked, acting as the embedded profile", file, expected)
+            }
+            Mismatch::OtherFamily => write!(f, "this EXE belongs to another family; regenerate it or move it back"),
+            Mismatch::StaleManifest => write!(f, "{} changed since this EXE was generated; regenerate to refresh it", MANIFEST_FILE),
+        }
+    }
+}
+
+/// The manifest entry this EXE is: the embedded profile when it belongs to this
+/// family, otherwise whatever the file name says.
+pub fn resolve<'a>(identity: Option<&Identity>, manifest: &'a Manifest, file_exe: &str) -> Option<&'a Entry> {
+    identity
+        .filter(|id| id.family_id == family_id(manifest))
+        .and_then(|id| manifest.entry_by_name(&id.profile))
+        .or_else(|| manifest.entry_by_exe(file_exe))
+}
+
+/// Everything worth warning about; `manifest_hash` is that of the manifest on disk.
+pub fn check(identity: &Identity, manifest: &Manifest, manifest_hash: Option<&str>, file_exe: &str) -> Vec<Mismatch> {
+    let entry = Some(identity).filter(|id| id.family_id == family_id(manifest)).and_then(|id| manifest.entry_by_name(&id.profile));
+    let Some(entry) = entry else { return vec![Mismatch::OtherFamily] };
+    let mut found = Vec::new();
+    if image_key(&entry.exe) != image_key(file_exe) {
+        found.push(Mismatch::FileName { file: file_exe.to_string(), expected: entry.exe.clone() });
+    }
+    if manifest_hash.is_some_and(|h| h != identity.manifest_hash) {
+        found.push(Mismatch::StaleManifest);
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::{Role, MANIFEST_VERSION};
+
+    fn manifest() -> Manifest {
+        let profiles = vec![
+            Entry::new("red", "red.exe", Role::Profile),
+            Entry::new("navy", "navy.exe", Role::Profile),
+            Entry::new("off", "off.exe", Role::Off),
+        ];
+        Manifest { version: MANIFEST_VERSION, generator_version: None, profiles, awcc: None, runner: Default::default() }
+    }
+
+    #[test]
+    fn trailer_round_trips_at_the_end_of_an_exe() {
+        let m = manifest();
+        let id = Identity::new(&m, &m.to_json(), "red");
+        let mut exe = b"MZ rest of the image".to_vec();
+        assert_eq!(Identity::from_trailer(&exe).unwrap(), None);
+        exe.extend_from_slice(&id.to_trailer());
+        assert_eq!(Identity::from_trailer(&exe).unwrap(), Some(id.clone()));
+
+        let path = std::env::temp_dir().join(format!("awcc-identity-test-{}.exe", std::process::id()));
+        fs::write(&path, &exe).unwrap();
+        assert_eq!(Identity::read(&path).unwrap(), Some(id));
+        fs::remove_file(&path).unwrap();
+
+        // A flipped payload byte is caught
+        let at = exe.len() - FOOTER_LEN - 3;
+        exe[at] ^= 1;
+        assert_eq!(Identity::from_trailer(&exe).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(Identity::from_trailer(b"short").unwrap(), None);
+    }
+
+    #[test]
+    fn family_id_depends_only_on_the_exe_set() {
+        let a = manifest();
+        let mut b = manifest();
+        b.profiles.swap(0, 1);
+        b.profiles[0].display_name = Some("Navy".to_string());
+        b.profiles[1].exe = "RED.exe".to_string();
+        assert_eq!(family_id(&a), family_id(&b));
+        b.profiles.push(Entry::new("green", "green.exe", Role::Profile));
+        assert_ne!(family_id(&a), family_id(&b));
+    }
+
+    #[test]
+    fn renamed_copies_keep_their_profile_and_are_reported() {
+        let m = manifest();
+        let json = m.to_json();
+        let hash = manifest_hash(json.as_bytes());
+        let red = Identity::new(&m, &json, "red");
+
+        assert_eq!(check(&red, &m, Some(&hash), "Red.exe"), []);
+        assert_eq!(resolve(Some(&red), &m, "red - copy.exe").map(|e| e.name.as_str()), Some("red"));
+        assert_eq!(
+            check(&red, &m, Some(&hash), "navy.exe"),
+            [Mismatch::FileName { file: "navy.exe".to_string(), expected: "red.exe".to_string() }]
+        );
+        assert_eq!(check(&red, &m, Some("0000000000000000"), "red.exe"), [Mismatch::StaleManifest]);
+        assert_eq!(check(&red, &m, None, "red.exe"), [], "legacy layouts have no manifest to compare");
+
+        // From another family: the file name decides, as before
+        let mut other = red.clone();
+        other.family_id = "ffffffffffffffff".to_string();
+        assert_eq!(check(&other, &m, Some(&hash), "red.exe"), [Mismatch::OtherFamily]);
+        assert_eq!(resolve(Some(&other), &m, "navy.exe").map(|e| e.name.as_str()), Some("navy"));
+        assert_eq!(resolve(None, &m, "off.exe").map(|e| e.role), Some(Role::Off));
+    }
+}
@@ -0,0 +1,170 @@
+//! One running instance per profile EXE.
+//!
+//! Sibling stopping deliberately skips our own image name, so launching `red.exe`
+//! twice would leave two tray icons. Instead the first instance holds a named
+//! lock for its profile; a second launch finds it taken, asks the holder to
+//! refresh (re-assert foreground, restart its auto-off timer on `--for`) and exits.
+//!
+//! The relaunch is still a switch request: it takes a switch ticket first and
+//! hands it over in the [`Refresh`], so the holder switches back to itself
+//! unless an even newer request has been made.
+
+use crate::siblings::dir_key;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Result of trying to become the instance for a profile.
+pub enum Acquire<G> {
+    /// We hold the lock until the guard is dropped.
+    Acquired(G),
+    AlreadyRunning,
+}
+
+/// Named single-instance primitive (a named mutex on Windows).
+pub trait InstanceLock {
+    type Guard;
+
+    fn acquire(&mut self, key: &str) -> Acquire<Self::Guard>;
+    /// Ask the instance holding `key` to refresh itself; false if it could not be reached.
+    fn notify_refresh(&mut self, key: &str, request: &Refresh) -> bool;
+}
+
+/// What a second launch hands the running instance of its profile.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Refresh {
+    /// The relaunch's switch ticket; the holder stops the siblings under it.
+    pub ticket: Option<u64>,
+    /// Creation time of the relaunch: siblings started before it are stopped,
+    /// later ones are newer requests and are left alone.
+    pub created: Option<u64>,
+    /// The relaunch's `--for`, in seconds; the holder restarts its auto-off timer with it.
+    pub auto_off_secs: Option<u64>,
+}
+
+/// What this launch should do.
+pub enum Startup<G> {
+    /// Carry on as the profile's instance, keeping the guard alive.
+    Primary(G),
+    /// Another instance already runs the profile; `notified` says whether it got the refresh.
+    Secondary { notified: bool },
+}
+
+impl<G> fmt::Debug for Startup<G> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Startup::Primary(_) => write!(f, "Primary"),
+            Startup::Secondary { notified } => write!(f, "Secondary {{ notified: {} }}", notified),
+        }
+    }
+}
+
+pub fn claim<L: InstanceLock>(lock: &mut L, key: &str, request: &Refresh) -> Startup<L::Guard> {
+    match lock.acquire(key) {
+        Acquire::Acquired(guard) => Startup::Primary(guard),
+        Acquire::AlreadyRunning => Startup::Secondary { notified: lock.notify_refresh(key, request) },
+    }
+}
+
+/// Lock key for the EXE `exe` in directory `dir`. Copies of the family in different
+/// folders do not block each other; case and path spelling do not matter.
+pub fn instance_key(dir: &str, exe: &str) -> String {
+    let stem = crate::manifest::image_key(exe);
+    let stem = stem.strip_suffix(".exe").unwrap_or(&stem);
+    format!("{}.{}", family_key(dir), stem)
+}
+
+/// Prefix shared by every named object of the family living in `dir`.
+pub fn family_key(dir: &str) -> String {
+    format!("AwccCtrlExeMoc.{:016x}", fnv1a(dir_key(dir).as_bytes()))
+}
+
+/// FNV-1a; stable across builds, unlike `DefaultHasher`.
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut h: u64 = 0xcbf2_9ce4_8422_2325;
+    for b in bytes {
+        h ^= *b as u64;
+        h = h.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    h
+}
+
+#[cfg(test)]
+pub(crate) mod fake {
+    use super::*;
+    use std::cell::RefCell;
+    use std::collections::HashSet;
+    use std::rc::Rc;
+
+    /// In-memory locks shared by every clone, standing in for the kernel namespace.
+    #[derive(Debug, Clone, Default)]
+    pub struct MemoryInstances {
+        held: Rc<RefCell<HashSet<String>>>,
+        pub refreshed: Rc<RefCell<Vec<(String, Refresh)>>>,
+    }
+
+    pub struct Guard {
+        key: String,
+        held: Rc<RefCell<HashSet<String>>>,
+    }
+
+    impl Drop for Guard {
+        fn drop(&mut self) {
+            self.held.borrow_mut().remove(&self.key);
+        }
+    }
+
+    impl InstanceLock for MemoryInstances {
+        type Guard = Guard;
+
+        fn acquire(&mut self, key: &str) -> Acquire<Guard> {
+            if self.held.borrow_mut().insert(key.to_string()) {
+                Acquire::Acquired(Guard { key: key.to_string(), held: self.held.clone() })
+            } else {
+                Acquire::AlreadyRunning
+            }
+        }
+
+        fn notify_refresh(&mut self, key: &str, request: &Refresh) -> bool {
+            if !self.held.borrow().contains(key) {
+                return false;
+            }
+            self.refreshed.borrow_mut().push((key.to_string(), request.clone()));
+            true
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::fake::MemoryInstances;
+    use super::*;
+
+    #[test]
+    fn second_launch_refreshes_the_first_and_exits() {
+        let ns = MemoryInstances::default();
+        let key = instance_key("C:\\dist", "red.exe");
+
+        let first = claim(&mut ns.clone(), &key, &Refresh::default());
+        assert!(matches!(first, Startup::Primary(_)));
+        let request = Refresh { ticket: Some(7), created: Some(1234), auto_off_secs: Some(45 * 60) };
+        let second = claim(&mut ns.clone(), &key, &request);
+        assert!(matches!(second, Startup::Secondary { notified: true }));
+        assert_eq!(ns.refreshed.borrow().as_slice(), [(key.clone(), request)]);
+
+        // Other profiles are independent
+        assert!(matches!(claim(&mut ns.clone(), &instance_key("C:\\dist", "navy.exe"), &Refresh::default()), Startup::Primary(_)));
+
+        // Once the first exits, the next launch takes over
+        drop(first);
+        assert!(matches!(claim(&mut ns.clone(), &key, &Refresh::default()), Startup::Primary(_)));
+    }
+
+    #[test]
+    fn keys_ignore_case_and_path_spelling_but_not_folder() {
+        let a = instance_key("C:\\Tools\\dist\\", "Red.EXE");
+        assert_eq!(a, instance_key("c:/tools/dist", "red"));
+        assert_ne!(a, instance_key("D:\\dist", "red.exe"));
+        assert!(a.starts_with("AwccCtrlExeMoc.") && a.ends_with(".red"), "{}", a);
+        assert!(!a.contains('\\'));
+    }
+}
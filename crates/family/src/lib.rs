@@ -0,0 +1,23 @@
+//! Shared runtime data for a generated runner family (`dist/manifest.json`).
+//!
+//! The generator writes the manifest, the runner and other tools read it.
+
+pub mod color;
+pub mod denylist;
+pub mod icon;
+pub mod identity;
+pub mod instance;
+pub mod manifest;
+pub mod png;
+pub mod siblings;
+pub mod switch;
+pub mod timer;
+pub mod tray;
+#[cfg(windows)]
+pub mod win32;
+
+pub use color::Color;
+pub use manifest::{
+    AwccSettings, Entry, Handoff, HandoffOrder, Manifest, ManifestError, ProfileOptions, Role, RunnerSettings, Scope,
+    TrayAction,
+};
@@ -0,0 +1,656 @@
+use crate::color::Color;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// File name of the manifest inside the output directory.
+pub const MANIFEST_FILE: &str = "manifest.json";
+/// Schema version written by this build. Readers reject anything newer.
+///
+/// Every struct denies unknown fields, so adding a field must bump this;
+/// otherwise an older runner fails on the field instead of naming the version.
+/// 2: display metadata, options, `runner` settings and the `duration` option.
+pub const MANIFEST_VERSION: u32 = 2;
+
+/// Legacy one-value-per-file layout written by generator 1.0.
+pub const LEGACY_FAMILY_FILE: &str = "family.txt";
+pub const LEGACY_OFF_FILE: &str = "off.txt";
+pub const LEGACY_AWCC_PATH_FILE: &str = "awcc_path.txt";
+pub const LEGACY_AWCC_ARGS_FILE: &str = "awcc_args.txt";
+pub const LEGACY_AWCC_MIN_FILE: &str = "awcc_start_minimized.txt";
+pub const LEGACY_FILES: [&str; 5] = [
+    LEGACY_FAMILY_FILE,
+    LEGACY_OFF_FILE,
+    LEGACY_AWCC_PATH_FILE,
+    LEGACY_AWCC_ARGS_FILE,
+    LEGACY_AWCC_MIN_FILE,
+];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    pub version: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub generator_version: Option<String>,
+    pub profiles: Vec<Entry>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub awcc: Option<AwccSettings>,
+    #[serde(default, skip_serializing_if = "RunnerSettings::is_default")]
+    pub runner: RunnerSettings,
+}
+
+/// Family-wide runner behaviour (`runner:` at the top of configure.yaml v2).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RunnerSettings {
+    /// Extra directories whose EXEs count as siblings, besides the runner's own.
+    /// Relative entries are resolved against the output directory.
+    pub sibling_dirs: Vec<String>,
+    /// Which siblings a runner may stop.
+    pub scope: Scope,
+    /// How long a sibling gets to exit after WM_CLOSE before it is terminated;
+    /// 0 terminates straight away.
+    pub close_timeout_ms: u32,
+    /// How long the off EXE waits for stopped siblings to be gone before reporting.
+    pub off_wait_ms: u32,
+    /// When a newly started profile stops the previous one.
+    #[serde(skip_serializing_if = "Handoff::is_default")]
+    pub handoff: Handoff,
+    /// What a double click on the tray icon does.
+    #[serde(skip_serializing_if = "TrayAction::is_none")]
+    pub double_click: TrayAction,
+}
+
+pub const DEFAULT_CLOSE_TIMEOUT_MS: u32 = 2000;
+pub const DEFAULT_OFF_WAIT_MS: u32 = 5000;
+pub const DEFAULT_OVERLAP_MS: u32 = 500;
+
+/// Ordering of a profile switch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Handoff {
+    pub order: HandoffOrder,
+    /// With [`HandoffOrder::StartFirst`], how long both profiles run side by side.
+    pub overlap_ms: u32,
+}
+
+impl Default for Handoff {
+    fn default() -> Self {
+        Handoff { order: HandoffOrder::StopFirst, overlap_ms: DEFAULT_OVERLAP_MS }
+    }
+}
+
+impl Handoff {
+    pub fn is_default(&self) -> bool {
+        *self == Handoff::default()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HandoffOrder {
+    /// Stop the previous profile first, then bring up the tray (AWCC may briefly
+    /// fall back to System Default in between).
+    #[default]
+    StopFirst,
+    /// Bring up the new profile, keep both running for `overlap_ms`, then stop the
+    /// previous one, so AWCC always sees a matching EXE.
+    StartFirst,
+}
+
+impl Default for RunnerSettings {
+    fn default() -> Self {
+        RunnerSettings {
+            sibling_dirs: Vec::new(),
+            scope: Scope::Session,
+            close_timeout_ms: DEFAULT_CLOSE_TIMEOUT_MS,
+            off_wait_ms: DEFAULT_OFF_WAIT_MS,
+            handoff: Handoff::default(),
+            double_click: TrayAction::None,
+        }
+    }
+}
+
+/// Tray icon action (`runner.double_click`): `none`, `menu`, `off` or a profile name.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum TrayAction {
+    #[default]
+    None,
+    /// Open the tray menu, as a right click does.
+    Menu,
+    /// Switch the lighting off.
+    Off,
+    /// Switch to the named profile.
+    Profile(String),
+}
+
+/// Values of `runner.double_click` that are not profile names.
+pub const TRAY_ACTION_KEYWORDS: [&str; 3] = ["none", "menu", "off"];
+
+impl TrayAction {
+    pub fn is_none(&self) -> bool {
+        *self == TrayAction::None
+    }
+}
+
+impl From<String> for TrayAction {
+    fn from(s: String) -> Self {
+        match s.as_str() {
+            "none" => TrayAction::None,
+            "menu" => TrayAction::Menu,
+            "off" => TrayAction::Off,
+            _ => TrayAction::Profile(s),
+        }
+    }
+}
+
+impl From<TrayAction> for String {
+    fn from(a: TrayAction) -> Self {
+        match a {
+            TrayAction::None => "none".to_string(),
+            TrayAction::Menu => "menu".to_string(),
+            TrayAction::Off => "off".to_string(),
+            TrayAction::Profile(name) => name,
+        }
+    }
+}
+
+/// Reach of sibling stopping.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Scope {
+    /// Only processes in the runner's own logon session.
+    #[default]
+    Session,
+    /// Every session on the machine (needs the rights to open other users' processes).
+    Machine,
+}
+
+impl RunnerSettings {
+    pub fn is_default(&self) -> bool {
+        *self == RunnerSettings::default()
+    }
+}
+
+/// One generated EXE in the family.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Entry {
+    /// Profile name from configure.yaml (the EXE stem).
+    pub name: String,
+    /// File name of the generated EXE inside the output directory.
+    pub exe: String,
+    pub role: Role,
+    /// Friendly name for tooltips and menus (configure.yaml v2).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+    /// Normalised `#rrggbb`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+    /// Icon file relative to the output directory.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub icon: Option<String>,
+    #[serde(default, skip_serializing_if = "ProfileOptions::is_default")]
+    pub options: ProfileOptions,
+}
+
+impl Entry {
+    pub fn new(name: &str, exe: &str, role: Role) -> Entry {
+        Entry {
+            name: name.to_string(),
+            exe: exe.to_string(),
+            role,
+            display_name: None,
+            color: None,
+            description: None,
+            group: None,
+            icon: None,
+            options: ProfileOptions::default(),
+        }
+    }
+
+    /// Name shown to people: `display_name` when set, otherwise the EXE stem.
+    pub fn label(&self) -> &str {
+        self.display_name.as_deref().unwrap_or(&self.name)
+    }
+
+    pub fn parsed_color(&self) -> Option<Color> {
+        self.color.as_deref().and_then(Color::parse)
+    }
+}
+
+/// Per-profile runner behaviour.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProfileOptions {
+    /// Keep a 1x1 topmost window, like `keep_foreground.txt` but for this profile only.
+    pub keep_foreground: bool,
+    /// Start AWCC when it is not running (only if `awcc` is configured).
+    pub start_awcc: bool,
+    /// Go Off by itself after this many seconds (`duration` in configure.yaml).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration_secs: Option<u64>,
+}
+
+impl Default for ProfileOptions {
+    fn default() -> Self {
+        ProfileOptions { keep_foreground: false, start_awcc: true, duration_secs: None }
+    }
+}
+
+impl ProfileOptions {
+    pub fn is_default(&self) -> bool {
+        *self == ProfileOptions::default()
+    }
+
+    pub fn duration(&self) -> Option<Duration> {
+        self.duration_secs.map(Duration::from_secs)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    /// Stays resident so AWCC applies the matching Per Game profile.
+    Profile,
+    /// Stops the profile EXEs and exits immediately.
+    Off,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AwccSettings {
+    pub path: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default = "default_true")]
+    pub start_minimized: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Debug)]
+pub enum ManifestError {
+    Io(PathBuf, io::Error),
+    Json(serde_json::Error),
+    UnsupportedVersion(u32),
+    Invalid(String),
+}
+
+impl fmt::Display for ManifestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ManifestError::Io(path, e) => write!(f, "failed to read {}: {}", path.display(), e),
+            ManifestError::Json(e) => write!(f, "invalid {}: {}", MANIFEST_FILE, e),
+            ManifestError::UnsupportedVersion(v) => write!(
+                f,
+                "unsupported {} version: {} (this build reads up to {})",
+                MANIFEST_FILE, v, MANIFEST_VERSION
+            ),
+            ManifestError::Invalid(msg) => write!(f, "invalid {}: {}", MANIFEST_FILE, msg),
+        }
+    }
+}
+
+impl std::error::Error for ManifestError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ManifestError::Io(_, e) => Some(e),
+            ManifestError::Json(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl Manifest {
+    /// Load the runtime description of `dir`.
+    ///
+    /// Prefers `manifest.json`; falls back to the legacy `*.txt` files so dist
+    /// folders produced by generator 1.0 keep working. `Ok(None)` means the
+    /// directory holds neither.
+    pub fn load(dir: &Path) -> Result<Option<Manifest>, ManifestError> {
+        let path = dir.join(MANIFEST_FILE);
+        match fs::read_to_string(&path) {
+            Ok(text) => Manifest::from_json(&text).map(Some),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Manifest::from_legacy(dir),
+            Err(e) => Err(ManifestError::Io(path, e)),
+        }
+    }
+
+    /// Parse and validate a manifest document.
+    pub fn from_json(text: &str) -> Result<Manifest, ManifestError> {
+        // Check the version first so a newer schema reports as such instead of
+        // as an unknown-field error.
+        let probe: VersionProbe = serde_json::from_str(text).map_err(ManifestError::Json)?;
+        if probe.version == 0 || probe.version > MANIFEST_VERSION {
+            return Err(ManifestError::UnsupportedVersion(probe.version));
+        }
+        let manifest: Manifest = serde_json::from_str(text).map_err(ManifestError::Json)?;
+        manifest.validate()?;
+        Ok(manifest)
+    }
+
+    pub fn to_json(&self) -> String {
+        let mut s = serde_json::to_string_pretty(self).expect("manifest serializes");
+        s.push('\n');
+        s
+    }
+
+    /// Build a manifest from `family.txt`, `off.txt` and `awcc_*.txt`.
+    pub fn from_legacy(dir: &Path) -> Result<Option<Manifest>, ManifestError> {
+        let family = read_optional(&dir.join(LEGACY_FAMILY_FILE))?;
+        let off = read_optional(&dir.join(LEGACY_OFF_FILE))?;
+        if family.is_none() && off.is_none() {
+            return Ok(None);
+        }
+
+        let mut profiles = Vec::new();
+        for exe in family.as_deref().map(list_lines).unwrap_or_default() {
+            profiles.push(Entry::new(exe_stem(&exe), &exe, Role::Profile));
+        }
+        for exe in off.as_deref().map(list_lines).unwrap_or_default() {
+            profiles.push(Entry::new(exe_stem(&exe), &exe, Role::Off));
+        }
+
+        let awcc = match read_optional(&dir.join(LEGACY_AWCC_PATH_FILE))? {
+            Some(raw) => {
+                let path = raw.lines().next().unwrap_or("").trim().to_string();
+                if path.is_empty() {
+                    None
+                } else {
+                    let args = read_optional(&dir.join(LEGACY_AWCC_ARGS_FILE))?
+                        .as_deref()
+                        .map(list_lines)
+                        .unwrap_or_default();
+                    let start_minimized = read_optional(&dir.join(LEGACY_AWCC_MIN_FILE))?
+                        .and_then(|s| s.lines().next().map(|v| v.trim().eq_ignore_ascii_case("true")))
+                        .unwrap_or(true);
+                    Some(AwccSettings { path, args, start_minimized })
+                }
+            }
+            None => None,
+        };
+
+        let manifest = Manifest {
+            version: MANIFEST_VERSION,
+            generator_version: None,
+            profiles,
+            awcc,
+            runner: RunnerSettings::default(),
+        };
+        manifest.validate()?;
+        Ok(Some(manifest))
+    }
+
+    pub fn validate(&self) -> Result<(), ManifestError> {
+        if self.version == 0 || self.version > MANIFEST_VERSION {
+            return Err(ManifestError::UnsupportedVersion(self.version));
+        }
+        let mut seen = HashSet::new();
+        let mut offs = 0;
+        for e in &self.profiles {
+            if e.name.trim().is_empty() {
+                return Err(ManifestError::Invalid("profile with empty name".to_string()));
+            }
+            if e.exe.trim().is_empty() || e.exe.contains(['/', '\\']) {
+                return Err(ManifestError::Invalid(format!(
+                    "profile '{}' has invalid exe name '{}'",
+                    e.name, e.exe
+                )));
+            }
+            if !seen.insert(image_key(&e.exe)) {
+                return Err(ManifestError::Invalid(format!("duplicate exe '{}'", e.exe)));
+            }
+            if let Some(c) = &e.color {
+                if Color::parse(c).is_none() {
+                    return Err(ManifestError::Invalid(format!(
+                        "profile '{}' has invalid color '{}'",
+                        e.name, c
+                    )));
+                }
+            }
+            if let Some(icon) = &e.icon {
+                if Path::new(icon).is_absolute() || icon.split(['/', '\\']).any(|c| c == "..") {
+                    return Err(ManifestError::Invalid(format!(
+                        "profile '{}' icon must stay inside the output directory: '{}'",
+                        e.name, icon
+                    )));
+                }
+            }
+            if e.role == Role::Off {
+                offs += 1;
+            }
+        }
+        if offs > 1 {
+            return Err(ManifestError::Invalid("more than one entry has role 'off'".to_string()));
+        }
+        if let TrayAction::Profile(name) = &self.runner.double_click {
+            if !self.profiles().any(|e| e.name.eq_ignore_ascii_case(name)) {
+                return Err(ManifestError::Invalid(format!("runner.double_click names unknown profile '{}'", name)));
+            }
+        }
+        if let Some(awcc) = &self.awcc {
+            if awcc.path.trim().is_empty() {
+                return Err(ManifestError::Invalid("awcc.path is empty".to_string()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Resident profile entries, in configure.yaml order.
+    pub fn profiles(&self) -> impl Iterator<Item = &Entry> {
+        self.profiles.iter().filter(|e| e.role == Role::Profile)
+    }
+
+    pub fn off(&self) -> Option<&Entry> {
+        self.profiles.iter().find(|e| e.role == Role::Off)
+    }
+
+    /// Look up an entry by EXE file name (case-insensitive, `.exe` optional).
+    pub fn entry_by_exe(&self, exe: &str) -> Option<&Entry> {
+        let key = image_key(exe);
+        self.profiles.iter().find(|e| image_key(&e.exe) == key)
+    }
+
+    /// Look up an entry by profile name (case-insensitive, like the file system).
+    pub fn entry_by_name(&self, name: &str) -> Option<&Entry> {
+        self.profiles.iter().find(|e| e.name.eq_ignore_ascii_case(name.trim()))
+    }
+
+    /// Every EXE file name this manifest manages (profiles and off).
+    pub fn managed_exes(&self) -> impl Iterator<Item = &str> {
+        self.profiles.iter().map(|e| e.exe.as_str())
+    }
+}
+
+#[derive(Deserialize)]
+struct VersionProbe {
+    version: u32,
+}
+
+/// Normalise an EXE name the way Toolhelp reports it: lowercase with `.exe`.
+pub fn image_key(exe: &str) -> String {
+    let mut name = exe.trim().to_ascii_lowercase();
+    if !name.ends_with(".exe") {
+        name.push_str(".exe");
+    }
+    name
+}
+
+fn exe_stem(exe: &str) -> &str {
+    let len = exe.len();
+    if len > 4 && exe[len - 4..].eq_ignore_ascii_case(".exe") {
+        &exe[..len - 4]
+    } else {
+        exe
+    }
+}
+
+fn read_optional(path: &Path) -> Result<Option<String>, ManifestError> {
+    match fs::read_to_string(path) {
+        Ok(s) => Ok(Some(s)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(ManifestError::Io(path.to_path_buf(), e)),
+    }
+}
+
+fn list_lines(text: &str) -> Vec<String> {
+    text.lines()
+        .map(|l| l.trim())
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .map(|l| l.to_string())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    fn sample() -> Manifest {
+        Manifest {
+            version: MANIFEST_VERSION,
+            generator_version: Some("1.0.0".to_string()),
+            profiles: vec![
+                Entry::new("red", "red.exe", Role::Profile),
+                Entry::new("navy", "navy.exe", Role::Profile),
+                Entry::new("off", "off.exe", Role::Off),
+            ],
+            awcc: Some(AwccSettings {
+                path: "C:\\AWCC\\AWCC.exe".to_string(),
+                args: vec!["--tray".to_string()],
+                start_minimized: false,
+            }),
+            runner: RunnerSettings::default(),
+        }
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let m = sample();
+        let parsed = Manifest::from_json(&m.to_json()).unwrap();
+        assert_eq!(parsed, m);
+        assert_eq!(parsed.profiles().count(), 2);
+        assert_eq!(parsed.off().map(|e| e.name.as_str()), Some("off"));
+        assert_eq!(parsed.entry_by_exe("NAVY.EXE").map(|e| e.name.as_str()), Some("navy"));
+        assert_eq!(parsed.entry_by_exe("navy").map(|e| e.name.as_str()), Some("navy"));
+        assert_eq!(parsed.entry_by_name("Navy").map(|e| e.exe.as_str()), Some("navy.exe"));
+        assert!(parsed.entry_by_name("navy.exe").is_none());
+    }
+
+    #[test]
+    fn v2_metadata_round_trips_and_is_omitted_when_unset() {
+        let mut m = sample();
+        assert!(!m.to_json().contains("display_name"));
+        assert!(!m.to_json().contains("options"));
+        assert_eq!(m.profiles[0].label(), "red");
+
+        m.profiles[0].display_name = Some("Meeting red".to_string());
+        m.profiles[0].color = Some("#ff0000".to_string());
+        m.profiles[0].group = Some("Work".to_string());
+        m.profiles[0].icon = Some("icons/red.ico".to_string());
+        m.profiles[0].options.keep_foreground = true;
+        m.runner.sibling_dirs = vec!["D:\\backup\\dist".to_string()];
+        m.runner.scope = Scope::Machine;
+        m.runner.close_timeout_ms = 500;
+        m.runner.handoff = Handoff { order: HandoffOrder::StartFirst, overlap_ms: 750 };
+        assert!(!m.to_json().contains("double_click"));
+        m.runner.double_click = TrayAction::Profile("navy".to_string());
+        assert!(m.to_json().contains(r#""double_click": "navy""#));
+        assert!(m.to_json().contains(r#""order": "start_first""#));
+        assert!(m.to_json().contains(r#""scope": "machine""#));
+        let parsed = Manifest::from_json(&m.to_json()).unwrap();
+        assert_eq!(parsed, m);
+        assert_eq!(parsed.profiles[0].label(), "Meeting red");
+        assert_eq!(parsed.profiles[0].parsed_color(), Some(Color::rgb(255, 0, 0)));
+        assert!(parsed.profiles[0].options.start_awcc);
+
+        m.runner.double_click = TrayAction::Off;
+        assert_eq!(Manifest::from_json(&m.to_json()).unwrap().runner.double_click, TrayAction::Off);
+        m.runner.double_click = TrayAction::Profile("blue".to_string());
+        assert!(m.validate().is_err());
+        m.runner.double_click = TrayAction::Menu;
+
+        m.profiles[0].color = Some("not-a-colour".to_string());
+        assert!(m.validate().is_err());
+        m.profiles[0].color = None;
+        m.profiles[0].icon = Some("../red.ico".to_string());
+        assert!(m.validate().is_err());
+    }
+
+    #[test]
+    fn reads_version_1_documents() {
+        let v1 = r#"{"version": 1, "profiles": [{"name": "red", "exe": "red.exe", "role": "profile"}]}"#;
+        let m = Manifest::from_json(v1).unwrap();
+        assert_eq!(m.version, 1);
+        assert!(m.runner.is_default());
+        assert!(m.to_json().contains("\"version\": 1"), "re-serialises as read");
+        // Fields added since version 1 reach an old reader only with the version that names them
+        let v2 = sample().to_json();
+        assert!(v2.contains(&format!("\"version\": {}", MANIFEST_VERSION)));
+        assert_eq!(MANIFEST_VERSION, 2);
+    }
+
+    #[test]
+    fn rejects_newer_version_and_bad_entries() {
+        let newer = r#"{"version": 99, "profiles": [], "future_field": true}"#;
+        assert!(matches!(Manifest::from_json(newer), Err(ManifestError::UnsupportedVersion(99))));
+
+        let mut dup = sample();
+        dup.profiles[1].exe = "RED.exe".to_string();
+        assert!(matches!(Manifest::from_json(&dup.to_json()), Err(ManifestError::Invalid(_))));
+
+        let mut two_offs = sample();
+        two_offs.profiles[0].role = Role::Off;
+        assert!(matches!(two_offs.validate(), Err(ManifestError::Invalid(_))));
+
+        let mut path_exe = sample();
+        path_exe.profiles[0].exe = "..\\red.exe".to_string();
+        assert!(path_exe.validate().is_err());
+
+        assert!(matches!(Manifest::from_json("{ not json"), Err(ManifestError::Json(_))));
+    }
+
+    #[test]
+    fn load_prefers_manifest_and_falls_back_to_legacy() {
+        let dir = unique_temp_dir();
+        assert!(Manifest::load(&dir).unwrap().is_none());
+
+        fs::write(dir.join(LEGACY_FAMILY_FILE), "# comment\nred.exe\n navy.exe \n").unwrap();
+        fs::write(dir.join(LEGACY_OFF_FILE), "off.exe\n").unwrap();
+        fs::write(dir.join(LEGACY_AWCC_PATH_FILE), "C:\\AWCC\\AWCC.exe\n").unwrap();
+        fs::write(dir.join(LEGACY_AWCC_ARGS_FILE), "--tray\n").unwrap();
+        fs::write(dir.join(LEGACY_AWCC_MIN_FILE), "false\n").unwrap();
+        let legacy = Manifest::load(&dir).unwrap().unwrap();
+        assert_eq!(legacy.generator_version, None);
+        assert_eq!(legacy.profiles, sample().profiles);
+        assert_eq!(legacy.awcc, sample().awcc);
+
+        let mut m = sample();
+        m.profiles.truncate(1);
+        fs::write(dir.join(MANIFEST_FILE), m.to_json()).unwrap();
+        assert_eq!(Manifest::load(&dir).unwrap().unwrap(), m);
+
+        fs::write(dir.join(MANIFEST_FILE), "{}").unwrap();
+        assert!(Manifest::load(&dir).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    fn unique_temp_dir() -> PathBuf {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos();
+        let path = std::env::temp_dir().join(format!("awcc-family-test-{}", nanos));
+        fs::create_dir_all(&path).unwrap();
+        path
+    }
+}
@@ -0,0 +1,207 @@
+//! PNG and ICO writers for generated icons.
+//!
+//! Image data goes into stored (uncompressed) deflate blocks: icons are small,
+//! and the output depends on nothing but the pixels.
+
+/// CRC-32 (IEEE), as used by PNG and zip.
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &b in data {
+        crc ^= b as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &x in data {
+        a = (a + x as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    b << 16 | a
+}
+
+/// zlib stream of `data` in stored blocks.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let mut blocks = data.chunks(0xffff).peekable();
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        out.push(blocks.peek().is_none() as u8);
+        let len = block.len() as u16;
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+fn chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = out.len();
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    let crc = crc32(&out[start..]);
+    out.extend_from_slice(&crc.to_be_bytes());
+}
+
+/// PNG of a `width` × `height` image given as RGBA rows, top row first.
+pub fn encode(width: u32, height: u32, rgba: &[u8]) -> Vec<u8> {
+    assert_eq!(rgba.len(), width as usize * height as usize * 4, "RGBA buffer size");
+    let mut out = b"\x89PNG\r\n\x1a\n".to_vec();
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    header.extend_from_slice(&[8, 6, 0, 0, 0]); // 8-bit RGBA, no interlace
+    chunk(&mut out, b"IHDR", &header);
+
+    let mut raw = Vec::with_capacity(rgba.len() + height as usize);
+    for row in rgba.chunks(width as usize * 4) {
+        raw.push(0); // filter: none
+        raw.extend_from_slice(row);
+    }
+    chunk(&mut out, b"IDAT", &zlib_stored(&raw));
+    chunk(&mut out, b"IEND", &[]);
+    out
+}
+
+/// Icon image in the classic DIB form: a 32-bit BITMAPINFOHEADER (height doubled
+/// for the mask), BGRA rows bottom-up, then an all-zero AND mask so alpha decides.
+/// Every icon reader takes these; PNG entries are only safe at 256 pixels.
+pub fn dib(size: u32, rgba: &[u8]) -> Vec<u8> {
+    assert_eq!(rgba.len(), size as usize * size as usize * 4, "RGBA buffer size");
+    let mask_row = (size as usize).div_ceil(32) * 4;
+    let mut out = Vec::with_capacity(40 + rgba.len() + mask_row * size as usize);
+    out.extend_from_slice(&40u32.to_le_bytes());
+    out.extend_from_slice(&size.to_le_bytes());
+    out.extend_from_slice(&(size * 2).to_le_bytes());
+    out.extend_from_slice(&1u16.to_le_bytes()); // planes
+    out.extend_from_slice(&32u16.to_le_bytes()); // bits per pixel
+    out.extend_from_slice(&0u32.to_le_bytes()); // BI_RGB
+    out.extend_from_slice(&(rgba.len() as u32).to_le_bytes());
+    out.extend_from_slice(&[0; 16]); // resolution and palette
+    for row in rgba.chunks(size as usize * 4).rev() {
+        for px in row.chunks(4) {
+            out.extend_from_slice(&[px[2], px[1], px[0], px[3]]);
+        }
+    }
+    out.resize(out.len() + mask_row * size as usize, 0);
+    out
+}
+
+/// ICO holding the given square images, each PNG or [`dib`].
+pub fn ico(images: &[(u32, Vec<u8>)]) -> Vec<u8> {
+    let mut out = vec![0, 0, 1, 0];
+    out.extend_from_slice(&(images.len() as u16).to_le_bytes());
+    let mut offset = 6 + 16 * images.len() as u32;
+    for (size, png) in images {
+        let dim = if *size >= 256 { 0 } else { *size as u8 }; // 0 means 256
+        out.extend_from_slice(&[dim, dim, 0, 0]);
+        out.extend_from_slice(&1u16.to_le_bytes()); // planes
+        out.extend_from_slice(&32u16.to_le_bytes()); // bits per pixel
+        out.extend_from_slice(&(png.len() as u32).to_le_bytes());
+        out.extend_from_slice(&offset.to_le_bytes());
+        offset += png.len() as u32;
+    }
+    for (_, png) in images {
+        out.extend_from_slice(png);
+    }
+    out
+}
+
+#[cfg(test)]
+pub(crate) mod read {
+    //! Just enough PNG reading to check what [`encode`](super::encode) wrote.
+
+    use super::*;
+
+    /// Width, height and RGBA pixels, checking every CRC and the Adler-32.
+    pub fn decode(png: &[u8]) -> (u32, u32, Vec<u8>) {
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        let (mut at, mut size, mut zlib) = (8, (0, 0), Vec::new());
+        loop {
+            let len = u32::from_be_bytes(png[at..at + 4].try_into().unwrap()) as usize;
+            let body = &png[at + 4..at + 8 + len];
+            let crc = u32::from_be_bytes(png[at + 8 + len..at + 12 + len].try_into().unwrap());
+            assert_eq!(crc32(body), crc, "chunk CRC");
+            let (kind, data) = body.split_at(4);
+            match kind {
+                b"IHDR" => {
+                    size = (u32::from_be_bytes(data[..4].try_into().unwrap()), u32::from_be_bytes(data[4..8].try_into().unwrap()));
+                    assert_eq!(&data[8..], &[8, 6, 0, 0, 0]);
+                }
+                b"IDAT" => zlib.extend_from_slice(data),
+                b"IEND" => break,
+                _ => {}
+            }
+            at += 12 + len;
+        }
+        let mut raw = Vec::new();
+        let mut at = 2;
+        loop {
+            let last = zlib[at] & 1;
+            assert_eq!(zlib[at] & 6, 0, "stored block");
+            let len = u16::from_le_bytes([zlib[at + 1], zlib[at + 2]]) as usize;
+            raw.extend_from_slice(&zlib[at + 5..at + 5 + len]);
+            at += 5 + len;
+            if last == 1 {
+                break;
+            }
+        }
+        assert_eq!(u32::from_be_bytes(zlib[at..at + 4].try_into().unwrap()), adler32(&raw));
+        let rgba = raw.chunks(size.0 as usize * 4 + 1).flat_map(|row| row[1..].to_vec()).collect();
+        (size.0, size.1, rgba)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checksums_match_reference_values() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+        assert_eq!(zlib_stored(b""), [0x78, 0x01, 1, 0, 0, 0xff, 0xff, 0, 0, 0, 1]);
+    }
+
+    #[test]
+    fn png_round_trips_through_several_blocks() {
+        // 200 × 100 RGBA is more than one 64 KiB stored block
+        let rgba: Vec<u8> = (0..200 * 100 * 4).map(|i| (i * 7 % 251) as u8).collect();
+        let png = encode(200, 100, &rgba);
+        assert_eq!(read::decode(&png), (200, 100, rgba));
+    }
+
+    #[test]
+    fn dib_rows_run_bottom_up_in_bgra() {
+        let mut rgba = vec![0u8; 2 * 2 * 4];
+        rgba[..4].copy_from_slice(&[1, 2, 3, 4]); // top-left
+        let dib = dib(2, &rgba);
+        assert_eq!(dib.len(), 40 + 16 + 2 * 4);
+        assert_eq!(i32::from_le_bytes(dib[8..12].try_into().unwrap()), 4, "height counts the mask");
+        assert_eq!(&dib[40 + 8..40 + 12], &[3, 2, 1, 4], "top row is stored last");
+    }
+
+    #[test]
+    fn ico_directory_points_at_each_png() {
+        let small = encode(16, 16, &[0; 16 * 16 * 4]);
+        let large = encode(256, 256, &vec![255; 256 * 256 * 4]);
+        let ico = ico(&[(16, small.clone()), (256, large.clone())]);
+        assert_eq!(&ico[..6], &[0, 0, 1, 0, 2, 0]);
+        let entry = |i: usize| &ico[6 + 16 * i..6 + 16 * (i + 1)];
+        assert_eq!(entry(0)[0], 16);
+        assert_eq!(entry(1)[0], 0, "256 is written as 0");
+        for (i, png) in [small, large].iter().enumerate() {
+            let size = u32::from_le_bytes(entry(i)[8..12].try_into().unwrap()) as usize;
+            let offset = u32::from_le_bytes(entry(i)[12..16].try_into().unwrap()) as usize;
+            assert_eq!(&ico[offset..offset + size], png.as_slice());
+        }
+    }
+}
@@ -0,0 +1,814 @@
+//! Which running processes count as siblings of this runner, and how they are stopped.
+//!
+//! Matching by image name alone would also hit an unrelated `navy.exe` elsewhere on
+//! the machine, so a candidate must also live in one of the family's directories,
+//! and its creation time must be unchanged between the snapshot and the handle we
+//! terminate through (a reused PID is never killed). Unless the manifest asks for
+//! [`Scope::Machine`], only processes in our own logon session are touched, so
+//! another user's runner on a shared PC is left alone. The OS access goes through
+//! [`ProcessTable`] so the rules can be tested with a fake table.
+//!
+//! Siblings are first asked to close (WM_CLOSE to their hidden runner window) so
+//! they remove their tray icon on the way out; only those still running after
+//! `close_timeout_ms` are terminated.
+
+use crate::denylist;
+use crate::manifest::{image_key, Manifest, Scope};
+use std::collections::HashSet;
+use std::fmt;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// Window class of the runner's hidden main window; WM_CLOSE to it shuts a runner down cleanly.
+pub const RUNNER_WINDOW_CLASS: &str = "AwccCtrlRunnerHiddenWindow";
+
+/// One row of the process list (Toolhelp `PROCESSENTRY32W`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProcessEntry {
+    pub pid: u32,
+    pub image_name: String,
+}
+
+/// Details read through a query handle.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProcessDetails {
+    /// Full Win32 image path.
+    pub path: String,
+    /// Creation time (FILETIME ticks); identifies the process across PID reuse.
+    pub created: u64,
+    /// Terminal Services session ID, if it could be read.
+    pub session: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpenError {
+    AccessDenied,
+    /// The process exited (or the PID is no longer valid).
+    Gone,
+    Other,
+}
+
+pub trait ProcessTable {
+    type Handle;
+
+    fn list(&mut self) -> Vec<ProcessEntry>;
+    fn query(&mut self, pid: u32) -> Option<ProcessDetails>;
+    /// Open `pid` with the rights needed to verify and terminate it.
+    fn open(&mut self, pid: u32) -> Result<Self::Handle, OpenError>;
+    fn creation_time(&mut self, handle: &Self::Handle) -> Option<u64>;
+    /// Post a close request to `pid`'s runner window; false if it has none.
+    fn request_close(&mut self, pid: u32) -> bool;
+    /// Wait up to `timeout` for the process to exit; true if it did.
+    fn wait_exit(&mut self, handle: &Self::Handle, timeout: Duration) -> bool;
+    fn terminate(&mut self, handle: &Self::Handle) -> bool;
+}
+
+/// Who we are and where siblings may live.
+#[derive(Debug, Clone)]
+pub struct SiblingRules {
+    /// Image keys (`lowercase.exe`) of the family, excluding ourselves.
+    pub targets: HashSet<String>,
+    pub self_pid: u32,
+    /// Directory keys (see [`dir_key`]) a sibling's image must be in.
+    pub dirs: Vec<String>,
+    pub scope: Scope,
+    /// Our own session ID; `None` if unknown, in which case session scope stops nothing.
+    pub self_session: Option<u32>,
+    /// Our own creation time. Siblings started after us are a newer switch request
+    /// and are left alone; they will stop us instead.
+    pub self_created: Option<u64>,
+    /// Grace period after WM_CLOSE; zero skips the close request.
+    pub close_timeout: Duration,
+    /// How long to wait for terminated siblings to actually exit; zero does not wait
+    /// (only the off EXE needs the confirmation).
+    pub exit_wait: Duration,
+}
+
+impl SiblingRules {
+    /// Rules for the EXE `self_exe` (file name) running as `self_pid` from `self_dir`.
+    /// `extra_dirs` are the resolved `runner.sibling_dirs` from the manifest.
+    pub fn new(manifest: &Manifest, self_exe: &str, self_pid: u32, self_dir: &str, extra_dirs: &[String]) -> Self {
+        let me = image_key(self_exe);
+        let targets = manifest
+            .profiles()
+            .map(|e| image_key(&e.exe))
+            .filter(|k| *k != me && !denylist::is_system_critical(k))
+            .collect();
+        let mut dirs = vec![dir_key(self_dir)];
+        for d in extra_dirs {
+            let key = dir_key(d);
+            if !dirs.contains(&key) {
+                dirs.push(key);
+            }
+        }
+        SiblingRules {
+            targets,
+            self_pid,
+            dirs,
+            scope: manifest.runner.scope,
+            self_session: None,
+            self_created: None,
+            close_timeout: Duration::from_millis(manifest.runner.close_timeout_ms as u64),
+            exit_wait: Duration::ZERO,
+        }
+    }
+
+    /// [`SiblingRules::new`] for an EXE in the dist folder `dir`, resolving
+    /// `runner.sibling_dirs` relative to it.
+    pub fn for_dir(manifest: &Manifest, self_exe: &str, self_pid: u32, dir: &Path) -> Self {
+        let base = dir.to_string_lossy();
+        let extra_dirs: Vec<String> = manifest
+            .runner
+            .sibling_dirs
+            .iter()
+            .map(|d| if is_absolute(d) { d.clone() } else { format!("{}\\{}", base, d) })
+            .collect();
+        SiblingRules::new(manifest, self_exe, self_pid, &base, &extra_dirs)
+    }
+
+    /// Set our own session ID (used with [`Scope::Session`]).
+    pub fn with_session(mut self, session: Option<u32>) -> Self {
+        self.self_session = session;
+        self
+    }
+
+    /// Set our own creation time (see [`SiblingRules::self_created`]).
+    pub fn with_created(mut self, created: Option<u64>) -> Self {
+        self.self_created = created;
+        self
+    }
+
+    pub fn matches_name(&self, entry: &ProcessEntry) -> bool {
+        entry.pid != self.self_pid && self.targets.contains(&image_key(&entry.image_name))
+    }
+}
+
+/// Everything the kill decision looks at for one running process.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Candidate<'a> {
+    pub pid: u32,
+    pub image_name: &'a str,
+    /// Full image path; `None` if it could not be read.
+    pub path: Option<&'a str>,
+    pub session: Option<u32>,
+    /// Creation time (FILETIME ticks), if known.
+    pub created: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Decision {
+    Stop,
+    Skip(SkipReason),
+    /// Not a sibling at all (ourselves, or a name outside the family); not reported.
+    Ignore,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SkipReason {
+    /// Image path could not be read (typically an elevated or protected process).
+    PathUnknown,
+    /// Same name, but the image lives outside the family directories.
+    OutsideFamilyDirs(String),
+    /// Running in another logon session (another user, or fast user switching).
+    OtherSession(u32),
+    /// Session ID of the candidate or of ourselves could not be read.
+    SessionUnknown,
+    /// Started after us: a newer switch request that wins over ours.
+    Newer,
+    /// The PID now belongs to a different process than the one in the snapshot.
+    PidReused,
+}
+
+/// Decide whether `c` is one of our siblings and may be stopped.
+pub fn classify(c: &Candidate<'_>, rules: &SiblingRules) -> Decision {
+    if c.pid == rules.self_pid || !rules.targets.contains(&image_key(c.image_name)) {
+        return Decision::Ignore;
+    }
+    let Some(path) = c.path else {
+        return Decision::Skip(SkipReason::PathUnknown);
+    };
+    if rules.scope == Scope::Session {
+        match (c.session, rules.self_session) {
+            (Some(theirs), Some(ours)) if theirs != ours => return Decision::Skip(SkipReason::OtherSession(theirs)),
+            (Some(_), Some(_)) => {}
+            _ => return Decision::Skip(SkipReason::SessionUnknown),
+        }
+    }
+    if !rules.dirs.contains(&parent_key(path)) {
+        return Decision::Skip(SkipReason::OutsideFamilyDirs(path.to_string()));
+    }
+    match (c.created, rules.self_created) {
+        (Some(theirs), Some(ours)) if theirs > ours => Decision::Skip(SkipReason::Newer),
+        _ => Decision::Stop,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Outcome {
+    pub pid: u32,
+    pub image_name: String,
+    pub action: Action,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    /// Exited on its own after WM_CLOSE.
+    Closed,
+    Terminated(Forced),
+    Skipped(SkipReason),
+    OpenFailed(OpenError),
+    TerminateFailed,
+    /// Terminated, but still running when `exit_wait` ran out.
+    Survived,
+}
+
+/// Why a sibling had to be terminated rather than closed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Forced {
+    /// `close_timeout_ms` is 0.
+    CloseDisabled,
+    /// No `AwccCtrlRunnerHiddenWindow` found for the process.
+    NoWindow,
+    /// Still running when the grace period (in ms) ran out.
+    TimedOut(u128),
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (pid {}): ", self.image_name, self.pid)?;
+        match &self.action {
+            Action::Closed => write!(f, "closed after WM_CLOSE"),
+            Action::Terminated(Forced::CloseDisabled) => write!(f, "terminated"),
+            Action::Terminated(Forced::NoWindow) => write!(f, "terminated, no runner window to close"),
+            Action::Terminated(Forced::TimedOut(ms)) => write!(f, "terminated, still running {} ms after WM_CLOSE", ms),
+            Action::Skipped(SkipReason::PathUnknown) => write!(f, "skipped, image path unavailable"),
+            Action::Skipped(SkipReason::OutsideFamilyDirs(p)) => write!(f, "skipped, not in family directory ({})", p),
+            Action::Skipped(SkipReason::OtherSession(s)) => write!(f, "skipped, belongs to session {}", s),
+            Action::Skipped(SkipReason::SessionUnknown) => write!(f, "skipped, session unknown"),
+            Action::Skipped(SkipReason::Newer) => write!(f, "skipped, started after us"),
+            Action::Skipped(SkipReason::PidReused) => write!(f, "skipped, pid was reused"),
+            Action::OpenFailed(e) => write!(f, "OpenProcess failed ({:?})", e),
+            Action::TerminateFailed => write!(f, "TerminateProcess failed"),
+            Action::Survived => write!(f, "terminated but still running"),
+        }
+    }
+}
+
+/// A running family member, as [`find_siblings`] reports it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sibling {
+    pub pid: u32,
+    pub image_name: String,
+    pub created: Option<u64>,
+}
+
+/// The processes [`stop_siblings`] would stop with the same `rules`, oldest first.
+/// Nothing is opened or signalled.
+pub fn find_siblings<T: ProcessTable>(table: &mut T, rules: &SiblingRules) -> Vec<Sibling> {
+    let mut found = Vec::new();
+    for entry in table.list().into_iter().filter(|e| rules.matches_name(e)) {
+        let details = table.query(entry.pid);
+        let candidate = Candidate {
+            pid: entry.pid,
+            image_name: &entry.image_name,
+            path: details.as_ref().map(|d| d.path.as_str()),
+            session: details.as_ref().and_then(|d| d.session),
+            created: details.as_ref().map(|d| d.created),
+        };
+        if classify(&candidate, rules) == Decision::Stop {
+            found.push(Sibling { pid: entry.pid, created: candidate.created, image_name: entry.image_name });
+        }
+    }
+    found.sort_by_key(|s| (s.created, s.pid));
+    found
+}
+
+/// Stop every sibling matching `rules`; returns one outcome per name match.
+///
+/// All verified siblings are asked to close first and then waited on against a
+/// single deadline, so several siblings cost at most one grace period.
+pub fn stop_siblings<T: ProcessTable>(table: &mut T, rules: &SiblingRules) -> Vec<Outcome> {
+    if rules.targets.is_empty() {
+        return Vec::new();
+    }
+    let candidates: Vec<ProcessEntry> = table.list().into_iter().filter(|e| rules.matches_name(e)).collect();
+    let mut outcomes = Vec::new();
+    // (index into outcomes, handle, close requested)
+    let mut pending = Vec::new();
+    for entry in candidates {
+        let details = table.query(entry.pid);
+        let candidate = Candidate {
+            pid: entry.pid,
+            image_name: &entry.image_name,
+            path: details.as_ref().map(|d| d.path.as_str()),
+            session: details.as_ref().and_then(|d| d.session),
+            created: details.as_ref().map(|d| d.created),
+        };
+        let action = match classify(&candidate, rules) {
+            Decision::Ignore => continue,
+            Decision::Skip(reason) => Action::Skipped(reason),
+            Decision::Stop => {
+                let created = details.map(|d| d.created);
+                match table.open(entry.pid) {
+                    Err(e) => Action::OpenFailed(e),
+                    Ok(h) if table.creation_time(&h) != created => Action::Skipped(SkipReason::PidReused),
+                    Ok(h) => {
+                        let closing = !rules.close_timeout.is_zero() && table.request_close(entry.pid);
+                        pending.push((outcomes.len(), h, closing));
+                        // Placeholder, settled below
+                        Action::TerminateFailed
+                    }
+                }
+            }
+        };
+        outcomes.push(Outcome { pid: entry.pid, image_name: entry.image_name, action });
+    }
+
+    let deadline = Instant::now() + rules.close_timeout;
+    let mut terminated = Vec::new();
+    for (i, h, closing) in pending {
+        let forced = if rules.close_timeout.is_zero() {
+            Forced::CloseDisabled
+        } else if !closing {
+            Forced::NoWindow
+        } else if table.wait_exit(&h, deadline.saturating_duration_since(Instant::now())) {
+            outcomes[i].action = Action::Closed;
+            continue;
+        } else {
+            Forced::TimedOut(rules.close_timeout.as_millis())
+        };
+        if table.terminate(&h) {
+            outcomes[i].action = Action::Terminated(forced);
+            terminated.push((i, h));
+        } else {
+            outcomes[i].action = Action::TerminateFailed;
+        }
+    }
+
+    // TerminateProcess only starts the teardown; confirm it finished if asked to
+    if !rules.exit_wait.is_zero() {
+        let deadline = Instant::now() + rules.exit_wait;
+        for (i, h) in terminated {
+            if !table.wait_exit(&h, deadline.saturating_duration_since(Instant::now())) {
+                outcomes[i].action = Action::Survived;
+            }
+        }
+    }
+    outcomes
+}
+
+/// Overall result of the off EXE, reported as its exit code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OffStatus {
+    /// Every sibling exited.
+    AllStopped,
+    /// No sibling of ours was running.
+    NothingRunning,
+    /// At least one sibling is still running.
+    SomeSurvived,
+    /// At least one sibling could not be opened for lack of rights.
+    AccessDenied,
+    /// A newer switch request arrived first; nothing was stopped.
+    Superseded,
+}
+
+impl OffStatus {
+    pub fn from_outcomes(outcomes: &[Outcome]) -> Self {
+        let mut stopped = false;
+        let mut survived = false;
+        for o in outcomes {
+            match o.action {
+                Action::Closed | Action::Terminated(_) | Action::OpenFailed(OpenError::Gone) => stopped = true,
+                Action::OpenFailed(OpenError::AccessDenied) => return OffStatus::AccessDenied,
+                Action::OpenFailed(OpenError::Other) | Action::TerminateFailed | Action::Survived => survived = true,
+                // Not provably ours (other session, other directory, reused PID)
+                Action::Skipped(_) => {}
+            }
+        }
+        match (stopped, survived) {
+            (_, true) => OffStatus::SomeSurvived,
+            (true, false) => OffStatus::AllStopped,
+            (false, false) => OffStatus::NothingRunning,
+        }
+    }
+
+    /// Process exit code; 1 is left to startup errors.
+    pub fn exit_code(self) -> i32 {
+        match self {
+            OffStatus::AllStopped => 0,
+            OffStatus::NothingRunning => 2,
+            OffStatus::SomeSurvived => 3,
+            OffStatus::AccessDenied => 4,
+            OffStatus::Superseded => 5,
+        }
+    }
+
+    /// Inverse of [`OffStatus::exit_code`], for tools that run the off EXE.
+    pub fn from_exit_code(code: i32) -> Option<Self> {
+        [
+            OffStatus::AllStopped,
+            OffStatus::NothingRunning,
+            OffStatus::SomeSurvived,
+            OffStatus::AccessDenied,
+            OffStatus::Superseded,
+        ]
+        .into_iter()
+        .find(|s| s.exit_code() == code)
+    }
+}
+
+impl fmt::Display for OffStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            OffStatus::AllStopped => "all siblings stopped",
+            OffStatus::NothingRunning => "nothing was running",
+            OffStatus::SomeSurvived => "some siblings are still running",
+            OffStatus::AccessDenied => "access denied",
+            OffStatus::Superseded => "superseded by a newer switch",
+        })
+    }
+}
+
+/// Normalise a Windows directory path for comparison: lowercase, `\` separators,
+/// no `\\?\` prefix, `.` and `..` resolved and no trailing separator.
+pub fn dir_key(dir: &str) -> String {
+    let mut s = dir.trim().replace('/', "\\").to_ascii_lowercase();
+    if let Some(rest) = s.strip_prefix("\\\\?\\") {
+        s = rest.to_string();
+    }
+    // Drive (`c:`), rooted (`\dir`) or UNC (`\\server\share`) prefix, which `..` cannot leave
+    let root = if s.starts_with("\\\\") {
+        4
+    } else if s.starts_with('\\') || s.as_bytes().get(1) == Some(&b':') {
+        1
+    } else {
+        0
+    };
+    let mut parts: Vec<&str> = Vec::new();
+    for (i, part) in s.split('\\').enumerate() {
+        match part {
+            _ if i < root => parts.push(part),
+            "" | "." => {}
+            ".." => {
+                if parts.len() > root {
+                    parts.pop();
+                }
+            }
+            _ => parts.push(part),
+        }
+    }
+    match parts.join("\\") {
+        joined if joined.is_empty() && root > 0 => "\\".to_string(),
+        joined => joined,
+    }
+}
+
+/// Whether a `runner.sibling_dirs` entry names its own location rather than
+/// one relative to the dist folder.
+fn is_absolute(dir: &str) -> bool {
+    dir.starts_with(['\\', '/']) || dir.as_bytes().get(1) == Some(&b':')
+}
+
+/// [`dir_key`] of the directory containing `path`.
+pub fn parent_key(path: &str) -> String {
+    let key = dir_key(path);
+    match key.rfind('\\') {
+        Some(i) => key[..i].to_string(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod fake {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[derive(Debug, Clone)]
+    pub struct FakeProc {
+        pub name: String,
+        pub path: Option<String>,
+        pub created: u64,
+        pub session: u32,
+        pub deny_open: bool,
+        pub window: Window,
+        /// Survives TerminateProcess (e.g. stuck in a driver call).
+        pub lingers: bool,
+    }
+
+    /// How a fake process reacts to a close request.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Window {
+        None,
+        Closes,
+        Hangs,
+    }
+
+    /// In-memory process table. `reuse_on_open` replaces a PID's process between
+    /// `query` and `open`, the way Windows may recycle an exited PID.
+    #[derive(Debug, Default)]
+    pub struct FakeTable {
+        pub procs: HashMap<u32, FakeProc>,
+        pub reuse_on_open: HashSet<u32>,
+        pub close_requested: Vec<u32>,
+        pub closed: Vec<u32>,
+        pub terminated: Vec<u32>,
+    }
+
+    impl FakeTable {
+        pub fn add(&mut self, pid: u32, name: &str, path: Option<&str>, created: u64) -> &mut FakeProc {
+            self.procs.insert(
+                pid,
+                FakeProc { name: name.to_string(), path: path.map(|p| p.to_string()), created, session: 1, deny_open: false, window: Window::Closes, lingers: false },
+            );
+            self.procs.get_mut(&pid).unwrap()
+        }
+    }
+
+    impl ProcessTable for FakeTable {
+        type Handle = u32;
+
+        fn list(&mut self) -> Vec<ProcessEntry> {
+            let mut v: Vec<ProcessEntry> =
+                self.procs.iter().map(|(pid, p)| ProcessEntry { pid: *pid, image_name: p.name.clone() }).collect();
+            v.sort_by_key(|e| e.pid);
+            v
+        }
+
+        fn query(&mut self, pid: u32) -> Option<ProcessDetails> {
+            let p = self.procs.get(&pid)?;
+            Some(ProcessDetails { path: p.path.clone()?, created: p.created, session: Some(p.session) })
+        }
+
+        fn open(&mut self, pid: u32) -> Result<u32, OpenError> {
+            if self.reuse_on_open.contains(&pid) {
+                let p = self.procs.get_mut(&pid).ok_or(OpenError::Gone)?;
+                p.created += 1;
+            }
+            match self.procs.get(&pid) {
+                None => Err(OpenError::Gone),
+                Some(p) if p.deny_open => Err(OpenError::AccessDenied),
+                Some(_) => Ok(pid),
+            }
+        }
+
+        fn creation_time(&mut self, handle: &u32) -> Option<u64> {
+            self.procs.get(handle).map(|p| p.created)
+        }
+
+        fn request_close(&mut self, pid: u32) -> bool {
+            match self.procs.get(&pid) {
+                Some(p) if p.window != Window::None => {
+                    self.close_requested.push(pid);
+                    true
+                }
+                _ => false,
+            }
+        }
+
+        fn wait_exit(&mut self, handle: &u32, _timeout: Duration) -> bool {
+            let closes = self.procs.get(handle).is_some_and(|p| p.window == Window::Closes);
+            if closes && self.close_requested.contains(handle) {
+                self.procs.remove(handle);
+                self.closed.push(*handle);
+            }
+            !self.procs.contains_key(handle)
+        }
+
+        fn terminate(&mut self, handle: &u32) -> bool {
+            let Some(p) = self.procs.get(handle) else { return false };
+            if !p.lingers {
+                self.procs.remove(handle);
+            }
+            self.terminated.push(*handle);
+            true
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::fake::{FakeTable, Window};
+    use super::*;
+    use crate::manifest::{Entry, Role, MANIFEST_VERSION};
+
+    const DIST: &str = "C:\\Tools\\awcc\\dist";
+
+    fn manifest() -> Manifest {
+        Manifest {
+            version: MANIFEST_VERSION,
+            generator_version: None,
+            profiles: vec![
+                Entry::new("red", "red.exe", Role::Profile),
+                Entry::new("navy", "navy.exe", Role::Profile),
+                Entry::new("white", "white.exe", Role::Profile),
+                Entry::new("off", "off.exe", Role::Off),
+            ],
+            awcc: None,
+            runner: Default::default(),
+        }
+    }
+
+    fn rules(extra: &[&str]) -> SiblingRules {
+        let extra: Vec<String> = extra.iter().map(|s| s.to_string()).collect();
+        SiblingRules::new(&manifest(), "red.exe", 100, DIST, &extra).with_session(Some(1))
+    }
+
+    fn candidate<'a>(pid: u32, name: &'a str, session: Option<u32>) -> Candidate<'a> {
+        Candidate { pid, image_name: name, path: Some("C:\\Tools\\awcc\\dist\\x.exe"), session, created: None }
+    }
+
+    #[test]
+    fn find_reports_what_stop_would_stop() {
+        let mut table = FakeTable::default();
+        table.add(200, "navy.exe", Some("C:\\Tools\\awcc\\dist\\navy.exe"), 9);
+        table.add(201, "white.exe", Some("C:\\Tools\\awcc\\dist\\white.exe"), 3);
+        table.add(202, "white.exe", Some("D:\\Games\\white.exe"), 1);
+        table.add(203, "navy.exe", Some("C:\\Tools\\awcc\\dist\\navy.exe"), 4).session = 2;
+
+        let found = find_siblings(&mut table, &rules(&[]));
+        let pids: Vec<u32> = found.iter().map(|s| s.pid).collect();
+        assert_eq!(pids, [201, 200]);
+        assert_eq!(found[0], Sibling { pid: 201, image_name: "white.exe".to_string(), created: Some(3) });
+        assert!(table.close_requested.is_empty() && table.terminated.is_empty());
+
+        let stopped: Vec<u32> = stop_siblings(&mut table, &rules(&[]))
+            .into_iter()
+            .filter(|o| !matches!(o.action, Action::Skipped(_)))
+            .map(|o| o.pid)
+            .collect();
+        assert_eq!(stopped, [200, 201]);
+    }
+
+    #[test]
+    fn for_dir_resolves_sibling_dirs_against_dist() {
+        let mut m = manifest();
+        m.runner.sibling_dirs = vec!["../backup".to_string(), "E:/shared".to_string()];
+        let r = SiblingRules::for_dir(&m, "red.exe", 100, Path::new("C:/dist"));
+        assert_eq!(r.dirs, ["c:\\dist", "c:\\backup", "e:\\shared"]);
+    }
+
+    #[test]
+    fn targets_exclude_self_and_off() {
+        let r = rules(&[]);
+        let mut t: Vec<&String> = r.targets.iter().collect();
+        t.sort();
+        assert_eq!(t, ["navy.exe", "white.exe"]);
+        assert_eq!(r.dirs, ["c:\\tools\\awcc\\dist"]);
+    }
+
+    #[test]
+    fn only_siblings_inside_family_dirs_are_stopped() {
+        let mut table = FakeTable::default();
+        table.add(100, "red.exe", Some("C:\\Tools\\awcc\\dist\\red.exe"), 1);
+        table.add(200, "navy.exe", Some("c:/tools/AWCC/dist/NAVY.EXE"), 2);
+        table.add(201, "white.exe", Some("D:\\Games\\white.exe"), 3);
+        table.add(202, "WHITE.EXE", Some("E:\\backup\\dist\\white.exe"), 4);
+        table.add(203, "navy.exe", None, 5);
+        table.add(300, "chrome.exe", Some("C:\\Tools\\awcc\\dist\\chrome.exe"), 6);
+
+        let out = stop_siblings(&mut table, &rules(&["e:\\backup\\dist\\"]));
+        let actions: Vec<(u32, Action)> = out.into_iter().map(|o| (o.pid, o.action)).collect();
+        assert_eq!(
+            actions,
+            [
+                (200, Action::Closed),
+                (201, Action::Skipped(SkipReason::OutsideFamilyDirs("D:\\Games\\white.exe".to_string()))),
+                (202, Action::Closed),
+                (203, Action::Skipped(SkipReason::PathUnknown)),
+            ]
+        );
+        assert_eq!(table.closed, [200, 202]);
+        assert!(table.terminated.is_empty());
+        assert!(table.procs.contains_key(&100));
+    }
+
+    #[test]
+    fn reused_pid_is_never_terminated() {
+        let mut table = FakeTable::default();
+        table.add(200, "navy.exe", Some("C:\\Tools\\awcc\\dist\\navy.exe"), 2);
+        table.add(201, "white.exe", Some("C:\\Tools\\awcc\\dist\\white.exe"), 3).deny_open = true;
+        table.reuse_on_open.insert(200);
+
+        let out = stop_siblings(&mut table, &rules(&[]));
+        assert_eq!(out[0].action, Action::Skipped(SkipReason::PidReused));
+        assert_eq!(out[1].action, Action::OpenFailed(OpenError::AccessDenied));
+        assert!(table.close_requested.is_empty());
+        assert!(table.terminated.is_empty());
+        assert_eq!(out[0].to_string(), "navy.exe (pid 200): skipped, pid was reused");
+    }
+
+    #[test]
+    fn close_is_tried_before_terminate() {
+        let mut table = FakeTable::default();
+        table.add(200, "navy.exe", Some("C:\\Tools\\awcc\\dist\\navy.exe"), 2);
+        table.add(201, "white.exe", Some("C:\\Tools\\awcc\\dist\\white.exe"), 3).window = Window::Hangs;
+        table.add(202, "navy.exe", Some("C:\\Tools\\awcc\\dist\\navy.exe"), 4).window = Window::None;
+
+        let out = stop_siblings(&mut table, &rules(&[]));
+        let lines: Vec<String> = out.iter().map(|o| o.to_string()).collect();
+        assert_eq!(
+            lines,
+            [
+                "navy.exe (pid 200): closed after WM_CLOSE",
+                "white.exe (pid 201): terminated, still running 2000 ms after WM_CLOSE",
+                "navy.exe (pid 202): terminated, no runner window to close",
+            ]
+        );
+        assert_eq!(table.close_requested, [200, 201]);
+        assert_eq!(table.closed, [200]);
+        assert_eq!(table.terminated, [201, 202]);
+    }
+
+    #[test]
+    fn zero_close_timeout_terminates_directly() {
+        let mut table = FakeTable::default();
+        table.add(200, "navy.exe", Some("C:\\Tools\\awcc\\dist\\navy.exe"), 2);
+        let mut r = rules(&[]);
+        r.close_timeout = Duration::ZERO;
+
+        let out = stop_siblings(&mut table, &r);
+        assert_eq!(out[0].action, Action::Terminated(Forced::CloseDisabled));
+        assert!(table.close_requested.is_empty());
+        assert_eq!(table.terminated, [200]);
+    }
+
+    #[test]
+    fn off_status_reflects_what_is_left_running() {
+        let dist = |name: &str| format!("C:\\Tools\\awcc\\dist\\{}", name);
+        let mut r = rules(&[]);
+        r.exit_wait = Duration::from_millis(10);
+
+        let mut table = FakeTable::default();
+        table.add(201, "white.exe", Some("D:\\Games\\white.exe"), 1);
+        assert_eq!(OffStatus::from_outcomes(&stop_siblings(&mut table, &r)), OffStatus::NothingRunning);
+
+        table.add(200, "navy.exe", Some(&dist("navy.exe")), 2).window = Window::None;
+        assert_eq!(OffStatus::from_outcomes(&stop_siblings(&mut table, &r)), OffStatus::AllStopped);
+
+        let p = table.add(200, "navy.exe", Some(&dist("navy.exe")), 3);
+        p.window = Window::Hangs;
+        p.lingers = true;
+        let out = stop_siblings(&mut table, &r);
+        assert_eq!(out[0].action, Action::Survived);
+        assert_eq!(OffStatus::from_outcomes(&out), OffStatus::SomeSurvived);
+
+        table.add(202, "navy.exe", Some(&dist("navy.exe")), 4).deny_open = true;
+        let status = OffStatus::from_outcomes(&stop_siblings(&mut table, &r));
+        assert_eq!(status, OffStatus::AccessDenied);
+        assert_eq!(status.exit_code(), 4);
+        assert_eq!(OffStatus::from_exit_code(4), Some(OffStatus::AccessDenied));
+        assert_eq!(OffStatus::from_exit_code(1), None);
+    }
+
+    #[test]
+    fn newer_siblings_are_spared() {
+        let mut table = FakeTable::default();
+        table.add(200, "navy.exe", Some("C:\\Tools\\awcc\\dist\\navy.exe"), 40);
+        table.add(201, "white.exe", Some("C:\\Tools\\awcc\\dist\\white.exe"), 60);
+
+        let out = stop_siblings(&mut table, &rules(&[]).with_created(Some(50)));
+        assert_eq!(out[0].action, Action::Closed);
+        assert_eq!(out[1].to_string(), "white.exe (pid 201): skipped, started after us");
+    }
+
+    #[test]
+    fn session_scope_spares_other_users() {
+        let r = rules(&[]);
+        assert_eq!(classify(&candidate(200, "navy.exe", Some(1)), &r), Decision::Stop);
+        assert_eq!(classify(&candidate(200, "navy.exe", Some(2)), &r), Decision::Skip(SkipReason::OtherSession(2)));
+        assert_eq!(classify(&candidate(200, "navy.exe", None), &r), Decision::Skip(SkipReason::SessionUnknown));
+        assert_eq!(classify(&candidate(100, "red.exe", Some(1)), &r), Decision::Ignore);
+        assert_eq!(classify(&candidate(300, "chrome.exe", Some(1)), &r), Decision::Ignore);
+
+        let unknown_self = rules(&[]).with_session(None);
+        assert_eq!(classify(&candidate(200, "navy.exe", Some(1)), &unknown_self), Decision::Skip(SkipReason::SessionUnknown));
+
+        let mut machine = rules(&[]).with_session(None);
+        machine.scope = Scope::Machine;
+        assert_eq!(classify(&candidate(200, "navy.exe", Some(2)), &machine), Decision::Stop);
+        assert_eq!(classify(&candidate(200, "navy.exe", None), &machine), Decision::Stop);
+    }
+
+    #[test]
+    fn other_session_siblings_are_reported_not_killed() {
+        let mut table = FakeTable::default();
+        table.add(200, "navy.exe", Some("C:\\Tools\\awcc\\dist\\navy.exe"), 2);
+        table.add(201, "navy.exe", Some("C:\\Tools\\awcc\\dist\\navy.exe"), 3).session = 2;
+
+        let out = stop_siblings(&mut table, &rules(&[]));
+        assert_eq!(out[1].to_string(), "navy.exe (pid 201): skipped, belongs to session 2");
+        assert_eq!(table.closed, [200]);
+    }
+
+    #[test]
+    fn dir_keys_normalise_prefix_case_and_separators() {
+        assert_eq!(dir_key("\\\\?\\C:\\Dist\\"), "c:\\dist");
+        assert_eq!(dir_key("C:/Dist"), "c:\\dist");
+        assert_eq!(parent_key("C:\\Dist\\red.exe"), "c:\\dist");
+        assert_eq!(parent_key("red.exe"), "");
+        assert_eq!(dir_key("C:\\dist\\..\\backup\\.\\old\\.."), "c:\\backup");
+        assert_eq!(dir_key("C:\\..\\.."), "c:");
+        assert_eq!(dir_key("\\\\nas\\share\\..\\dist"), "\\\\nas\\share\\dist");
+        assert_eq!(dir_key("\\"), "\\");
+    }
+}
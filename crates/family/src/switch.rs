@@ -0,0 +1,328 @@
+//! Arbitration between profile switches launched at the same time.
+//!
+//! Two quick Stream Deck presses start two runners that would otherwise stop each
+//! other. Every launch takes a ticket from a sequence file next to the manifest
+//! ([`SEQUENCE_FILE`]) under a family-wide lock, then switches under the same lock
+//! only if no newer ticket has been issued meanwhile ("last request wins"). While
+//! switching, siblings started after us are spared (see
+//! [`SiblingRules::self_created`](crate::siblings::SiblingRules::self_created)),
+//! so the newest request is the one left running.
+//!
+//! Relaunching a profile that is already running is a request as well: the
+//! second launch takes its ticket before finding the instance lock held and
+//! hands it over in its [`Refresh`](crate::instance::Refresh), and the running
+//! instance switches under that ticket.
+//!
+//! [`handoff_delay`] decides whether that happens right at startup or after the
+//! new profile has been up for a while (see [`Handoff`]).
+
+use crate::manifest::{Handoff, HandoffOrder};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Last issued ticket, as decimal text, in the output directory.
+pub const SEQUENCE_FILE: &str = "switch.seq";
+
+/// Family-wide mutual exclusion (a named mutex on Windows).
+pub trait FamilyLock {
+    type Guard;
+
+    /// Block until the lock is held; released when the guard is dropped.
+    fn lock(&self) -> Self::Guard;
+}
+
+/// The sequence file. Only read or written while the [`FamilyLock`] is held.
+#[derive(Debug, Clone)]
+pub struct SwitchFile {
+    path: PathBuf,
+}
+
+impl SwitchFile {
+    pub fn new(dir: &Path) -> Self {
+        SwitchFile { path: dir.join(SEQUENCE_FILE) }
+    }
+
+    /// Last issued ticket; 0 if none was issued yet or the file is unreadable.
+    pub fn latest(&self) -> u64 {
+        fs::read_to_string(&self.path).ok().and_then(|s| s.trim().parse().ok()).unwrap_or(0)
+    }
+
+    /// Issue the next ticket.
+    pub fn bump(&self) -> io::Result<u64> {
+        let next = self.latest().wrapping_add(1).max(1);
+        fs::write(&self.path, format!("{}\n", next))?;
+        Ok(next)
+    }
+}
+
+/// Whether a launch holding `ticket` may switch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Turn {
+    Proceed,
+    /// A newer request (`latest`) exists; this launch should exit without switching.
+    Superseded { latest: u64 },
+}
+
+pub fn decide(ticket: u64, latest: u64) -> Turn {
+    if latest > ticket {
+        Turn::Superseded { latest }
+    } else {
+        Turn::Proceed
+    }
+}
+
+pub fn take_ticket<L: FamilyLock>(lock: &L, file: &SwitchFile) -> io::Result<u64> {
+    let _guard = lock.lock();
+    file.bump()
+}
+
+/// Run `switch` under the lock if `ticket` is still the latest request.
+pub fn with_turn<L: FamilyLock, R>(lock: &L, file: &SwitchFile, ticket: u64, switch: impl FnOnce() -> R) -> Result<R, Turn> {
+    let _guard = lock.lock();
+    match decide(ticket, file.latest()) {
+        Turn::Proceed => Ok(switch()),
+        superseded => Err(superseded),
+    }
+}
+
+/// How long after startup the switch (stopping the previous profile) should run;
+/// `None` means before the tray is set up. The off EXE has nothing to hand off to.
+pub fn handoff_delay(handoff: &Handoff, off_mode: bool) -> Option<Duration> {
+    match handoff.order {
+        HandoffOrder::StartFirst if !off_mode => Some(Duration::from_millis(handoff.overlap_ms as u64)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod fake {
+    use super::*;
+    use std::sync::{Arc, Condvar, Mutex};
+
+    /// Lock shared between threads, standing in for the named mutex.
+    #[derive(Debug, Clone, Default)]
+    pub struct MemoryLock(Arc<(Mutex<bool>, Condvar)>);
+
+    pub struct Guard(Arc<(Mutex<bool>, Condvar)>);
+
+    impl Drop for Guard {
+        fn drop(&mut self) {
+            let (held, cv) = &*self.0;
+            *held.lock().unwrap() = false;
+            cv.notify_one();
+        }
+    }
+
+    impl FamilyLock for MemoryLock {
+        type Guard = Guard;
+
+        fn lock(&self) -> Guard {
+            let (held, cv) = &*self.0;
+            let mut h = cv.wait_while(held.lock().unwrap(), |h| *h).unwrap();
+            *h = true;
+            Guard(self.0.clone())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::fake::MemoryLock;
+    use super::*;
+    use crate::manifest::{Entry, Manifest, Role, MANIFEST_VERSION};
+    use crate::siblings::{classify, Candidate, Decision, SiblingRules};
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::{Arc, Barrier, Mutex};
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    #[test]
+    fn tickets_increase_and_newer_ones_win() {
+        let dir = unique_temp_dir("seq");
+        let file = SwitchFile::new(&dir);
+        let lock = MemoryLock::default();
+        assert_eq!(file.latest(), 0);
+        let a = take_ticket(&lock, &file).unwrap();
+        let b = take_ticket(&lock, &file).unwrap();
+        assert_eq!((a, b), (1, 2));
+        assert_eq!(fs::read_to_string(dir.join(SEQUENCE_FILE)).unwrap(), "2\n");
+
+        assert_eq!(with_turn(&lock, &file, a, || "switched"), Err(Turn::Superseded { latest: 2 }));
+        assert_eq!(with_turn(&lock, &file, b, || "switched"), Ok("switched"));
+
+        fs::write(dir.join(SEQUENCE_FILE), "garbage").unwrap();
+        assert_eq!(take_ticket(&lock, &file).unwrap(), 1);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn handoff_delay_follows_order_and_skips_off() {
+        let start_first = Handoff { order: HandoffOrder::StartFirst, overlap_ms: 750 };
+        assert_eq!(handoff_delay(&start_first, false), Some(Duration::from_millis(750)));
+        assert_eq!(handoff_delay(&start_first, true), None);
+        assert_eq!(handoff_delay(&Handoff::default(), false), None);
+    }
+
+    /// One simulated runner process.
+    #[derive(Debug)]
+    struct SimProc {
+        exe: String,
+        created: u64,
+        /// Order in which the launch reached the family lock, i.e. made its request.
+        requested: u64,
+        ticket: Option<u64>,
+        alive: bool,
+        /// Holds the profile's instance lock.
+        primary: bool,
+        /// Past its startup switch, so it can take a relaunch's refresh.
+        ready: bool,
+    }
+
+    /// With `running` already up, launch `profiles` at once, each following the runner's startup order: a
+    /// ticket, then the instance lock, then the switch under the family lock, with
+    /// kills taking effect immediately. A relaunch of a running profile hands its
+    /// ticket and creation time over, and the running one switches under them; one
+    /// whose target is still starting waits until that target is ready or gone.
+    fn simulate(running: Option<&str>, profiles: &[&'static str], dir: &Path) -> Vec<SimProc> {
+        let names = ["red", "navy", "white", "pink", "orange"];
+        let manifest = Manifest {
+            version: MANIFEST_VERSION,
+            generator_version: None,
+            profiles: names.iter().map(|n| Entry::new(n, &format!("{}.exe", n), Role::Profile)).collect(),
+            awcc: None,
+            runner: Default::default(),
+        };
+        let world: Arc<Mutex<Vec<SimProc>>> = Arc::new(Mutex::new(Vec::new()));
+        let clock = Arc::new(AtomicU64::new(1));
+        let lock = MemoryLock::default();
+        let file = SwitchFile::new(dir);
+        if let Some(name) = running {
+            let ticket = Some(file.bump().unwrap());
+            let exe = format!("{}.exe", name);
+            world.lock().unwrap().push(SimProc { exe, created: 0, requested: 0, ticket, alive: true, primary: true, ready: true });
+        }
+        let start = Arc::new(Barrier::new(profiles.len()));
+
+        let handles: Vec<_> = profiles
+            .iter()
+            .map(|&name| {
+                let (world, clock, lock, file, manifest, start) =
+                    (world.clone(), clock.clone(), lock.clone(), file.clone(), manifest.clone(), start.clone());
+                std::thread::spawn(move || {
+                    let exe = format!("{}.exe", name);
+                    let pid = {
+                        let mut w = world.lock().unwrap();
+                        let created = clock.fetch_add(1, Ordering::SeqCst);
+                        w.push(SimProc { exe: exe.clone(), created, requested: 0, ticket: None, alive: true, primary: false, ready: false });
+                        w.len() - 1
+                    };
+                    let alive = |world: &Mutex<Vec<SimProc>>| world.lock().unwrap()[pid].alive;
+                    start.wait();
+
+                    let ticket = {
+                        let _g = lock.lock();
+                        if !alive(&world) {
+                            return;
+                        }
+                        world.lock().unwrap()[pid].requested = clock.fetch_add(1, Ordering::SeqCst);
+                        let t = file.bump().unwrap();
+                        world.lock().unwrap()[pid].ticket = Some(t);
+                        t
+                    };
+                    std::thread::yield_now();
+
+                    // The instance lock: become primary, or find the one to refresh
+                    let (target, since) = loop {
+                        let mut w = world.lock().unwrap();
+                        if !w[pid].alive {
+                            return;
+                        }
+                        let created = w[pid].created;
+                        match w.iter().position(|p| p.alive && p.primary && p.exe == exe) {
+                            None => {
+                                w[pid].primary = true;
+                                break (pid, created);
+                            }
+                            Some(other) if w[other].ready => {
+                                w[pid].alive = false;
+                                break (other, created);
+                            }
+                            Some(_) => {}
+                        }
+                        drop(w);
+                        std::thread::yield_now();
+                    };
+                    // Window creation, or a start_first overlap
+                    std::thread::sleep(Duration::from_millis(1));
+
+                    let _g = lock.lock();
+                    let mut w = world.lock().unwrap();
+                    if !w[target].alive {
+                        return;
+                    }
+                    if let Turn::Superseded { .. } = decide(ticket, file.latest()) {
+                        // Our own startup gives way; a relaunch's switch is simply dropped
+                        if target == pid {
+                            w[pid].alive = false;
+                        }
+                        return;
+                    }
+                    let rules = SiblingRules::new(&manifest, &exe, target as u32, "C:\\dist", &[])
+                        .with_session(Some(1))
+                        .with_created(Some(since));
+                    for (other, p) in w.iter_mut().enumerate() {
+                        let c = Candidate {
+                            pid: other as u32,
+                            image_name: &p.exe,
+                            path: Some("C:\\dist\\x.exe"),
+                            session: Some(1),
+                            created: Some(p.created),
+                        };
+                        if p.alive && classify(&c, &rules) == Decision::Stop {
+                            p.alive = false;
+                        }
+                    }
+                    w[target].ready = true;
+                })
+            })
+            .collect();
+        for h in handles {
+            h.join().unwrap();
+        }
+        Arc::try_unwrap(world).unwrap().into_inner().unwrap()
+    }
+
+    /// Exactly one runner is left, and it is the profile of the latest request.
+    fn assert_latest_wins(running: Option<&str>, profiles: &[&'static str], rounds: usize) {
+        for round in 0..rounds {
+            let dir = unique_temp_dir(&format!("sim-{}", round));
+            let procs = simulate(running, profiles, &dir);
+            let alive: Vec<&SimProc> = procs.iter().filter(|p| p.alive).collect();
+            assert_eq!(alive.len(), 1, "round {}: {:?}", round, procs);
+            let latest = procs.iter().max_by_key(|p| p.requested).unwrap();
+            assert_eq!(alive[0].exe, latest.exe, "round {}: {:?}", round, procs);
+            fs::remove_dir_all(dir).unwrap();
+        }
+    }
+
+    #[test]
+    fn concurrent_launches_settle_on_the_latest_request() {
+        assert_latest_wins(None, &["red", "navy", "white", "pink", "orange"], 50);
+    }
+
+    #[test]
+    fn relaunching_a_running_profile_counts_as_the_latest_request() {
+        // red running, navy and red requested: red must win when its relaunch is last
+        assert_latest_wins(Some("red"), &["navy", "red"], 100);
+        assert_latest_wins(Some("red"), &["navy", "red", "white", "red"], 50);
+        assert_latest_wins(None, &["red", "navy", "red", "navy", "red"], 50);
+    }
+
+    fn unique_temp_dir(tag: &str) -> PathBuf {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos();
+        let path = std::env::temp_dir().join(format!("awcc-switch-test-{}-{}-{}", tag, std::process::id(), nanos));
+        fs::create_dir_all(&path).unwrap();
+        path
+    }
+}
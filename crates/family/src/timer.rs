@@ -0,0 +1,253 @@
+//! Auto-revert: a profile that switches itself off after a while.
+//!
+//! The limit comes from the profile's `duration` (configure.yaml v2) or from
+//! `--for 45m` on the runner's command line, which wins; launching a running
+//! profile again with `--for` restarts its timer. The runner polls
+//! [`AutoOff`] from a window timer, shows what is left in the tray tooltip and
+//! goes Off when it runs out; the tray menu can extend or cancel it.
+
+use std::time::{Duration, Instant};
+
+/// Command-line option: `--for 45m` or `--for=45m`.
+pub const ARG: &str = "--for";
+/// What the tray's extend entry adds.
+pub const EXTEND_STEP: Duration = Duration::from_secs(15 * 60);
+/// Longest accepted limit, which also keeps deadlines far from `Instant` overflow.
+pub const MAX_DURATION: Duration = Duration::from_secs(7 * 24 * 3600);
+
+/// Source of the current time, replaced by a fake in tests.
+pub trait Clock {
+    fn now(&self) -> Instant;
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// Result of one [`AutoOff::tick`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tick {
+    /// No limit set (or it was cancelled).
+    Idle,
+    Running(Duration),
+    /// The limit has just run out; reported once, after which the timer is idle.
+    Expired,
+}
+
+/// Deadline for the running profile.
+#[derive(Debug)]
+pub struct AutoOff<C> {
+    clock: C,
+    deadline: Option<Instant>,
+}
+
+impl<C: Clock> AutoOff<C> {
+    pub const fn new(clock: C) -> Self {
+        AutoOff { clock, deadline: None }
+    }
+
+    /// Go off `limit` from now, replacing any earlier deadline.
+    pub fn start(&mut self, limit: Duration) {
+        self.deadline = self.clock.now().checked_add(limit.min(MAX_DURATION));
+    }
+
+    /// Push a running deadline back by `by`; an idle timer stays idle.
+    pub fn extend(&mut self, by: Duration) {
+        let Some(remaining) = self.remaining() else { return };
+        self.start(remaining + by);
+    }
+
+    pub fn cancel(&mut self) {
+        self.deadline = None;
+    }
+
+    /// Time left, zero once it has run out; `None` when idle.
+    pub fn remaining(&self) -> Option<Duration> {
+        self.deadline.map(|d| d.saturating_duration_since(self.clock.now()))
+    }
+
+    pub fn tick(&mut self) -> Tick {
+        match self.remaining() {
+            None => Tick::Idle,
+            Some(left) if left.is_zero() => {
+                self.deadline = None;
+                Tick::Expired
+            }
+            Some(left) => Tick::Running(left),
+        }
+    }
+}
+
+/// `45m`, `1h30m`, `1h 30m`, `90s`: numbers with `h` / `m` / `s` units, largest first.
+pub fn parse_duration(text: &str) -> Result<Duration, String> {
+    let invalid = || format!("invalid duration {:?} (use e.g. 45m, 1h30m or 90s)", text);
+    let mut total: u64 = 0;
+    let mut last_unit = u64::MAX;
+    let mut rest = text.trim();
+    if rest.is_empty() {
+        return Err(invalid());
+    }
+    while !rest.is_empty() {
+        let digits = rest.find(|c: char| !c.is_ascii_digit()).ok_or_else(invalid)?;
+        let value: u64 = rest[..digits].parse().map_err(|_| invalid())?;
+        let unit = match rest[digits..].chars().next().map(|c| c.to_ascii_lowercase()) {
+            Some('h') => 3600,
+            Some('m') => 60,
+            Some('s') => 1,
+            _ => return Err(invalid()),
+        };
+        if unit >= last_unit {
+            return Err(invalid());
+        }
+        last_unit = unit;
+        total = value.checked_mul(unit).and_then(|v| total.checked_add(v)).ok_or_else(invalid)?;
+        rest = rest[digits + 1..].trim_start();
+    }
+    let limit = Duration::from_secs(total);
+    if limit.is_zero() {
+        return Err(format!("duration {:?} must be longer than zero", text));
+    }
+    if limit > MAX_DURATION {
+        return Err(format!("duration {:?} is longer than {}h", text, MAX_DURATION.as_secs() / 3600));
+    }
+    Ok(limit)
+}
+
+/// `--for` from the runner's arguments (without the program name); `None` when absent.
+pub fn duration_arg(args: impl IntoIterator<Item = String>) -> Result<Option<Duration>, String> {
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if arg == ARG {
+            let value = args.next().ok_or_else(|| format!("{} needs a duration", ARG))?;
+            return parse_duration(&value).map(Some);
+        }
+        if let Some(value) = arg.strip_prefix(ARG).and_then(|v| v.strip_prefix('=')) {
+            return parse_duration(value).map(Some);
+        }
+    }
+    Ok(None)
+}
+
+/// Compact time left for the tooltip and menu: `1h 05m`, `45m`, `30s`.
+/// Minutes are rounded up, so a fresh `45m` limit reads `45m`.
+pub fn format_remaining(left: Duration) -> String {
+    let secs = left.as_secs() + u64::from(left.subsec_nanos() > 0);
+    if secs < 60 {
+        return format!("{}s", secs);
+    }
+    let minutes = secs.div_ceil(60);
+    if minutes < 60 {
+        format!("{}m", minutes)
+    } else {
+        format!("{}h {:02}m", minutes / 60, minutes % 60)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod fake {
+    use super::*;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    /// A clock that only moves when told to; clones share the same time.
+    #[derive(Debug, Clone)]
+    pub struct FakeClock(Rc<Cell<Instant>>);
+
+    impl FakeClock {
+        pub fn new() -> Self {
+            FakeClock(Rc::new(Cell::new(Instant::now())))
+        }
+
+        pub fn advance(&self, by: Duration) {
+            self.0.set(self.0.get() + by);
+        }
+    }
+
+    impl Clock for FakeClock {
+        fn now(&self) -> Instant {
+            self.0.get()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::fake::FakeClock;
+    use super::*;
+
+    const MIN: Duration = Duration::from_secs(60);
+
+    #[test]
+    fn timer_counts_down_and_expires_once() {
+        let clock = FakeClock::new();
+        let mut timer = AutoOff::new(clock.clone());
+        assert_eq!(timer.tick(), Tick::Idle);
+
+        timer.start(45 * MIN);
+        assert_eq!(timer.tick(), Tick::Running(45 * MIN));
+        clock.advance(44 * MIN);
+        assert_eq!(timer.tick(), Tick::Running(MIN));
+        clock.advance(MIN);
+        assert_eq!(timer.tick(), Tick::Expired);
+        assert_eq!(timer.tick(), Tick::Idle);
+        assert_eq!(timer.remaining(), None);
+    }
+
+    #[test]
+    fn extend_and_cancel() {
+        let clock = FakeClock::new();
+        let mut timer = AutoOff::new(clock.clone());
+        timer.extend(EXTEND_STEP);
+        assert_eq!(timer.tick(), Tick::Idle, "nothing to extend");
+
+        timer.start(10 * MIN);
+        clock.advance(8 * MIN);
+        timer.extend(EXTEND_STEP);
+        assert_eq!(timer.remaining(), Some(17 * MIN));
+        clock.advance(16 * MIN);
+        assert_eq!(timer.tick(), Tick::Running(MIN));
+
+        timer.cancel();
+        clock.advance(60 * MIN);
+        assert_eq!(timer.tick(), Tick::Idle);
+
+        timer.start(MAX_DURATION);
+        timer.extend(EXTEND_STEP);
+        assert_eq!(timer.remaining(), Some(MAX_DURATION), "capped");
+    }
+
+    #[test]
+    fn parses_durations_and_the_command_line() {
+        assert_eq!(parse_duration("45m"), Ok(45 * MIN));
+        assert_eq!(parse_duration("1h30m"), Ok(90 * MIN));
+        assert_eq!(parse_duration(" 1H 30m 15s "), Ok(90 * MIN + Duration::from_secs(15)));
+        assert_eq!(parse_duration("90s"), Ok(Duration::from_secs(90)));
+        for bad in ["", "45", "m", "30m1h", "1m1m", "1d", "-5m", "1h30"] {
+            assert!(parse_duration(bad).is_err(), "{:?}", bad);
+        }
+        assert!(parse_duration("0m").unwrap_err().contains("longer than zero"));
+        assert!(parse_duration("169h").unwrap_err().contains("168h"));
+        assert!(parse_duration("99999999999999999999h").is_err());
+
+        let args = |v: &[&str]| v.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert_eq!(duration_arg(args(&["--for", "45m"])), Ok(Some(45 * MIN)));
+        assert_eq!(duration_arg(args(&["--other", "--for=1h"])), Ok(Some(60 * MIN)));
+        assert_eq!(duration_arg(args(&[])), Ok(None));
+        assert!(duration_arg(args(&["--for"])).is_err());
+        assert!(duration_arg(args(&["--for", "soon"])).is_err());
+    }
+
+    #[test]
+    fn remaining_time_reads_compactly() {
+        assert_eq!(format_remaining(45 * MIN), "45m");
+        assert_eq!(format_remaining(44 * MIN + Duration::from_millis(1)), "45m");
+        assert_eq!(format_remaining(65 * MIN), "1h 05m");
+        assert_eq!(format_remaining(Duration::from_millis(29_500)), "30s");
+        assert_eq!(format_remaining(Duration::ZERO), "0s");
+    }
+}
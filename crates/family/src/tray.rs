@@ -0,0 +1,238 @@
+//! The runner's tray menu: every profile of the family (grouped into submenus
+//! by `group`, the running one checked), the auto-off timer when one is
+//! running, Off and Exit.
+//!
+//! Switching never happens here: the chosen sibling EXE is launched and does
+//! the usual handoff, stopping this runner along the way.
+
+use crate::manifest::{image_key, Manifest, Role, TrayAction};
+use crate::timer;
+use std::time::Duration;
+
+/// Menu title line (disabled).
+pub const ID_TITLE: usize = 1000;
+pub const ID_EXIT: usize = 1001;
+pub const ID_OFF: usize = 1002;
+pub const ID_EXTEND_TIMER: usize = 1003;
+pub const ID_CANCEL_TIMER: usize = 1004;
+/// Profile items are this plus the entry's index in `Manifest::profiles`.
+pub const ID_PROFILE_BASE: usize = 2000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Item {
+    /// Disabled line: the running profile, or the time left before auto-off.
+    Title(String),
+    Separator,
+    Command { id: usize, label: String, checked: bool },
+    Submenu { label: String, items: Vec<Item> },
+}
+
+/// What a menu choice or a double click asks the runner to do.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    Nothing,
+    /// Start this sibling EXE (a file name in the dist folder).
+    Launch(String),
+    /// Close this runner, which leaves no profile running.
+    Quit,
+    ShowMenu,
+    /// Push the auto-off deadline back by [`timer::EXTEND_STEP`].
+    ExtendTimer,
+    CancelTimer,
+}
+
+/// Menu for the runner `current_exe` (file name, `.exe` optional), with
+/// `auto_off` left on its timer if it has one. Without a manifest only the
+/// title, the timer, Off and Exit are offered.
+pub fn menu(manifest: Option<&Manifest>, current_exe: &str, title: &str, auto_off: Option<Duration>) -> Vec<Item> {
+    let mut items = vec![Item::Title(title.to_string()), Item::Separator];
+    let current = image_key(current_exe);
+    let mut any = false;
+    for (i, entry) in manifest.iter().flat_map(|m| m.profiles.iter().enumerate()) {
+        if entry.role != Role::Profile {
+            continue;
+        }
+        any = true;
+        let item = Item::Command {
+            id: ID_PROFILE_BASE + i,
+            label: entry.label().to_string(),
+            checked: image_key(&entry.exe) == current,
+        };
+        let Some(group) = entry.group.as_deref() else {
+            items.push(item);
+            continue;
+        };
+        // A group's submenu sits where its first profile appears
+        let existing = items.iter_mut().find_map(|it| match it {
+            Item::Submenu { label, items } if label == group => Some(items),
+            _ => None,
+        });
+        match existing {
+            Some(sub) => sub.push(item),
+            None => items.push(Item::Submenu { label: group.to_string(), items: vec![item] }),
+        }
+    }
+    if any {
+        items.push(Item::Separator);
+    }
+    if let Some(left) = auto_off {
+        let step = timer::format_remaining(timer::EXTEND_STEP);
+        items.push(Item::Title(format!("Off in {}", timer::format_remaining(left))));
+        items.push(Item::Command { id: ID_EXTEND_TIMER, label: format!("Extend by {}", step), checked: false });
+        items.push(Item::Command { id: ID_CANCEL_TIMER, label: "Cancel timer".to_string(), checked: false });
+        items.push(Item::Separator);
+    }
+    items.push(Item::Command { id: ID_OFF, label: "Off".to_string(), checked: false });
+    items.push(Item::Separator);
+    items.push(Item::Command { id: ID_EXIT, label: "Exit".to_string(), checked: false });
+    items
+}
+
+/// Action for the menu item `id` chosen in [`menu`].
+pub fn choose(manifest: Option<&Manifest>, current_exe: &str, id: usize) -> Action {
+    match id {
+        ID_EXIT => Action::Quit,
+        ID_OFF => off(manifest),
+        ID_EXTEND_TIMER => Action::ExtendTimer,
+        ID_CANCEL_TIMER => Action::CancelTimer,
+        _ => {
+            let entry = id.checked_sub(ID_PROFILE_BASE).and_then(|i| manifest?.profiles.get(i));
+            match entry {
+                Some(e) if e.role == Role::Profile => launch(&e.exe, current_exe),
+                _ => Action::Nothing,
+            }
+        }
+    }
+}
+
+/// Action for a double click on the tray icon, as set by `runner.double_click`.
+pub fn double_click(manifest: Option<&Manifest>, current_exe: &str) -> Action {
+    let Some(m) = manifest else { return Action::Nothing };
+    match &m.runner.double_click {
+        TrayAction::None => Action::Nothing,
+        TrayAction::Menu => Action::ShowMenu,
+        TrayAction::Off => off(manifest),
+        TrayAction::Profile(name) => match m.entry_by_name(name).filter(|e| e.role == Role::Profile) {
+            Some(e) => launch(&e.exe, current_exe),
+            None => Action::Nothing,
+        },
+    }
+}
+
+/// What to do when the auto-off timer runs out: the same as choosing Off.
+pub fn timer_expired(manifest: Option<&Manifest>) -> Action {
+    off(manifest)
+}
+
+/// The off EXE when there is one; otherwise closing this runner has the same effect.
+fn off(manifest: Option<&Manifest>) -> Action {
+    match manifest.and_then(Manifest::off) {
+        Some(e) => Action::Launch(e.exe.clone()),
+        None => Action::Quit,
+    }
+}
+
+fn launch(exe: &str, current_exe: &str) -> Action {
+    if image_key(exe) == image_key(current_exe) {
+        Action::Nothing
+    } else {
+        Action::Launch(exe.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::MANIFEST_VERSION;
+    use crate::Entry;
+
+    fn manifest() -> Manifest {
+        let mut entries = vec![
+            Entry::new("red", "red.exe", Role::Profile),
+            Entry::new("navy", "navy.exe", Role::Profile),
+            Entry::new("green", "green.exe", Role::Profile),
+            Entry::new("white", "white.exe", Role::Profile),
+            Entry::new("off", "off.exe", Role::Off),
+        ];
+        entries[0].display_name = Some("Meeting red".to_string());
+        entries[1].group = Some("Work".to_string());
+        entries[3].group = Some("Work".to_string());
+        Manifest { version: MANIFEST_VERSION, generator_version: None, profiles: entries, awcc: None, runner: Default::default() }
+    }
+
+    fn command(id: usize, label: &str, checked: bool) -> Item {
+        Item::Command { id, label: label.to_string(), checked }
+    }
+
+    #[test]
+    fn menu_lists_profiles_grouped_with_the_current_one_checked() {
+        let m = manifest();
+        assert_eq!(
+            menu(Some(&m), "NAVY.EXE", "awcc-ctrl-exe-moc - navy", None),
+            [
+                Item::Title("awcc-ctrl-exe-moc - navy".to_string()),
+                Item::Separator,
+                command(2000, "Meeting red", false),
+                Item::Submenu { label: "Work".to_string(), items: vec![command(2001, "navy", true), command(2003, "white", false)] },
+                command(2002, "green", false),
+                Item::Separator,
+                command(ID_OFF, "Off", false),
+                Item::Separator,
+                command(ID_EXIT, "Exit", false),
+            ]
+        );
+        let bare = menu(None, "red.exe", "t", None);
+        assert_eq!(bare.len(), 5);
+        assert_eq!(bare[2], command(ID_OFF, "Off", false));
+    }
+
+    #[test]
+    fn a_running_timer_can_be_extended_or_cancelled() {
+        let items = menu(None, "red.exe", "t", Some(Duration::from_secs(44 * 60 + 1)));
+        assert_eq!(
+            items[2..6],
+            [
+                Item::Title("Off in 45m".to_string()),
+                command(ID_EXTEND_TIMER, "Extend by 15m", false),
+                command(ID_CANCEL_TIMER, "Cancel timer", false),
+                Item::Separator,
+            ]
+        );
+        let m = manifest();
+        assert_eq!(choose(Some(&m), "red.exe", ID_EXTEND_TIMER), Action::ExtendTimer);
+        assert_eq!(choose(Some(&m), "red.exe", ID_CANCEL_TIMER), Action::CancelTimer);
+        assert_eq!(timer_expired(Some(&m)), Action::Launch("off.exe".to_string()));
+        assert_eq!(timer_expired(None), Action::Quit);
+    }
+
+    #[test]
+    fn choices_launch_siblings() {
+        let m = manifest();
+        assert_eq!(choose(Some(&m), "navy.exe", 2000), Action::Launch("red.exe".to_string()));
+        assert_eq!(choose(Some(&m), "navy.exe", 2001), Action::Nothing, "already running");
+        assert_eq!(choose(Some(&m), "navy.exe", 2004), Action::Nothing, "the off entry has its own item");
+        assert_eq!(choose(Some(&m), "navy.exe", 2099), Action::Nothing);
+        assert_eq!(choose(Some(&m), "navy.exe", ID_OFF), Action::Launch("off.exe".to_string()));
+        assert_eq!(choose(Some(&m), "navy.exe", ID_EXIT), Action::Quit);
+        assert_eq!(choose(Some(&m), "navy.exe", ID_TITLE), Action::Nothing);
+
+        let mut no_off = manifest();
+        no_off.profiles.pop();
+        assert_eq!(choose(Some(&no_off), "navy.exe", ID_OFF), Action::Quit);
+        assert_eq!(choose(None, "navy.exe", ID_OFF), Action::Quit);
+    }
+
+    #[test]
+    fn double_click_follows_the_setting() {
+        let mut m = manifest();
+        assert_eq!(double_click(Some(&m), "red.exe"), Action::Nothing);
+        m.runner.double_click = TrayAction::Off;
+        assert_eq!(double_click(Some(&m), "red.exe"), Action::Launch("off.exe".to_string()));
+        m.runner.double_click = TrayAction::Menu;
+        assert_eq!(double_click(Some(&m), "red.exe"), Action::ShowMenu);
+        m.runner.double_click = TrayAction::Profile("Green".to_string());
+        assert_eq!(double_click(Some(&m), "red.exe"), Action::Launch("green.exe".to_string()));
+        assert_eq!(double_click(Some(&m), "green.exe"), Action::Nothing);
+        assert_eq!(double_click(None, "red.exe"), Action::Nothing);
+    }
+}
@@ -0,0 +1,277 @@
+//! [`ProcessTable`] backed by Toolhelp and `OpenProcess`; [`InstanceLock`] and
+//! [`FamilyLock`] backed by session-local named mutexes.
+
+use crate::instance::{Acquire, InstanceLock, Refresh};
+use crate::switch::FamilyLock;
+use crate::siblings::{OpenError, ProcessDetails, ProcessEntry, ProcessTable, RUNNER_WINDOW_CLASS};
+use std::time::Duration;
+use windows::core::{PCWSTR, PWSTR};
+use windows::Win32::Foundation::{
+    CloseHandle, GetLastError, ERROR_ACCESS_DENIED, ERROR_ALREADY_EXISTS, ERROR_INVALID_PARAMETER, FILETIME, HANDLE, HWND, LPARAM, WAIT_OBJECT_0, WIN32_ERROR,
+    WAIT_ABANDONED_0, WPARAM,
+};
+use windows::Win32::System::DataExchange::COPYDATASTRUCT;
+use windows::Win32::System::Diagnostics::ToolHelp::*;
+use windows::Win32::System::RemoteDesktop::ProcessIdToSessionId;
+use windows::Win32::System::Threading::*;
+use windows::Win32::UI::WindowsAndMessaging::{
+    FindWindowExW, GetWindowThreadProcessId, PostMessageW, SendMessageTimeoutW, SMTO_ABORTIFHUNG, WM_CLOSE, WM_COPYDATA,
+};
+
+/// Kernel handle (process or mutex) closed on drop.
+pub struct OwnedHandle(HANDLE);
+
+impl Drop for OwnedHandle {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = CloseHandle(self.0);
+        }
+    }
+}
+
+impl OwnedHandle {
+    pub fn raw(&self) -> HANDLE {
+        self.0
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Win32ProcessTable;
+
+impl ProcessTable for Win32ProcessTable {
+    type Handle = OwnedHandle;
+
+    fn list(&mut self) -> Vec<ProcessEntry> {
+        let mut out = Vec::new();
+        unsafe {
+            let snapshot = match CreateToolhelp32Snapshot(TH32CS_SNAPPROCESS, 0) {
+                Ok(h) => h,
+                Err(_) => return out,
+            };
+            let mut entry: PROCESSENTRY32W = std::mem::zeroed();
+            entry.dwSize = std::mem::size_of::<PROCESSENTRY32W>() as u32;
+            let mut ok = Process32FirstW(snapshot, &mut entry).is_ok();
+            while ok {
+                out.push(ProcessEntry { pid: entry.th32ProcessID, image_name: wchar_to_string(&entry.szExeFile) });
+                ok = Process32NextW(snapshot, &mut entry).is_ok();
+            }
+            let _ = CloseHandle(snapshot);
+        }
+        out
+    }
+
+    fn query(&mut self, pid: u32) -> Option<ProcessDetails> {
+        unsafe {
+            let h = OwnedHandle(OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, pid).ok()?);
+            let path = image_path(h.0)?;
+            let created = creation_time(h.0)?;
+            Some(ProcessDetails { path, created, session: session_of(pid) })
+        }
+    }
+
+    fn open(&mut self, pid: u32) -> Result<OwnedHandle, OpenError> {
+        let access = PROCESS_TERMINATE | PROCESS_QUERY_LIMITED_INFORMATION | PROCESS_SYNCHRONIZE;
+        unsafe {
+            OpenProcess(access, false, pid).map(OwnedHandle).map_err(|e| {
+                let code = WIN32_ERROR::from_error(&e);
+                if code == Some(ERROR_ACCESS_DENIED) {
+                    OpenError::AccessDenied
+                } else if code == Some(ERROR_INVALID_PARAMETER) {
+                    OpenError::Gone
+                } else {
+                    OpenError::Other
+                }
+            })
+        }
+    }
+
+    fn creation_time(&mut self, handle: &OwnedHandle) -> Option<u64> {
+        unsafe { creation_time(handle.0) }
+    }
+
+    fn request_close(&mut self, pid: u32) -> bool {
+        let class = wide(RUNNER_WINDOW_CLASS);
+        let mut posted = false;
+        unsafe {
+            // Top-level windows of our class; the hidden window is not message-only
+            let mut hwnd = HWND(0);
+            loop {
+                hwnd = FindWindowExW(HWND(0), hwnd, PCWSTR(class.as_ptr()), PCWSTR::null());
+                if hwnd.0 == 0 {
+                    break;
+                }
+                let mut owner = 0u32;
+                GetWindowThreadProcessId(hwnd, Some(&mut owner));
+                if owner == pid && PostMessageW(hwnd, WM_CLOSE, WPARAM(0), LPARAM(0)).is_ok() {
+                    posted = true;
+                }
+            }
+        }
+        posted
+    }
+
+    fn wait_exit(&mut self, handle: &OwnedHandle, timeout: Duration) -> bool {
+        let ms = timeout.as_millis().min(u32::MAX as u128 - 1) as u32;
+        unsafe { WaitForSingleObject(handle.0, ms) == WAIT_OBJECT_0 }
+    }
+
+    fn terminate(&mut self, handle: &OwnedHandle) -> bool {
+        unsafe { TerminateProcess(handle.0, 0).is_ok() }
+    }
+}
+
+/// Terminal Services session of `pid`.
+pub fn session_of(pid: u32) -> Option<u32> {
+    let mut session = 0u32;
+    unsafe { ProcessIdToSessionId(pid, &mut session).ok()? };
+    Some(session)
+}
+
+/// `dwData` of the `WM_COPYDATA` a second launch sends the running instance;
+/// the data is the [`Refresh`] as JSON. The receiver answers 1 once it has taken it.
+pub const REFRESH_COPYDATA: usize = 0x4177_6363;
+
+/// How long a second launch waits for the running instance to take its refresh.
+const REFRESH_TIMEOUT_MS: u32 = 5_000;
+
+/// The refresh carried by a `WM_COPYDATA` message's `lparam`; `None` for anything else.
+///
+/// # Safety
+/// `lparam` must come from a `WM_COPYDATA` message being handled right now.
+pub unsafe fn refresh_request(lparam: LPARAM) -> Option<Refresh> {
+    let data = (lparam.0 as *const COPYDATASTRUCT).as_ref()?;
+    if data.dwData != REFRESH_COPYDATA || data.lpData.is_null() {
+        return None;
+    }
+    let bytes = std::slice::from_raw_parts(data.lpData as *const u8, data.cbData as usize);
+    serde_json::from_slice(bytes).ok()
+}
+
+/// Named mutexes in the session namespace (`Local\`). The runner's hidden window
+/// carries the same key as its title so a second launch can find it.
+#[derive(Debug, Default)]
+pub struct Win32Instances;
+
+impl InstanceLock for Win32Instances {
+    type Guard = OwnedHandle;
+
+    fn acquire(&mut self, key: &str) -> Acquire<OwnedHandle> {
+        let name = wide(&format!("Local\\{}", key));
+        unsafe {
+            match CreateMutexW(None, true, PCWSTR(name.as_ptr())) {
+                Ok(h) => {
+                    let existed = GetLastError().err().and_then(|e| WIN32_ERROR::from_error(&e)) == Some(ERROR_ALREADY_EXISTS);
+                    let h = OwnedHandle(h);
+                    if existed {
+                        Acquire::AlreadyRunning
+                    } else {
+                        Acquire::Acquired(h)
+                    }
+                }
+                // Could not create it (e.g. a same-named object we cannot open): do not block startup
+                Err(_) => Acquire::Acquired(OwnedHandle(HANDLE(0))),
+            }
+        }
+    }
+
+    fn notify_refresh(&mut self, key: &str, request: &Refresh) -> bool {
+        let class = wide(RUNNER_WINDOW_CLASS);
+        let title = wide(key);
+        let mut payload = serde_json::to_vec(request).expect("refresh serializes");
+        let data = COPYDATASTRUCT { dwData: REFRESH_COPYDATA, cbData: payload.len() as u32, lpData: payload.as_mut_ptr() as *mut _ };
+        unsafe {
+            let hwnd = FindWindowExW(HWND(0), HWND(0), PCWSTR(class.as_ptr()), PCWSTR(title.as_ptr()));
+            if hwnd.0 == 0 {
+                return false;
+            }
+            // Sent, not posted: the data only lives as long as this call
+            let mut taken = 0usize;
+            let sent = SendMessageTimeoutW(
+                hwnd,
+                WM_COPYDATA,
+                WPARAM(0),
+                LPARAM(&data as *const COPYDATASTRUCT as isize),
+                SMTO_ABORTIFHUNG,
+                REFRESH_TIMEOUT_MS,
+                Some(&mut taken),
+            );
+            sent.0 != 0 && taken == 1
+        }
+    }
+}
+
+/// Longest wait for the family switch lock; a wedged holder must not block switching forever.
+const SWITCH_LOCK_TIMEOUT_MS: u32 = 10_000;
+
+/// The family-wide switch mutex (`Local\\<family key>.switch`).
+pub struct Win32FamilyLock(OwnedHandle);
+
+impl Win32FamilyLock {
+    pub fn new(family_key: &str) -> windows::core::Result<Self> {
+        let name = wide(&format!("Local\\{}.switch", family_key));
+        unsafe { CreateMutexW(None, false, PCWSTR(name.as_ptr())).map(|h| Win32FamilyLock(OwnedHandle(h))) }
+    }
+}
+
+/// Releases the switch mutex on drop, if it was obtained.
+pub struct SwitchGuard(Option<HANDLE>);
+
+impl Drop for SwitchGuard {
+    fn drop(&mut self) {
+        if let Some(h) = self.0 {
+            unsafe {
+                let _ = ReleaseMutex(h);
+            }
+        }
+    }
+}
+
+impl SwitchGuard {
+    /// False if the wait timed out and we are running unlocked.
+    pub fn held(&self) -> bool {
+        self.0.is_some()
+    }
+}
+
+impl FamilyLock for Win32FamilyLock {
+    type Guard = SwitchGuard;
+
+    fn lock(&self) -> SwitchGuard {
+        let h = self.0.raw();
+        // An abandoned mutex (holder crashed) is still ours
+        match unsafe { WaitForSingleObject(h, SWITCH_LOCK_TIMEOUT_MS) } {
+            r if r == WAIT_OBJECT_0 || r == WAIT_ABANDONED_0 => SwitchGuard(Some(h)),
+            _ => SwitchGuard(None),
+        }
+    }
+}
+
+/// Creation time of the current process (FILETIME ticks).
+pub fn self_creation_time() -> Option<u64> {
+    unsafe { creation_time(GetCurrentProcess()) }
+}
+
+fn wide(s: &str) -> Vec<u16> {
+    s.encode_utf16().chain(std::iter::once(0)).collect()
+}
+
+unsafe fn image_path(h: HANDLE) -> Option<String> {
+    let mut buf = [0u16; 1024];
+    let mut len = buf.len() as u32;
+    QueryFullProcessImageNameW(h, PROCESS_NAME_WIN32, PWSTR(buf.as_mut_ptr()), &mut len).ok()?;
+    Some(String::from_utf16_lossy(&buf[..len as usize]))
+}
+
+unsafe fn creation_time(h: HANDLE) -> Option<u64> {
+    let mut created = FILETIME::default();
+    let mut exited = FILETIME::default();
+    let mut kernel = FILETIME::default();
+    let mut user = FILETIME::default();
+    GetProcessTimes(h, &mut created, &mut exited, &mut kernel, &mut user).ok()?;
+    Some(((created.dwHighDateTime as u64) << 32) | created.dwLowDateTime as u64)
+}
+
+fn wchar_to_string(buf: &[u16]) -> String {
+    let len = buf.iter().position(|&c| c == 0).unwrap_or(buf.len());
+    String::from_utf16_lossy(&buf[..len])
+}
@@ -1,33 +1,79 @@
 use anyhow::{bail, Context, Result};
-use clap::Parser;
-use regex::Regex;
+use clap::{Parser, Subcommand};
+use family::icon;
+use family::identity::Identity;
+use family::manifest::{DEFAULT_CLOSE_TIMEOUT_MS, DEFAULT_OFF_WAIT_MS, LEGACY_FILES, MANIFEST_FILE, MANIFEST_VERSION};
+use family::timer;
+use family::{AwccSettings, Color, Entry, Handoff, Manifest, ProfileOptions, Role, RunnerSettings, Scope, TrayAction};
 use serde::Deserialize;
 use std::collections::HashSet;
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::process::Command;
+use rsrc::{Resources, VersionInfo};
+use streamdeck::bundle;
+use streamdeck::profile::{self, Device};
+
+mod names;
+mod pe;
+mod rsrc;
+mod validate;
 
 #[derive(Debug, Parser)]
 #[command(name = "generator", about = "Generate named runner EXEs from configure.yaml")] 
 struct Opts {
-    #[arg(short, long, default_value = "configure.yaml")]
+    #[command(subcommand)]
+    command: Option<Cmd>,
+    #[arg(short, long, global = true, default_value = "configure.yaml")]
     config: PathBuf,
     #[arg(long, help = "Skip rebuilding runner (use existing target/release/runner.exe)")]
     no_build: bool,
 }
 
+#[derive(Debug, Subcommand)]
+enum Cmd {
+    /// Check configure.yaml and report every problem (exit code 1 on errors)
+    Validate,
+    /// Write the Stream Deck plugin folder (com.matsuoka-ko.awcc.sdPlugin) into the output directory
+    StreamdeckPlugin,
+    /// Write a Stream Deck profile with one Open key per EXE (import it by double-clicking)
+    StreamdeckProfile {
+        #[arg(long, default_value = "standard", help = "Key layout: mini, standard or xl")]
+        device: Device,
+        #[arg(short, long, help = "Output file (default: <output_dir>/awcc-lighting.streamDeckProfile)")]
+        out: Option<PathBuf>,
+    },
+}
+
 #[derive(Debug, Deserialize)]
 struct Config {
-    version: u32,
+    // `version` is checked by validate
     #[serde(default)]
     output_dir: Option<String>,
     #[serde(default)]
     off_name: Option<String>,
     #[serde(default)]
     awcc: Option<AwccConfig>,
+    #[serde(default)]
+    runner: Option<RunnerConfig>,
     profiles: Vec<Profile>,
 }
 
+/// Family-wide runner settings (version 2)
+#[derive(Debug, Deserialize)]
+struct RunnerConfig {
+    #[serde(default)]
+    sibling_dirs: Vec<String>,
+    #[serde(default)]
+    scope: Scope,
+    close_timeout_ms: Option<u32>,
+    off_wait_ms: Option<u32>,
+    #[serde(default)]
+    handoff: Handoff,
+    #[serde(default)]
+    double_click: TrayAction,
+}
+
 #[derive(Debug, Deserialize)]
 struct AwccConfig {
     path: String,
@@ -40,23 +86,68 @@ struct AwccConfig {
 #[derive(Debug, Deserialize)]
 struct Profile {
     name: String,
+    // version 2 metadata
+    #[serde(default)]
+    display_name: Option<String>,
+    #[serde(default)]
+    color: Option<String>,
+    #[serde(default)]
+    description: Option<String>,
+    #[serde(default)]
+    group: Option<String>,
+    #[serde(default)]
+    icon: Option<PathBuf>,
+    /// Auto-off limit such as `45m` (checked by validate)
+    #[serde(default)]
+    duration: Option<String>,
+    #[serde(default)]
+    runner: Option<ProfileRunner>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ProfileRunner {
+    #[serde(default)]
+    keep_foreground: Option<bool>,
+    #[serde(default)]
+    start_awcc: Option<bool>,
+}
+
+impl Profile {
+    /// Where the profile icon is copied to, relative to the output directory.
+    fn icon_dest(&self) -> Option<String> {
+        let src = self.icon.as_ref()?;
+        let ext = src
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase())
+            .unwrap_or_else(|| "ico".to_string());
+        Some(format!("{}/{}.{}", ICONS_DIR, self.name, ext))
+    }
 }
 
+const ICONS_DIR: &str = "icons";
+
 fn main() -> Result<()> {
     let opts = Opts::parse();
     let cfg_text = fs::read_to_string(&opts.config)
         .with_context(|| format!("failed to read {}", opts.config.display()))?;
-    let cfg: Config = serde_yaml::from_str(&cfg_text).context("invalid YAML")?;
-    if cfg.version != 1 {
-        bail!("unsupported config version: {}", cfg.version);
+    let config_dir = opts.config.parent().unwrap_or_else(|| Path::new("."));
+    let diags = validate::validate(&cfg_text, config_dir);
+    let errors = validate::error_count(&diags);
+    if !diags.is_empty() {
+        eprint!("{}", validate::render(&diags, &opts.config, &cfg_text));
     }
-
-    let name_re = Regex::new(r"^[A-Za-z0-9_-]+$").unwrap();
-    for p in &cfg.profiles {
-        if !name_re.is_match(&p.name) {
-            bail!("invalid profile name: {}", p.name);
-        }
+    if errors > 0 {
+        bail!("{} is invalid ({} error(s))", opts.config.display(), errors);
     }
+    if let Some(Cmd::Validate) = opts.command {
+        println!("{}: ok", opts.config.display());
+        return Ok(());
+    }
+    let cfg: Config = serde_yaml::from_str(&cfg_text).context("invalid YAML")?;
+    let manifest = build_manifest(&cfg);
+    manifest.validate()?;
+    let manifest_json = manifest.to_json();
 
     let out_dir = cfg.output_dir.clone().unwrap_or_else(|| "dist".to_string());
     fs::create_dir_all(&out_dir).with_context(|| format!("create dir {}", out_dir))?;
@@ -67,19 +158,22 @@ fn main() -> Result<()> {
         .join("release")
         .join(exe_name("runner"));
 
+    match &opts.command {
+        Some(Cmd::StreamdeckPlugin) => return write_streamdeck_plugin(&workspace_root, Path::new(&out_dir), opts.no_build),
+        Some(Cmd::StreamdeckProfile { device, out }) => {
+            let dist = absolute_dir(Path::new(&out_dir))?;
+            let bytes = profile::export(&manifest, &dist, *device)?;
+            let dest = out.clone().unwrap_or_else(|| Path::new(&out_dir).join(profile::DEFAULT_FILE));
+            fs::write(&dest, bytes).with_context(|| format!("write {}", dest.display()))?;
+            println!("generated: {}", dest.display());
+            return Ok(());
+        }
+        _ => {}
+    }
+
     if !opts.no_build {
         // Always build runner to pick up latest changes
-        let status = Command::new("cargo")
-            .arg("build")
-            .arg("--release")
-            .arg("-p")
-            .arg("runner")
-            .current_dir(&workspace_root)
-            .status()
-            .context("failed to spawn cargo build")?;
-        if !status.success() {
-            bail!("cargo build failed (runner)");
-        }
+        cargo_build(&workspace_root, "runner")?;
     } else if !runner_exe.exists() {
         bail!("runner executable not found: {} (remove --no-build or build manually)", runner_exe.display());
     }
@@ -96,69 +190,211 @@ fn main() -> Result<()> {
     // Remove obsolete exes that were managed in previous runs but are no longer desired
     let prev = read_prev_managed(&out_dir);
     remove_obsolete(&out_dir, &prev, &desired);
+    remove_obsolete_icons(&out_dir, &prev, &desired);
 
-    for p in &cfg.profiles {
-        let dest = Path::new(&out_dir).join(exe_name(&p.name));
+    for entry in manifest.profiles() {
+        let dest = Path::new(&out_dir).join(&entry.exe);
         fs::copy(&runner_exe, &dest)
             .with_context(|| format!("copy {} -> {}", runner_exe.display(), dest.display()))?;
+        stamp_exe(&dest, entry, &Identity::new(&manifest, &manifest_json, &entry.name))?;
         println!("generated: {}", dest.display());
     }
 
-    // Write family file listing exe names (one per line)
-    let family_path = Path::new(&out_dir).join("family.txt");
-    let mut family = String::new();
+    // Colour-swatch icons for every profile; a user icon copied below takes precedence
+    for path in write_profile_icons(Path::new(&out_dir), &manifest)? {
+        println!("generated: {}", path.display());
+    }
+
+    // Copy profile icons next to the exes (icon paths are relative to configure.yaml)
     for p in &cfg.profiles {
-        family.push_str(&exe_name(&p.name));
-        family.push('\n');
+        let (Some(src), Some(rel)) = (p.icon.as_ref(), p.icon_dest()) else { continue };
+        let src = config_dir.join(src);
+        let dest = Path::new(&out_dir).join(&rel);
+        fs::create_dir_all(Path::new(&out_dir).join(ICONS_DIR))
+            .with_context(|| format!("create dir {}/{}", out_dir, ICONS_DIR))?;
+        fs::copy(&src, &dest).with_context(|| format!("copy {} -> {}", src.display(), dest.display()))?;
+        println!("updated: {}", dest.display());
     }
-    fs::write(&family_path, family).with_context(|| format!("write {}", family_path.display()))?;
-    println!("updated: {}", family_path.display());
 
     // Optional: generate OFF exe that stops siblings then exits
-    if let Some(off) = cfg.off_name.as_ref() {
-        let off_dest = Path::new(&out_dir).join(exe_name(off));
+    if let Some(off) = manifest.off() {
+        let off_dest = Path::new(&out_dir).join(&off.exe);
         fs::copy(&runner_exe, &off_dest)
             .with_context(|| format!("copy {} -> {}", runner_exe.display(), off_dest.display()))?;
+        stamp_exe(&off_dest, off, &Identity::new(&manifest, &manifest_json, &off.name))?;
         println!("generated: {}", off_dest.display());
-        // Write off.txt with the exact EXE name
-        let off_list_path = Path::new(&out_dir).join("off.txt");
-        fs::write(&off_list_path, format!("{}\n", exe_name(off)))
-            .with_context(|| format!("write {}", off_list_path.display()))?;
-        println!("updated: {}", off_list_path.display());
-    }
-
-    // Optional: write AWCC launch settings for runner
-    if let Some(awcc) = cfg.awcc.as_ref() {
-        let awcc_path = Path::new(&out_dir).join("awcc_path.txt");
-        fs::write(&awcc_path, format!("{}\n", awcc.path))
-            .with_context(|| format!("write {}", awcc_path.display()))?;
-        println!("updated: {}", awcc_path.display());
-
-        let awcc_args = Path::new(&out_dir).join("awcc_args.txt");
-        if awcc.args.is_empty() {
-            if awcc_args.exists() {
-                let _ = fs::remove_file(&awcc_args);
-            }
-        } else {
-            let mut buf = String::new();
-            for a in &awcc.args {
-                buf.push_str(a);
-                buf.push('\n');
-            }
-            fs::write(&awcc_args, buf).with_context(|| format!("write {}", awcc_args.display()))?;
-            println!("updated: {}", awcc_args.display());
+    }
+
+    // Runtime manifest read by runner (replaces family.txt / off.txt / awcc_*.txt)
+    let manifest_path = Path::new(&out_dir).join(MANIFEST_FILE);
+    fs::write(&manifest_path, &manifest_json)
+        .with_context(|| format!("write {}", manifest_path.display()))?;
+    println!("updated: {}", manifest_path.display());
+    remove_legacy_files(&out_dir);
+
+    Ok(())
+}
+
+/// `icons/<name>-72.png`, `icons/<name>-144.png` and `icons/<name>.ico` for each
+/// profile, in its colour (or one derived from the name) with its label.
+fn write_profile_icons(out_dir: &Path, manifest: &Manifest) -> Result<Vec<PathBuf>> {
+    let dir = out_dir.join(ICONS_DIR);
+    fs::create_dir_all(&dir).with_context(|| format!("create dir {}", dir.display()))?;
+    let mut written = Vec::new();
+    for entry in manifest.profiles() {
+        let color = icon::profile_color(entry);
+        let mut files: Vec<(String, Vec<u8>)> =
+            icon::PNG_SIZES.iter().map(|&size| (icon::png_file(&entry.name, size), icon::png(color, entry.label(), size))).collect();
+        files.push((icon::ico_file(&entry.name), icon::ico(color, entry.label())));
+        for (name, bytes) in files {
+            let dest = dir.join(name);
+            fs::write(&dest, bytes).with_context(|| format!("write {}", dest.display()))?;
+            written.push(dest);
         }
+    }
+    Ok(written)
+}
+
+/// Gives the runner copy at `dest` the icon (profiles only) and version info
+/// of `entry`, so Explorer and the taskbar tell the EXEs apart, then appends
+/// `identity` so the runner knows who it is whatever the file is called. A
+/// runner the patcher cannot handle keeps its resources, with a warning.
+fn stamp_exe(dest: &Path, entry: &Entry, identity: &Identity) -> Result<()> {
+    let bytes = fs::read(dest).with_context(|| format!("read {}", dest.display()))?;
+    let mut bytes = match pe::edit_resources(&bytes, |res| exe_resources(res, entry)) {
+        Ok(patched) => patched,
+        Err(e) => {
+            eprintln!("warn: {}: resources not updated: {}", dest.display(), e);
+            bytes
+        }
+    };
+    bytes.extend_from_slice(&identity.to_trailer());
+    if let Err(e) = pe::update_checksum(&mut bytes) {
+        eprintln!("warn: {}: checksum not updated: {}", dest.display(), e);
+    }
+    fs::write(dest, bytes).with_context(|| format!("write {}", dest.display()))
+}
 
-        let awcc_min = Path::new(&out_dir).join("awcc_start_minimized.txt");
-        let start_min = awcc.start_minimized.unwrap_or(true);
-        fs::write(&awcc_min, format!("{}\n", if start_min { "true" } else { "false" }))
-            .with_context(|| format!("write {}", awcc_min.display()))?;
-        println!("updated: {}", awcc_min.display());
+fn exe_resources(res: &mut Resources, entry: &Entry) {
+    if entry.role == Role::Profile {
+        res.set_icon(&icon::ico_images(icon::profile_color(entry), entry.label()));
     }
+    let version = env!("CARGO_PKG_VERSION");
+    let mut strings = vec![
+        ("FileDescription", entry.label()),
+        ("ProductName", "awcc-ctrl-exe-moc"),
+        ("FileVersion", version),
+        ("ProductVersion", version),
+        ("InternalName", &entry.name),
+        ("OriginalFilename", &entry.exe),
+    ];
+    if let Some(description) = entry.description.as_deref() {
+        strings.push(("Comments", description));
+    }
+    res.set_version(&VersionInfo {
+        file_version: rsrc::version_numbers(version),
+        product_version: rsrc::version_numbers(version),
+        strings: strings.into_iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+    });
+}
 
+fn cargo_build(workspace_root: &Path, package: &str) -> Result<()> {
+    let status = Command::new("cargo")
+        .arg("build")
+        .arg("--release")
+        .arg("-p")
+        .arg(package)
+        .current_dir(workspace_root)
+        .status()
+        .context("failed to spawn cargo build")?;
+    if !status.success() {
+        bail!("cargo build failed ({})", package);
+    }
     Ok(())
 }
 
+/// Plugin folder next to the EXEs; it records the absolute dist path, so it
+/// keeps working when copied into the Stream Deck app's Plugins folder.
+fn write_streamdeck_plugin(workspace_root: &Path, out_dir: &Path, no_build: bool) -> Result<()> {
+    let plugin_exe = workspace_root.join("target").join("release").join(exe_name("awcc-streamdeck"));
+    if !no_build {
+        cargo_build(workspace_root, "streamdeck")?;
+    } else if !plugin_exe.exists() {
+        bail!("plugin executable not found: {} (remove --no-build or build manually)", plugin_exe.display());
+    }
+    let dist = absolute_dir(out_dir)?;
+    let dir = out_dir.join(bundle::FOLDER);
+    for path in bundle::write(&dir, &dist, env!("CARGO_PKG_VERSION")).with_context(|| format!("write {}", dir.display()))? {
+        println!("updated: {}", path.display());
+    }
+    let dest = dir.join(bundle::PLUGIN_EXE);
+    fs::copy(&plugin_exe, &dest).with_context(|| format!("copy {} -> {}", plugin_exe.display(), dest.display()))?;
+    println!("generated: {}", dest.display());
+    Ok(())
+}
+
+/// Absolute form of `dir` for files that leave the workspace (plugin, profile),
+/// without the `\\?\` prefix other programs choke on.
+fn absolute_dir(dir: &Path) -> Result<PathBuf> {
+    let path = dir.canonicalize().with_context(|| format!("resolve {}", dir.display()))?;
+    if let Some(rest) = path.to_str().and_then(|t| t.strip_prefix(r"\\?\")) {
+        return Ok(PathBuf::from(rest));
+    }
+    Ok(path)
+}
+
+fn build_manifest(cfg: &Config) -> Manifest {
+    let mut profiles: Vec<Entry> = cfg
+        .profiles
+        .iter()
+        .map(|p| {
+            let runner = p.runner.as_ref();
+            let defaults = ProfileOptions::default();
+            Entry {
+                display_name: p.display_name.clone(),
+                color: p.color.as_deref().and_then(Color::parse).map(Color::to_hex),
+                description: p.description.clone(),
+                group: p.group.clone(),
+                icon: p.icon_dest(),
+                options: ProfileOptions {
+                    keep_foreground: runner
+                        .and_then(|r| r.keep_foreground)
+                        .unwrap_or(defaults.keep_foreground),
+                    start_awcc: runner.and_then(|r| r.start_awcc).unwrap_or(defaults.start_awcc),
+                    duration_secs: p.duration.as_deref().and_then(|d| timer::parse_duration(d).ok()).map(|d| d.as_secs()),
+                },
+                ..Entry::new(&p.name, &exe_name(&p.name), Role::Profile)
+            }
+        })
+        .collect();
+    if let Some(off) = cfg.off_name.as_ref() {
+        profiles.push(Entry::new(off, &exe_name(off), Role::Off));
+    }
+    let awcc = cfg.awcc.as_ref().map(|a| AwccSettings {
+        path: a.path.clone(),
+        args: a.args.clone(),
+        start_minimized: a.start_minimized.unwrap_or(true),
+    });
+    let runner = match &cfg.runner {
+        Some(r) => RunnerSettings {
+            sibling_dirs: r.sibling_dirs.clone(),
+            scope: r.scope,
+            close_timeout_ms: r.close_timeout_ms.unwrap_or(DEFAULT_CLOSE_TIMEOUT_MS),
+            off_wait_ms: r.off_wait_ms.unwrap_or(DEFAULT_OFF_WAIT_MS),
+            handoff: r.handoff,
+            double_click: r.double_click.clone(),
+        },
+        None => RunnerSettings::default(),
+    };
+    Manifest {
+        version: MANIFEST_VERSION,
+        generator_version: Some(env!("CARGO_PKG_VERSION").to_string()),
+        profiles,
+        awcc,
+        runner,
+    }
+}
+
 fn exe_name(stem: &str) -> String {
     if cfg!(target_os = "windows") {
         format!("{}.exe", stem)
@@ -180,6 +416,11 @@ fn workspace_root() -> Result<PathBuf> {
 
 fn read_prev_managed(out_dir: &str) -> HashSet<String> {
     let mut set = HashSet::new();
+    // manifest.json lists every managed exe; an unreadable one is ignored here
+    // because it is about to be rewritten anyway
+    if let Ok(Some(m)) = Manifest::load(Path::new(out_dir)) {
+        set.extend(m.managed_exes().map(|s| s.to_string()));
+    }
     // family.txt lists the profile exes
     let fam = Path::new(out_dir).join("family.txt");
     if let Ok(s) = fs::read_to_string(&fam) {
@@ -203,6 +444,18 @@ fn read_prev_managed(out_dir: &str) -> HashSet<String> {
     set
 }
 
+fn remove_legacy_files(out_dir: &str) {
+    for name in LEGACY_FILES {
+        let path = Path::new(out_dir).join(name);
+        if path.exists() {
+            match fs::remove_file(&path) {
+                Ok(_) => println!("removed: {}", path.display()),
+                Err(e) => eprintln!("warn: could not remove {}: {}", path.display(), e),
+            }
+        }
+    }
+}
+
 fn remove_obsolete(out_dir: &str, prev: &HashSet<String>, desired: &HashSet<String>) {
     for name in prev.difference(desired) {
         let path = Path::new(out_dir).join(name);
@@ -215,6 +468,28 @@ fn remove_obsolete(out_dir: &str, prev: &HashSet<String>, desired: &HashSet<Stri
     }
 }
 
+/// Icons of the profiles whose EXEs [`remove_obsolete`] removes: the generated
+/// swatches and any copied `icon`. The runner loads `icons/<name>.ico` whenever
+/// it exists, so a profile added back later must not pick up the old one.
+fn remove_obsolete_icons(out_dir: &str, prev: &HashSet<String>, desired: &HashSet<String>) {
+    let dir = Path::new(out_dir).join(ICONS_DIR);
+    let Ok(entries) = fs::read_dir(&dir) else { return };
+    let names: Vec<&str> = prev.difference(desired).filter_map(|exe| Path::new(exe).file_stem()?.to_str()).collect();
+    for entry in entries.flatten() {
+        let file = entry.file_name().to_string_lossy().into_owned();
+        let stem = file.rsplit_once('.').map_or(file.as_str(), |(stem, _)| stem);
+        let stale = names.iter().any(|&name| stem == name || icon::PNG_SIZES.iter().any(|&size| file == icon::png_file(name, size)));
+        if !stale {
+            continue;
+        }
+        let path = entry.path();
+        match fs::remove_file(&path) {
+            Ok(_) => println!("removed: {}", path.display()),
+            Err(e) => eprintln!("warn: could not remove {}: {}", path.display(), e),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -250,6 +525,138 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn read_prev_managed_reads_manifest() -> Result<()> {
+        let out = unique_temp_dir()?;
+        let cfg: Config = serde_yaml::from_str("version: 1\noff_name: off\nprofiles:\n  - name: red\n")?;
+        fs::write(out.join(MANIFEST_FILE), build_manifest(&cfg).to_json())?;
+
+        let set = read_prev_managed(out.to_str().unwrap());
+        assert!(set.contains(&exe_name("red")));
+        assert!(set.contains(&exe_name("off")));
+        assert_eq!(set.len(), 2);
+        fs::remove_dir_all(out)?;
+        Ok(())
+    }
+
+    #[test]
+    fn profile_icons_are_written_per_profile() -> Result<()> {
+        let out = unique_temp_dir()?;
+        let yaml = "version: 2\noff_name: off\nprofiles:\n  - name: red\n    color: \"#f00\"\n    display_name: Meeting red\n  - name: navy\n";
+        let cfg: Config = serde_yaml::from_str(yaml)?;
+        let written = write_profile_icons(&out, &build_manifest(&cfg))?;
+        let names: Vec<String> = written.iter().map(|p| p.file_name().unwrap().to_string_lossy().into_owned()).collect();
+        assert_eq!(names, ["red-72.png", "red-144.png", "red.ico", "navy-72.png", "navy-144.png", "navy.ico"]);
+        let red = Color::parse("#f00").unwrap();
+        assert_eq!(fs::read(out.join(ICONS_DIR).join("red-72.png"))?, icon::png(red, "Meeting red", 72));
+        assert_eq!(fs::read(out.join(ICONS_DIR).join("navy.ico"))?, icon::ico(icon::fallback_color("navy"), "navy"));
+        fs::remove_dir_all(out)?;
+        Ok(())
+    }
+
+    #[test]
+    fn exes_are_stamped_with_resources_and_identity() -> Result<()> {
+        let out = unique_temp_dir()?;
+        let yaml = "version: 2\noff_name: off\nprofiles:\n  - name: red\n    display_name: Meeting red\n    description: Camera on\n";
+        let manifest = build_manifest(&serde_yaml::from_str(yaml)?);
+        let json = manifest.to_json();
+        for entry in &manifest.profiles {
+            let dest = out.join(&entry.exe);
+            fs::write(&dest, pe::fake::exe())?;
+            stamp_exe(&dest, entry, &Identity::new(&manifest, &json, &entry.name))?;
+        }
+        let read = |exe: &str| -> Result<Resources> {
+            let bytes = fs::read(out.join(exe))?;
+            Ok(pe::Pe::parse(&bytes)?.resources()?)
+        };
+        let red = read(&exe_name("red"))?;
+        assert!(red.get(rsrc::RT_GROUP_ICON, &rsrc::Key::Id(rsrc::ICON_GROUP_ID)).is_some());
+        let strings = rsrc::read::strings(red.get(rsrc::RT_VERSION, &rsrc::Key::Id(1)).unwrap());
+        assert!(strings.contains(&("FileDescription".to_string(), "Meeting red".to_string())));
+        assert!(strings.contains(&("ProductName".to_string(), "awcc-ctrl-exe-moc".to_string())));
+        assert!(strings.contains(&("Comments".to_string(), "Camera on".to_string())));
+        let identity = Identity::read(&out.join(exe_name("red")))?.unwrap();
+        assert_eq!(identity, Identity::new(&manifest, &json, "red"));
+        // The checksum covers the identity trailer too
+        let bytes = fs::read(out.join(exe_name("red")))?;
+        let mut summed = bytes.clone();
+        pe::update_checksum(&mut summed)?;
+        assert_eq!(summed, bytes);
+        assert_ne!(pe::Pe::parse(&bytes)?.checksum(), 0);
+        let off = read(&exe_name("off"))?;
+        assert!(off.get(rsrc::RT_GROUP_ICON, &rsrc::Key::Id(rsrc::ICON_GROUP_ID)).is_none());
+        assert!(off.get(rsrc::RT_VERSION, &rsrc::Key::Id(1)).is_some());
+
+        // Not a PE: only the identity is added
+        let junk = out.join("junk.exe");
+        fs::write(&junk, b"junk")?;
+        stamp_exe(&junk, &manifest.profiles[0], &identity)?;
+        assert_eq!(fs::read(&junk)?, [&b"junk"[..], &identity.to_trailer()].concat());
+        fs::remove_dir_all(out)?;
+        Ok(())
+    }
+
+    #[test]
+    fn build_manifest_lists_profiles_off_and_awcc() {
+        let yaml = "version: 1\noff_name: off\nawcc:\n  path: C:/AWCC.exe\nprofiles:\n  - name: red\n  - name: navy\n";
+        let cfg: Config = serde_yaml::from_str(yaml).unwrap();
+        let m = build_manifest(&cfg);
+        let names: Vec<&str> = m.profiles().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, ["red", "navy"]);
+        assert_eq!(m.off().map(|e| e.exe.clone()), Some(exe_name("off")));
+        let awcc = m.awcc.as_ref().unwrap();
+        assert!(awcc.start_minimized);
+        assert!(awcc.args.is_empty());
+        assert_eq!(Manifest::from_json(&m.to_json()).unwrap(), m);
+    }
+
+    #[test]
+    fn v2_metadata_flows_into_manifest() {
+        let yaml = r##"
+version: 2
+profiles:
+  - name: red
+    display_name: Meeting Red
+    color: "#F00"
+    description: Calls and meetings
+    group: Work
+    icon: icons/red.ico
+    duration: 1h30m
+    runner:
+      keep_foreground: true
+  - name: navy
+    color: Navy
+"##;
+        let cfg: Config = serde_yaml::from_str(yaml).unwrap();
+        let m = build_manifest(&cfg);
+        let red = &m.profiles[0];
+        assert_eq!(red.label(), "Meeting Red");
+        assert_eq!(red.color.as_deref(), Some("#ff0000"));
+        assert_eq!(red.group.as_deref(), Some("Work"));
+        assert_eq!(red.icon.as_deref(), Some("icons/red.ico"));
+        assert!(red.options.keep_foreground);
+        assert!(red.options.start_awcc);
+        assert_eq!(red.options.duration_secs, Some(5400));
+        assert_eq!(m.profiles[1].options.duration(), None);
+        assert_eq!(m.profiles[1].label(), "navy");
+        assert_eq!(m.profiles[1].color.as_deref(), Some("#000080"));
+        assert!(m.runner.is_default());
+    }
+
+    #[test]
+    fn runner_block_flows_into_manifest() {
+        let yaml = "version: 2\nrunner:\n  sibling_dirs: [D:/old-dist]\n  scope: machine\n  close_timeout_ms: 0\n  off_wait_ms: 8000\n  handoff:\n    order: start_first\n  double_click: \"off\"\nprofiles:\n  - name: red\n";
+        let cfg: Config = serde_yaml::from_str(yaml).unwrap();
+        let m = build_manifest(&cfg);
+        assert_eq!(m.runner.sibling_dirs, ["D:/old-dist"]);
+        assert_eq!(m.runner.scope, Scope::Machine);
+        assert_eq!(m.runner.close_timeout_ms, 0);
+        assert_eq!(m.runner.off_wait_ms, 8000);
+        assert_eq!(m.runner.handoff.order, family::HandoffOrder::StartFirst);
+        assert_eq!(m.runner.handoff.overlap_ms, family::manifest::DEFAULT_OVERLAP_MS);
+        assert_eq!(m.runner.double_click, TrayAction::Off);
+    }
+
     #[test]
     fn remove_obsolete_removes_missing_from_desired() -> Result<()> {
         let out = unique_temp_dir()?;
@@ -273,6 +680,27 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn remove_obsolete_icons_removes_icons_of_removed_profiles() -> Result<()> {
+        let out = unique_temp_dir()?;
+        let icons = out.join(ICONS_DIR);
+        fs::create_dir_all(&icons)?;
+        let files = ["old-72.png", "old-144.png", "old.ico", "old.png", "keep-72.png", "keep.ico", "old-fashioned.ico"];
+        for name in files {
+            fs::write(icons.join(name), b"x")?;
+        }
+
+        let prev: HashSet<String> = ["old.exe", "keep.exe"].iter().map(|s| s.to_string()).collect();
+        let desired: HashSet<String> = ["keep.exe", "old-fashioned.exe"].iter().map(|s| s.to_string()).collect();
+        remove_obsolete_icons(out.to_str().unwrap(), &prev, &desired);
+
+        let mut left: Vec<String> = fs::read_dir(&icons)?.map(|e| e.unwrap().file_name().to_string_lossy().into_owned()).collect();
+        left.sort();
+        assert_eq!(left, ["keep-72.png", "keep.ico", "old-fashioned.ico"]);
+        fs::remove_dir_all(out)?;
+        Ok(())
+    }
+
     fn unique_temp_dir() -> Result<PathBuf> {
         let base = std::env::temp_dir();
         let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos();
@@ -0,0 +1,70 @@
+//! Rules for profile and off names. Each name becomes a Windows file name and
+//! a Toolhelp image name, both of which are case-insensitive.
+
+use family::denylist;
+
+/// DOS device names Windows reserves in every directory, with or without an extension.
+const RESERVED: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM0", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9",
+    "LPT0", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+pub fn is_reserved(name: &str) -> bool {
+    RESERVED.iter().any(|r| r.eq_ignore_ascii_case(name))
+}
+
+/// Why a name would make sibling termination hit an unrelated program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shadow {
+    SystemCritical,
+    WellKnownApp,
+    UserDenylist,
+    Awcc,
+}
+
+/// Check `name` against the built-in lists, the user's `denylist` and the AWCC image stem.
+pub fn shadowed(name: &str, user_denylist: &[String], awcc_stem: Option<&str>) -> Option<Shadow> {
+    if denylist::is_system_critical(name) {
+        Some(Shadow::SystemCritical)
+    } else if awcc_stem.is_some_and(|a| denylist::stem_key(a) == denylist::stem_key(name)) {
+        Some(Shadow::Awcc)
+    } else if denylist::is_well_known_app(name) {
+        Some(Shadow::WellKnownApp)
+    } else if user_denylist.iter().any(|d| denylist::stem_key(d) == denylist::stem_key(name)) {
+        Some(Shadow::UserDenylist)
+    } else {
+        None
+    }
+}
+
+/// Key under which two names refer to the same EXE on Windows.
+pub fn fold(name: &str) -> String {
+    name.to_ascii_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shadowed_checks_builtin_user_and_awcc_names() {
+        let user = vec!["MyGame.EXE".to_string(), "tool".to_string(), "Other.Exe".to_string()];
+        assert_eq!(shadowed("Explorer", &user, None), Some(Shadow::SystemCritical));
+        assert_eq!(shadowed("chrome", &user, None), Some(Shadow::WellKnownApp));
+        assert_eq!(shadowed("mygame", &user, None), Some(Shadow::UserDenylist));
+        assert_eq!(shadowed("TOOL", &user, None), Some(Shadow::UserDenylist));
+        assert_eq!(shadowed("other", &user, None), Some(Shadow::UserDenylist));
+        assert_eq!(shadowed("awcc_ui", &user, Some("AWCC_UI")), Some(Shadow::Awcc));
+        assert_eq!(shadowed("navy", &user, Some("AWCC")), None);
+    }
+
+    #[test]
+    fn reserved_names_match_case_insensitively() {
+        for n in ["CON", "con", "Nul", "COM1", "lpt9", "aux"] {
+            assert!(is_reserved(n), "{}", n);
+        }
+        for n in ["console", "com10", "red", "nul_", "LPT"] {
+            assert!(!is_reserved(n), "{}", n);
+        }
+    }
+}
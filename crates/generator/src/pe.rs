@@ -0,0 +1,356 @@
+//! Just enough PE/COFF to give each generated EXE its own resources.
+//!
+//! The runner image is left as it is; the new resource tree goes into an extra
+//! `.rsrc` section at the end of the file and the resource data directory is
+//! pointed at it. Resources the image already had are carried over.
+
+use crate::rsrc::Resources;
+use std::fmt;
+
+const PE_SIGNATURE: &[u8; 4] = b"PE\0\0";
+const PE32_MAGIC: u16 = 0x10b;
+const PE32_PLUS_MAGIC: u16 = 0x20b;
+const SECTION_HEADER_SIZE: usize = 40;
+const DIR_RESOURCE: usize = 2;
+/// Authenticode signature: it would no longer match, so such files are refused.
+const DIR_SECURITY: usize = 4;
+/// Initialised data, readable.
+const RSRC_CHARACTERISTICS: u32 = 0x4000_0040;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PeError {
+    Invalid(String),
+    Signed,
+    /// The headers have no space for one more section header.
+    NoRoom,
+}
+
+impl fmt::Display for PeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PeError::Invalid(msg) => write!(f, "not a usable PE file: {}", msg),
+            PeError::Signed => write!(f, "the executable is signed; patching would invalidate the signature"),
+            PeError::NoRoom => write!(f, "no room for another section header"),
+        }
+    }
+}
+
+impl std::error::Error for PeError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Section {
+    pub name: [u8; 8],
+    pub virtual_size: u32,
+    pub virtual_address: u32,
+    pub raw_size: u32,
+    pub raw_offset: u32,
+}
+
+/// Parsed headers of a PE image.
+#[derive(Debug)]
+pub struct Pe<'a> {
+    bytes: &'a [u8],
+    coff: usize,
+    optional: usize,
+    data_dirs: usize,
+    data_dir_count: usize,
+    section_table: usize,
+    pub sections: Vec<Section>,
+    pub section_alignment: u32,
+    pub file_alignment: u32,
+    pub size_of_headers: u32,
+}
+
+fn u16_at(b: &[u8], at: usize) -> Result<u16, PeError> {
+    b.get(at..at + 2).map(|s| u16::from_le_bytes([s[0], s[1]])).ok_or_else(|| PeError::Invalid("truncated".to_string()))
+}
+
+fn u32_at(b: &[u8], at: usize) -> Result<u32, PeError> {
+    b.get(at..at + 4)
+        .map(|s| u32::from_le_bytes([s[0], s[1], s[2], s[3]]))
+        .ok_or_else(|| PeError::Invalid("truncated".to_string()))
+}
+
+fn put_u16(b: &mut [u8], at: usize, v: u16) {
+    b[at..at + 2].copy_from_slice(&v.to_le_bytes());
+}
+
+fn put_u32(b: &mut [u8], at: usize, v: u32) {
+    b[at..at + 4].copy_from_slice(&v.to_le_bytes());
+}
+
+impl<'a> Pe<'a> {
+    pub fn parse(bytes: &'a [u8]) -> Result<Pe<'a>, PeError> {
+        if bytes.get(..2) != Some(b"MZ") {
+            return Err(PeError::Invalid("no MZ header".to_string()));
+        }
+        let pe = u32_at(bytes, 0x3c)? as usize;
+        if bytes.get(pe..pe + 4) != Some(PE_SIGNATURE) {
+            return Err(PeError::Invalid("no PE signature".to_string()));
+        }
+        let coff = pe + 4;
+        let section_count = u16_at(bytes, coff + 2)? as usize;
+        let optional_size = u16_at(bytes, coff + 16)? as usize;
+        let optional = coff + 20;
+        let (data_dirs, count_at) = match u16_at(bytes, optional)? {
+            PE32_MAGIC => (optional + 96, optional + 92),
+            PE32_PLUS_MAGIC => (optional + 112, optional + 108),
+            magic => return Err(PeError::Invalid(format!("unknown optional header magic {:#x}", magic))),
+        };
+        let data_dir_count = u32_at(bytes, count_at)? as usize;
+        if data_dir_count <= DIR_SECURITY || data_dirs + 8 * data_dir_count > optional + optional_size {
+            return Err(PeError::Invalid("too few data directories".to_string()));
+        }
+        let section_table = optional + optional_size;
+        let mut sections = Vec::with_capacity(section_count);
+        for i in 0..section_count {
+            let at = section_table + SECTION_HEADER_SIZE * i;
+            let name = bytes.get(at..at + 8).ok_or_else(|| PeError::Invalid("truncated".to_string()))?;
+            sections.push(Section {
+                name: name.try_into().unwrap(),
+                virtual_size: u32_at(bytes, at + 8)?,
+                virtual_address: u32_at(bytes, at + 12)?,
+                raw_size: u32_at(bytes, at + 16)?,
+                raw_offset: u32_at(bytes, at + 20)?,
+            });
+        }
+        Ok(Pe {
+            bytes,
+            coff,
+            optional,
+            data_dirs,
+            data_dir_count,
+            section_table,
+            sections,
+            section_alignment: u32_at(bytes, optional + 32)?,
+            file_alignment: u32_at(bytes, optional + 36)?,
+            size_of_headers: u32_at(bytes, optional + 60)?,
+        })
+    }
+
+    /// RVA and size of data directory `index`.
+    pub fn data_dir(&self, index: usize) -> (u32, u32) {
+        if index >= self.data_dir_count {
+            return (0, 0);
+        }
+        let at = self.data_dirs + 8 * index;
+        (u32_at(self.bytes, at).unwrap_or(0), u32_at(self.bytes, at + 4).unwrap_or(0))
+    }
+
+    /// Offset of the CheckSum field.
+    fn checksum_offset(&self) -> usize {
+        self.optional + 64
+    }
+
+    /// The stored checksum.
+    #[cfg(test)]
+    pub fn checksum(&self) -> u32 {
+        u32_at(self.bytes, self.checksum_offset()).unwrap_or(0)
+    }
+
+    /// The section that maps `rva`.
+    fn section_of(&self, rva: u32) -> Option<&Section> {
+        self.sections
+            .iter()
+            .find(|s| rva >= s.virtual_address && rva < s.virtual_address + s.virtual_size.max(s.raw_size))
+    }
+
+    /// The resource tree, empty when the image has none.
+    pub fn resources(&self) -> Result<Resources, PeError> {
+        let (rva, size) = self.data_dir(DIR_RESOURCE);
+        if rva == 0 || size == 0 {
+            return Ok(Resources::default());
+        }
+        let section = self.section_of(rva).ok_or_else(|| PeError::Invalid("resource directory is not mapped".to_string()))?;
+        let start = section.raw_offset as usize;
+        let raw = self
+            .bytes
+            .get(start..start + section.raw_size as usize)
+            .ok_or_else(|| PeError::Invalid("resource section is truncated".to_string()))?;
+        Resources::decode(raw, section.virtual_address, rva).map_err(PeError::Invalid)
+    }
+}
+
+/// PE checksum of `bytes`, skipping the CheckSum field at `field`.
+pub fn checksum(bytes: &[u8], field: usize) -> u32 {
+    let mut sum: u64 = 0;
+    for (i, word) in bytes.chunks(2).enumerate() {
+        if i * 2 == field || i * 2 == field + 2 {
+            continue;
+        }
+        sum += u16::from_le_bytes([word[0], word.get(1).copied().unwrap_or(0)]) as u64;
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    sum = (sum & 0xffff) + (sum >> 16);
+    sum as u32 + bytes.len() as u32
+}
+
+/// `exe` with its resources passed through `edit`, in a new last section.
+/// Everything else keeps its offset, so symbols appended after the sections
+/// stay valid.
+pub fn edit_resources(exe: &[u8], edit: impl FnOnce(&mut Resources)) -> Result<Vec<u8>, PeError> {
+    let pe = Pe::parse(exe)?;
+    if pe.data_dir(DIR_SECURITY).1 != 0 {
+        return Err(PeError::Signed);
+    }
+    let mut resources = pe.resources()?;
+    edit(&mut resources);
+
+    let header_at = pe.section_table + SECTION_HEADER_SIZE * pe.sections.len();
+    let first_raw = pe.sections.iter().filter(|s| s.raw_size > 0).map(|s| s.raw_offset as usize).min();
+    let limit = first_raw.unwrap_or(usize::MAX).min(pe.size_of_headers as usize);
+    if header_at + SECTION_HEADER_SIZE > limit {
+        return Err(PeError::NoRoom);
+    }
+
+    let image_end = pe.sections.iter().map(|s| s.virtual_address + s.virtual_size.max(s.raw_size)).max().unwrap_or(pe.size_of_headers);
+    let rva = image_end.next_multiple_of(pe.section_alignment);
+    let data = resources.encode(rva);
+    let raw_offset = exe.len().next_multiple_of(pe.file_alignment as usize);
+    let raw_size = data.len().next_multiple_of(pe.file_alignment as usize);
+
+    let mut out = exe.to_vec();
+    out.resize(raw_offset, 0);
+    out.extend_from_slice(&data);
+    out.resize(raw_offset + raw_size, 0);
+
+    let mut header = [0u8; SECTION_HEADER_SIZE];
+    header[..6].copy_from_slice(b".rsrc\0");
+    put_u32(&mut header, 8, data.len() as u32);
+    put_u32(&mut header, 12, rva);
+    put_u32(&mut header, 16, raw_size as u32);
+    put_u32(&mut header, 20, raw_offset as u32);
+    put_u32(&mut header, 36, RSRC_CHARACTERISTICS);
+    out[header_at..header_at + SECTION_HEADER_SIZE].copy_from_slice(&header);
+
+    put_u16(&mut out, pe.coff + 2, pe.sections.len() as u16 + 1);
+    let initialized = u32_at(&out, pe.optional + 8)?;
+    put_u32(&mut out, pe.optional + 8, initialized + raw_size as u32);
+    put_u32(&mut out, pe.optional + 56, (rva + data.len() as u32).next_multiple_of(pe.section_alignment));
+    put_u32(&mut out, pe.data_dirs + 8 * DIR_RESOURCE, rva);
+    put_u32(&mut out, pe.data_dirs + 8 * DIR_RESOURCE + 4, data.len() as u32);
+    Ok(out)
+}
+
+/// Recomputes the CheckSum field over all of `exe`, so call it after the
+/// last byte is appended.
+pub fn update_checksum(exe: &mut [u8]) -> Result<(), PeError> {
+    let field = Pe::parse(exe)?.checksum_offset();
+    put_u32(exe, field, 0);
+    let sum = checksum(exe, field);
+    put_u32(exe, field, sum);
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod fake {
+    //! Minimal PE32+ images for tests.
+
+    /// Headers padded to 0x400 and one `.text` section of 0x200 bytes.
+    pub fn exe() -> Vec<u8> {
+        let mut b = vec![0u8; 0x600];
+        b[..2].copy_from_slice(b"MZ");
+        b[0x3c..0x40].copy_from_slice(&0x80u32.to_le_bytes());
+        b[0x80..0x84].copy_from_slice(b"PE\0\0");
+        let coff = 0x84;
+        b[coff..coff + 2].copy_from_slice(&0x8664u16.to_le_bytes());
+        b[coff + 2..coff + 4].copy_from_slice(&1u16.to_le_bytes());
+        b[coff + 16..coff + 18].copy_from_slice(&240u16.to_le_bytes());
+        b[coff + 18..coff + 20].copy_from_slice(&0x22u16.to_le_bytes()); // executable, large address aware
+        let opt = coff + 20;
+        let put = |b: &mut Vec<u8>, at: usize, v: u32| b[opt + at..opt + at + 4].copy_from_slice(&v.to_le_bytes());
+        b[opt..opt + 2].copy_from_slice(&0x20bu16.to_le_bytes());
+        put(&mut b, 4, 0x200); // size of code
+        put(&mut b, 16, 0x1000); // entry point
+        put(&mut b, 32, 0x1000); // section alignment
+        put(&mut b, 36, 0x200); // file alignment
+        put(&mut b, 56, 0x2000); // size of image
+        put(&mut b, 60, 0x400); // size of headers
+        b[opt + 68..opt + 70].copy_from_slice(&2u16.to_le_bytes()); // GUI subsystem
+        put(&mut b, 108, 16); // data directories
+        let text = opt + 240;
+        b[text..text + 5].copy_from_slice(b".text");
+        b[text + 8..text + 12].copy_from_slice(&0x10u32.to_le_bytes());
+        b[text + 12..text + 16].copy_from_slice(&0x1000u32.to_le_bytes());
+        b[text + 16..text + 20].copy_from_slice(&0x200u32.to_le_bytes());
+        b[text + 20..text + 24].copy_from_slice(&0x400u32.to_le_bytes());
+        b[text + 36..text + 40].copy_from_slice(&0x6000_0020u32.to_le_bytes());
+        b[0x400..0x410].copy_from_slice(&[0xc3; 16]);
+        b
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rsrc::{Key, VersionInfo, LANG_EN_US, RT_GROUP_ICON, RT_ICON, RT_VERSION};
+
+    fn version(description: &str) -> VersionInfo {
+        VersionInfo {
+            file_version: [1, 0, 0, 0],
+            product_version: [1, 0, 0, 0],
+            strings: vec![("FileDescription".to_string(), description.to_string())],
+        }
+    }
+
+    #[test]
+    fn resources_go_into_a_new_section() {
+        let exe = fake::exe();
+        assert!(Pe::parse(&exe).unwrap().resources().unwrap().entries.is_empty());
+        // Something after the last section, like the COFF symbols of a GNU build
+        let mut with_tail = exe.clone();
+        with_tail.extend_from_slice(b"symbols");
+
+        let patched = edit_resources(&with_tail, |r| {
+            r.set_icon(&[(16, vec![1; 100])]);
+            r.set_version(&version("Meeting red"));
+        })
+        .unwrap();
+        let pe = Pe::parse(&patched).unwrap();
+        assert_eq!(pe.sections.len(), 2);
+        let rsrc = &pe.sections[1];
+        assert_eq!(&rsrc.name, b".rsrc\0\0\0");
+        assert_eq!(rsrc.virtual_address, 0x2000);
+        assert_eq!(rsrc.raw_offset % 0x200, 0);
+        assert!(rsrc.raw_offset as usize >= with_tail.len());
+        assert_eq!(pe.data_dir(DIR_RESOURCE).0, 0x2000);
+        assert_eq!(u32_at(&patched, pe.optional + 56).unwrap(), 0x3000, "size of image");
+        assert_eq!(&patched[..with_tail.len()][0x400..], &with_tail[0x400..], "sections and tail untouched");
+
+        let res = pe.resources().unwrap();
+        assert_eq!(res.get(RT_ICON, &Key::Id(1)), Some(&[1u8; 100][..]));
+        assert!(res.get(RT_GROUP_ICON, &Key::Id(1)).is_some());
+        let info = res.get(RT_VERSION, &Key::Id(1)).unwrap();
+        assert_eq!(crate::rsrc::read::strings(info), version("Meeting red").strings);
+    }
+
+    #[test]
+    fn patching_again_replaces_and_keeps_other_resources() {
+        let first = edit_resources(&fake::exe(), |r| {
+            r.insert(24, Key::Id(1), LANG_EN_US, b"<assembly/>".to_vec());
+            r.set_version(&version("red"));
+        })
+        .unwrap();
+        let second = edit_resources(&first, |r| r.set_version(&version("navy"))).unwrap();
+        let res = Pe::parse(&second).unwrap().resources().unwrap();
+        assert_eq!(res.entries.len(), 2);
+        assert_eq!(res.get(24, &Key::Id(1)), Some(&b"<assembly/>"[..]));
+        assert_eq!(crate::rsrc::read::strings(res.get(RT_VERSION, &Key::Id(1)).unwrap()), version("navy").strings);
+    }
+
+    #[test]
+    fn refuses_what_it_cannot_patch() {
+        assert!(matches!(Pe::parse(b"not an exe"), Err(PeError::Invalid(_))));
+        let mut signed = fake::exe();
+        let opt = 0x84 + 20;
+        signed[opt + 112 + 8 * DIR_SECURITY + 4] = 8;
+        assert_eq!(edit_resources(&signed, |_| {}).unwrap_err(), PeError::Signed);
+        // Headers full up to the first section
+        let mut full = fake::exe();
+        full[opt + 60..opt + 64].copy_from_slice(&0x1f0u32.to_le_bytes());
+        full[0x84 + 2] = 8; // eight section headers do not fit before 0x1f0
+        assert_eq!(edit_resources(&full, |_| {}).unwrap_err(), PeError::NoRoom);
+        // The checksum field itself is skipped; the file length is added
+        assert_eq!(checksum(&[1, 0, 2, 0, 0xff, 0xff, 9, 9, 9, 9], 4), 1 + 2 + 0x0909 + 10);
+    }
+}
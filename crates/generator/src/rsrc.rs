@@ -0,0 +1,427 @@
+//! Win32 resources: the `.rsrc` directory tree, icon groups and VERSIONINFO.
+//!
+//! The tree has the usual three levels (type, name, language). Directory
+//! offsets are relative to the start of the tree; data entries hold RVAs.
+
+use std::collections::BTreeMap;
+
+pub const RT_ICON: u16 = 3;
+pub const RT_GROUP_ICON: u16 = 14;
+pub const RT_VERSION: u16 = 16;
+/// English (United States), the language `rc` uses by default.
+pub const LANG_EN_US: u16 = 0x0409;
+/// UTF-16, the code page of the version strings.
+const CODEPAGE_UNICODE: u16 = 1200;
+/// Resource ID of the application icon (Explorer shows the lowest group).
+pub const ICON_GROUP_ID: u16 = 1;
+const VERSION_ID: u16 = 1;
+
+/// Type or name of a resource. Named entries sort before numbered ones, as
+/// the format requires.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Key {
+    Name(String),
+    Id(u16),
+}
+
+/// Resources keyed by (type, name, language).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Resources {
+    pub entries: BTreeMap<(Key, Key, u16), Vec<u8>>,
+}
+
+impl Resources {
+    pub fn insert(&mut self, kind: u16, name: Key, lang: u16, data: Vec<u8>) {
+        self.entries.insert((Key::Id(kind), name, lang), data);
+    }
+
+    /// Drop every resource of type `kind`.
+    pub fn remove_kind(&mut self, kind: u16) {
+        self.entries.retain(|(k, _, _), _| *k != Key::Id(kind));
+    }
+
+    /// Data of `kind`/`name` in any language.
+    #[cfg(test)]
+    pub fn get(&self, kind: u16, name: &Key) -> Option<&[u8]> {
+        self.entries.iter().find(|((k, n, _), _)| *k == Key::Id(kind) && n == name).map(|(_, d)| d.as_slice())
+    }
+
+    /// Replace the application icon with `images` (size, PNG or DIB).
+    pub fn set_icon(&mut self, images: &[(u32, Vec<u8>)]) {
+        self.remove_kind(RT_ICON);
+        self.remove_kind(RT_GROUP_ICON);
+        let mut group = Vec::with_capacity(6 + 14 * images.len());
+        group.extend_from_slice(&[0, 0, 1, 0]); // reserved, type: icon
+        group.extend_from_slice(&(images.len() as u16).to_le_bytes());
+        for (i, (size, data)) in images.iter().enumerate() {
+            let id = i as u16 + 1;
+            let dim = if *size >= 256 { 0 } else { *size as u8 };
+            group.extend_from_slice(&[dim, dim, 0, 0]);
+            group.extend_from_slice(&1u16.to_le_bytes()); // planes
+            group.extend_from_slice(&32u16.to_le_bytes()); // bits per pixel
+            group.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            group.extend_from_slice(&id.to_le_bytes());
+            self.insert(RT_ICON, Key::Id(id), LANG_EN_US, data.clone());
+        }
+        self.insert(RT_GROUP_ICON, Key::Id(ICON_GROUP_ID), LANG_EN_US, group);
+    }
+
+    /// Replace the version information.
+    pub fn set_version(&mut self, info: &VersionInfo) {
+        self.remove_kind(RT_VERSION);
+        self.insert(RT_VERSION, Key::Id(VERSION_ID), LANG_EN_US, info.encode());
+    }
+
+    /// The tree as it is laid out at `rva`.
+    pub fn encode(&self, rva: u32) -> Vec<u8> {
+        // type -> name -> language
+        type Names<'a> = Vec<(&'a Key, Vec<(u16, &'a Vec<u8>)>)>;
+        let mut tree: Vec<(&Key, Names)> = Vec::new();
+        for ((kind, name, lang), data) in &self.entries {
+            if tree.last().is_none_or(|(k, _)| *k != kind) {
+                tree.push((kind, Vec::new()));
+            }
+            let names = &mut tree.last_mut().unwrap().1;
+            if names.last().is_none_or(|(n, _)| *n != name) {
+                names.push((name, Vec::new()));
+            }
+            names.last_mut().unwrap().1.push((*lang, data));
+        }
+
+        // Sizes first: directories, then data entries, then names, then data
+        let dir_size = |entries: usize| 16 + 8 * entries;
+        let mut tables = dir_size(tree.len());
+        for (_, names) in &tree {
+            tables += dir_size(names.len());
+            tables += names.iter().map(|(_, langs)| dir_size(langs.len())).sum::<usize>();
+        }
+        let data_entries = tables;
+        let mut strings = data_entries + 16 * self.entries.len();
+        let (mut string_at, mut string_order) = (BTreeMap::new(), Vec::new());
+        for key in tree.iter().flat_map(|(k, names)| std::iter::once(*k).chain(names.iter().map(|(n, _)| *n))) {
+            if let Key::Name(s) = key {
+                if !string_at.contains_key(s.as_str()) {
+                    string_at.insert(s.as_str(), strings);
+                    string_order.push(s.as_str());
+                    strings += 2 + 2 * s.encode_utf16().count();
+                }
+            }
+        }
+        let mut data_at = strings.next_multiple_of(8);
+
+        let mut out = Vec::new();
+        let mut next_table = dir_size(tree.len());
+        let mut next_entry = data_entries;
+        let mut blobs = Vec::new();
+        let key_field = |key: &Key| match key {
+            Key::Name(s) => 0x8000_0000 | string_at[s.as_str()] as u32,
+            Key::Id(id) => *id as u32,
+        };
+        let directory = |out: &mut Vec<u8>, keys: &[&Key]| {
+            let named = keys.iter().filter(|k| matches!(k, Key::Name(_))).count();
+            out.extend_from_slice(&[0; 12]); // characteristics, time stamp, version
+            out.extend_from_slice(&(named as u16).to_le_bytes());
+            out.extend_from_slice(&((keys.len() - named) as u16).to_le_bytes());
+        };
+
+        // Level 1: types, each pointing at its name directory
+        directory(&mut out, &tree.iter().map(|(k, _)| *k).collect::<Vec<_>>());
+        for (kind, names) in &tree {
+            out.extend_from_slice(&key_field(kind).to_le_bytes());
+            out.extend_from_slice(&(0x8000_0000 | next_table as u32).to_le_bytes());
+            next_table += dir_size(names.len());
+        }
+        // Level 2: names, each pointing at its language directory
+        for (_, names) in &tree {
+            directory(&mut out, &names.iter().map(|(n, _)| *n).collect::<Vec<_>>());
+            for (name, langs) in names {
+                out.extend_from_slice(&key_field(name).to_le_bytes());
+                out.extend_from_slice(&(0x8000_0000 | next_table as u32).to_le_bytes());
+                next_table += dir_size(langs.len());
+            }
+        }
+        // Level 3: languages, each pointing at a data entry
+        for (_, names) in &tree {
+            for (_, langs) in names {
+                let keys: Vec<Key> = langs.iter().map(|(l, _)| Key::Id(*l)).collect();
+                directory(&mut out, &keys.iter().collect::<Vec<_>>());
+                for (lang, data) in langs {
+                    out.extend_from_slice(&(*lang as u32).to_le_bytes());
+                    out.extend_from_slice(&(next_entry as u32).to_le_bytes());
+                    next_entry += 16;
+                    blobs.push((data_at, *data));
+                    data_at = (data_at + data.len()).next_multiple_of(8);
+                }
+            }
+        }
+        debug_assert_eq!(out.len(), data_entries);
+        for (at, data) in &blobs {
+            out.extend_from_slice(&(rva + *at as u32).to_le_bytes());
+            out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            out.extend_from_slice(&[0; 8]); // code page, reserved
+        }
+        for s in &string_order {
+            out.extend_from_slice(&(s.encode_utf16().count() as u16).to_le_bytes());
+            out.extend(s.encode_utf16().flat_map(u16::to_le_bytes));
+        }
+        for (at, data) in &blobs {
+            out.resize(*at, 0);
+            out.extend_from_slice(data);
+        }
+        out.resize(out.len().next_multiple_of(8), 0);
+        out
+    }
+
+    /// Read the tree at `dir_rva` from `section`, the raw bytes of the section
+    /// mapped at `section_rva`.
+    pub fn decode(section: &[u8], section_rva: u32, dir_rva: u32) -> Result<Resources, String> {
+        let root = dir_rva.checked_sub(section_rva).ok_or("resource directory outside its section")? as usize;
+        let u16_at = |at: usize| section.get(at..at + 2).map(|b| u16::from_le_bytes([b[0], b[1]])).ok_or("truncated resource directory");
+        let u32_at = |at: usize| {
+            section.get(at..at + 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]])).ok_or("truncated resource directory")
+        };
+        // (key, offset from the root, is a directory)
+        let entries = |dir: usize| -> Result<Vec<(Key, usize, bool)>, String> {
+            let count = u16_at(root + dir + 12)? as usize + u16_at(root + dir + 14)? as usize;
+            let mut out = Vec::with_capacity(count);
+            for i in 0..count {
+                let at = root + dir + 16 + 8 * i;
+                let (name, target) = (u32_at(at)?, u32_at(at + 4)?);
+                let key = if name & 0x8000_0000 != 0 {
+                    let s = root + (name & 0x7fff_ffff) as usize;
+                    let len = u16_at(s)? as usize;
+                    let units: Vec<u16> = (0..len).map(|j| u16_at(s + 2 + 2 * j)).collect::<Result<_, _>>()?;
+                    Key::Name(String::from_utf16_lossy(&units))
+                } else {
+                    Key::Id(name as u16)
+                };
+                out.push((key, (target & 0x7fff_ffff) as usize, target & 0x8000_0000 != 0));
+            }
+            Ok(out)
+        };
+
+        let mut res = Resources::default();
+        for (kind, names_at, dir) in entries(0)? {
+            if !dir {
+                return Err("resource type without a directory".to_string());
+            }
+            for (name, langs_at, dir) in entries(names_at)? {
+                if !dir {
+                    return Err("resource name without a directory".to_string());
+                }
+                for (lang, entry_at, dir) in entries(langs_at)? {
+                    let Key::Id(lang) = lang else { return Err("named resource language".to_string()) };
+                    if dir {
+                        return Err("resource tree deeper than three levels".to_string());
+                    }
+                    let data_rva = u32_at(root + entry_at)?;
+                    let size = u32_at(root + entry_at + 4)? as usize;
+                    let start = data_rva.checked_sub(section_rva).ok_or("resource data outside its section")? as usize;
+                    let data = section.get(start..start + size).ok_or("resource data outside its section")?;
+                    res.entries.insert((kind.clone(), name.clone(), lang), data.to_vec());
+                }
+            }
+        }
+        Ok(res)
+    }
+}
+
+/// Contents of the VERSIONINFO resource.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VersionInfo {
+    pub file_version: [u16; 4],
+    pub product_version: [u16; 4],
+    /// StringFileInfo values (`FileDescription`, `ProductName`, ...).
+    pub strings: Vec<(String, String)>,
+}
+
+/// `1.2.3` as `[1, 2, 3, 0]`; missing or unparsable parts are 0.
+pub fn version_numbers(version: &str) -> [u16; 4] {
+    let mut out = [0; 4];
+    for (slot, part) in out.iter_mut().zip(version.split(['.', '-', '+'])) {
+        *slot = part.parse().unwrap_or(0);
+    }
+    out
+}
+
+impl VersionInfo {
+    /// The VS_VERSIONINFO block.
+    pub fn encode(&self) -> Vec<u8> {
+        let pair = |v: [u16; 4]| [(v[0] as u32) << 16 | v[1] as u32, (v[2] as u32) << 16 | v[3] as u32];
+        let mut fixed = Vec::with_capacity(52);
+        let [file_ms, file_ls] = pair(self.file_version);
+        let [product_ms, product_ls] = pair(self.product_version);
+        for v in [
+            0xfeef_04bd, // signature
+            0x0001_0000, // structure version
+            file_ms,
+            file_ls,
+            product_ms,
+            product_ls,
+            0x3f,      // flags mask
+            0,         // flags
+            0x0004_0004, // VOS_NT_WINDOWS32
+            1,         // VFT_APP
+            0,         // subtype
+            0,         // date
+            0,
+        ] {
+            fixed.extend_from_slice(&u32::to_le_bytes(v));
+        }
+
+        let table_key = format!("{:04x}{:04x}", LANG_EN_US, CODEPAGE_UNICODE);
+        let strings: Vec<Vec<u8>> = self.strings.iter().map(|(k, v)| node(k, Value::Text(v), &[])).collect();
+        let string_info = node("StringFileInfo", Value::None, &[node(&table_key, Value::None, &strings)]);
+        let translation = [LANG_EN_US.to_le_bytes(), CODEPAGE_UNICODE.to_le_bytes()].concat();
+        let var_info = node("VarFileInfo", Value::None, &[node("Translation", Value::Binary(&translation), &[])]);
+        node("VS_VERSION_INFO", Value::Binary(&fixed), &[string_info, var_info])
+    }
+}
+
+enum Value<'a> {
+    None,
+    Text(&'a str),
+    Binary(&'a [u8]),
+}
+
+/// One version block: length, value length, type, key, value and children,
+/// each part aligned to 32 bits.
+fn node(key: &str, value: Value, children: &[Vec<u8>]) -> Vec<u8> {
+    let utf16 = |s: &str| s.encode_utf16().chain([0]).flat_map(u16::to_le_bytes).collect::<Vec<u8>>();
+    let (value, value_len, text) = match value {
+        Value::None => (Vec::new(), 0, true),
+        Value::Text(s) => (utf16(s), s.encode_utf16().count() + 1, true),
+        Value::Binary(b) => (b.to_vec(), b.len(), false),
+    };
+    let mut out = vec![0, 0];
+    out.extend_from_slice(&(value_len as u16).to_le_bytes());
+    out.extend_from_slice(&(text as u16).to_le_bytes());
+    out.extend_from_slice(&utf16(key));
+    if !value.is_empty() {
+        out.resize(out.len().next_multiple_of(4), 0);
+        out.extend_from_slice(&value);
+    }
+    for child in children {
+        out.resize(out.len().next_multiple_of(4), 0);
+        out.extend_from_slice(child);
+    }
+    let len = out.len() as u16;
+    out[..2].copy_from_slice(&len.to_le_bytes());
+    out
+}
+
+#[cfg(test)]
+pub(crate) mod read {
+    //! Version block reader for checking what [`VersionInfo::encode`](super::VersionInfo::encode) wrote.
+
+    /// Key, value bytes (text without its terminator) and children of the block at the start of `b`.
+    pub fn node(b: &[u8]) -> (String, Vec<u8>, Vec<&[u8]>) {
+        let len = u16::from_le_bytes([b[0], b[1]]) as usize;
+        let value_len = u16::from_le_bytes([b[2], b[3]]) as usize;
+        let text = u16::from_le_bytes([b[4], b[5]]) == 1;
+        let mut at = 6;
+        let mut key = Vec::new();
+        loop {
+            let unit = u16::from_le_bytes([b[at], b[at + 1]]);
+            at += 2;
+            if unit == 0 {
+                break;
+            }
+            key.push(unit);
+        }
+        at = at.next_multiple_of(4);
+        let value_bytes = if text { value_len.saturating_sub(1) * 2 } else { value_len };
+        let value = b[at..at + value_bytes].to_vec();
+        if value_len > 0 {
+            at = (at + if text { value_len * 2 } else { value_len }).next_multiple_of(4);
+        }
+        let mut children = Vec::new();
+        while at < len {
+            let child_len = u16::from_le_bytes([b[at], b[at + 1]]) as usize;
+            children.push(&b[at..at + child_len]);
+            at = (at + child_len).next_multiple_of(4);
+        }
+        (String::from_utf16_lossy(&key), value, children)
+    }
+
+    /// StringFileInfo values of a VS_VERSIONINFO block.
+    pub fn strings(version: &[u8]) -> Vec<(String, String)> {
+        let (_, _, children) = node(version);
+        let (_, _, tables) = node(children[0]);
+        let (_, _, strings) = node(tables[0]);
+        strings
+            .iter()
+            .map(|s| {
+                let (k, v, _) = node(s);
+                let units: Vec<u16> = v.chunks(2).map(|c| u16::from_le_bytes([c[0], c[1]])).collect();
+                (k, String::from_utf16_lossy(&units))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tree_round_trips_with_names_and_ids() {
+        let mut res = Resources::default();
+        res.insert(24, Key::Id(1), LANG_EN_US, b"<assembly/>".to_vec());
+        res.insert(10, Key::Name("CONFIG".to_string()), 0, vec![1, 2, 3]);
+        res.insert(10, Key::Id(7), 0, vec![4; 9]);
+        res.entries.insert((Key::Name("PNG".to_string()), Key::Id(1), 0x0411), vec![5; 20]);
+        let bytes = res.encode(0x5000);
+        assert_eq!(bytes.len() % 8, 0);
+        // Root directory: one named type, two numbered ones
+        assert_eq!(&bytes[12..16], &[1, 0, 2, 0]);
+        assert_eq!(Resources::decode(&bytes, 0x5000, 0x5000).unwrap(), res);
+        // A tree that starts part-way into its section
+        let mut section = vec![0xcc; 0x40];
+        section.extend_from_slice(&res.encode(0x5040));
+        assert_eq!(Resources::decode(&section, 0x5000, 0x5040).unwrap(), res);
+        assert!(Resources::decode(&bytes[..20], 0x5000, 0x5000).is_err());
+    }
+
+    #[test]
+    fn icon_group_lists_each_image() {
+        let mut res = Resources::default();
+        res.insert(RT_ICON, Key::Id(9), 0, vec![0; 4]);
+        res.set_icon(&[(16, vec![1; 10]), (256, vec![2; 30])]);
+        assert_eq!(res.get(RT_ICON, &Key::Id(9)), None, "old icons are dropped");
+        assert_eq!(res.get(RT_ICON, &Key::Id(2)), Some(&[2u8; 30][..]));
+        let group = res.get(RT_GROUP_ICON, &Key::Id(ICON_GROUP_ID)).unwrap();
+        assert_eq!(&group[..6], &[0, 0, 1, 0, 2, 0]);
+        assert_eq!(group[6], 16);
+        assert_eq!(u32::from_le_bytes(group[14..18].try_into().unwrap()), 10);
+        assert_eq!(group[20], 0, "256 is written as 0");
+        assert_eq!(u16::from_le_bytes([group[32], group[33]]), 2);
+    }
+
+    #[test]
+    fn version_info_blocks_parse_back() {
+        let info = VersionInfo {
+            file_version: version_numbers("1.2.3"),
+            product_version: version_numbers("1.2.3-beta"),
+            strings: vec![
+                ("FileDescription".to_string(), "会議（赤）".to_string()),
+                ("ProductName".to_string(), "awcc-ctrl-exe-moc".to_string()),
+                ("Comments".to_string(), String::new()),
+            ],
+        };
+        assert_eq!(info.product_version, [1, 2, 3, 0]);
+        let block = info.encode();
+        let (key, fixed, children) = read::node(&block);
+        assert_eq!(key, "VS_VERSION_INFO");
+        assert_eq!(fixed.len(), 52);
+        assert_eq!(u32::from_le_bytes(fixed[..4].try_into().unwrap()), 0xfeef_04bd);
+        assert_eq!(u32::from_le_bytes(fixed[8..12].try_into().unwrap()), 0x0001_0002);
+        assert_eq!(u32::from_le_bytes(fixed[12..16].try_into().unwrap()), 0x0003_0000);
+        assert_eq!(children.len(), 2);
+        assert_eq!(read::node(read::node(children[0]).2[0]).0, "040904b0");
+        assert_eq!(read::strings(&block), info.strings);
+        let (var_key, _, vars) = read::node(children[1]);
+        assert_eq!(var_key, "VarFileInfo");
+        assert_eq!(read::node(vars[0]).1, [0x09, 0x04, 0xb0, 0x04]);
+    }
+}
@@ -0,0 +1,801 @@
+//! `generator validate`: check configure.yaml and report every problem at once,
+//! each with its line/column and a source snippet.
+//!
+//! serde silently ignores unknown keys and stops at the first error, so the
+//! document is walked as a position-annotated YAML tree instead.
+
+use crate::names::{self, Shadow};
+use family::denylist;
+use family::manifest::TRAY_ACTION_KEYWORDS;
+use family::timer;
+use family::Color;
+use regex::Regex;
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::path::Path;
+use yaml_rust2::parser::{Event, MarkedEventReceiver, Parser};
+use yaml_rust2::scanner::{Marker, TScalarStyle};
+
+pub const NAME_PATTERN: &str = r"^[A-Za-z0-9_-]+$";
+pub const SUPPORTED_VERSIONS: [u32; 2] = [1, 2];
+
+const ROOT_KEYS: &[&str] = &["version", "output_dir", "off_name", "awcc", "denylist", "runner", "profiles"];
+const ROOT_V2_KEYS: &[&str] = &["denylist", "runner"];
+const RUNNER_KEYS: &[&str] = &["sibling_dirs", "scope", "close_timeout_ms", "off_wait_ms", "handoff", "double_click"];
+const SCOPES: &[&str] = &["session", "machine"];
+const HANDOFF_KEYS: &[&str] = &["order", "overlap_ms"];
+const HANDOFF_ORDERS: &[&str] = &["stop_first", "start_first"];
+const AWCC_KEYS: &[&str] = &["path", "args", "start_minimized"];
+const PROFILE_KEYS: &[&str] = &["name", "display_name", "color", "description", "group", "icon", "duration", "runner"];
+const PROFILE_V2_KEYS: &[&str] = &["display_name", "color", "description", "group", "icon", "duration", "runner"];
+const PROFILE_RUNNER_KEYS: &[&str] = &["keep_foreground", "start_awcc"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// 1-based line and column of the offending token, plus its width for the underline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pos {
+    pub line: usize,
+    pub col: usize,
+    pub len: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub pos: Option<Pos>,
+    pub help: Option<String>,
+}
+
+impl Diagnostic {
+    fn error(pos: Option<Pos>, message: impl Into<String>) -> Diagnostic {
+        Diagnostic { severity: Severity::Error, message: message.into(), pos, help: None }
+    }
+
+    fn warning(pos: Option<Pos>, message: impl Into<String>) -> Diagnostic {
+        Diagnostic { severity: Severity::Warning, message: message.into(), pos, help: None }
+    }
+
+    fn with_help(mut self, help: impl Into<String>) -> Diagnostic {
+        self.help = Some(help.into());
+        self
+    }
+}
+
+pub fn error_count(diags: &[Diagnostic]) -> usize {
+    diags.iter().filter(|d| d.severity == Severity::Error).count()
+}
+
+/// Validate configure.yaml `text`. `config_dir` resolves relative `icon` paths.
+pub fn validate(text: &str, config_dir: &Path) -> Vec<Diagnostic> {
+    let root = match parse_tree(text) {
+        Ok(Some(root)) => root,
+        Ok(None) => return vec![Diagnostic::error(None, "configuration is empty")],
+        Err(d) => return vec![d],
+    };
+    let mut v = Validator { diags: Vec::new(), config_dir, denylist: Vec::new(), awcc_stem: None };
+    v.check_root(&root);
+    v.diags
+}
+
+/// Render diagnostics in a compiler-like layout for the terminal.
+pub fn render(diags: &[Diagnostic], path: &Path, text: &str) -> String {
+    let lines: Vec<&str> = text.lines().collect();
+    let mut out = String::new();
+    for d in diags {
+        let label = match d.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        let _ = writeln!(out, "{}: {}", label, d.message);
+        match d.pos {
+            Some(pos) => {
+                let gutter = pos.line.to_string().len();
+                let _ = writeln!(out, "{:>w$}--> {}:{}:{}", "", path.display(), pos.line, pos.col, w = gutter);
+                if let Some(src) = lines.get(pos.line - 1) {
+                    let _ = writeln!(out, "{:>w$} |", "", w = gutter);
+                    let _ = writeln!(out, "{} | {}", pos.line, src);
+                    let _ = writeln!(
+                        out,
+                        "{:>w$} | {}{}",
+                        "",
+                        " ".repeat(pos.col.saturating_sub(1)),
+                        "^".repeat(pos.len.max(1)),
+                        w = gutter
+                    );
+                }
+                if let Some(help) = &d.help {
+                    let _ = writeln!(out, "{:>w$} = help: {}", "", help, w = gutter);
+                }
+            }
+            None => {
+                let _ = writeln!(out, " --> {}", path.display());
+                if let Some(help) = &d.help {
+                    let _ = writeln!(out, "  = help: {}", help);
+                }
+            }
+        }
+        out.push('\n');
+    }
+    let errors = error_count(diags);
+    let warnings = diags.len() - errors;
+    let _ = writeln!(out, "{}: {} error(s), {} warning(s)", path.display(), errors, warnings);
+    out
+}
+
+struct Validator<'a> {
+    diags: Vec<Diagnostic>,
+    config_dir: &'a Path,
+    /// Extra process names from the `denylist` key.
+    denylist: Vec<String>,
+    /// Image stem of `awcc.path`, which no profile may reuse.
+    awcc_stem: Option<String>,
+}
+
+impl Validator<'_> {
+    fn check_root(&mut self, root: &Node) {
+        let Some(map) = self.expect_map(root, "the top level") else { return };
+        self.check_keys(map, ROOT_KEYS, "top-level");
+
+        let version = match lookup(map, "version") {
+            Some((_, v)) => self.expect_u32(v, "version"),
+            None => {
+                self.diags.push(Diagnostic::error(Some(root.pos), "missing required field `version`"));
+                None
+            }
+        };
+        if let (Some(ver), Some((_, node))) = (version, lookup(map, "version")) {
+            if !SUPPORTED_VERSIONS.contains(&ver) {
+                self.diags.push(
+                    Diagnostic::error(Some(node.pos), format!("unsupported config version: {}", ver))
+                        .with_help("supported versions are 1 and 2"),
+                );
+            }
+        }
+
+        if version.unwrap_or(1) < 2 {
+            self.check_v2_keys(map, ROOT_V2_KEYS);
+        }
+        if let Some((_, v)) = lookup(map, "output_dir") {
+            self.expect_str(v, "output_dir");
+        }
+        if let Some((_, v)) = lookup(map, "awcc") {
+            self.check_awcc(v);
+        }
+        if let Some((_, v)) = lookup(map, "denylist") {
+            let names = self.expect_str_list(v, "denylist");
+            self.denylist.extend(names);
+        }
+        if let Some((_, v)) = lookup(map, "runner") {
+            self.check_runner(v);
+        }
+        let off = lookup(map, "off_name").and_then(|(_, v)| self.expect_str(v, "off_name").map(|s| (s, v.pos)));
+        let off_ok = off.as_ref().is_some_and(|(name, pos)| self.check_name(name, *pos, "off_name"));
+
+        let Some((_, profiles)) = lookup(map, "profiles") else {
+            self.diags.push(Diagnostic::error(Some(root.pos), "missing required field `profiles`"));
+            return;
+        };
+        let Kind::Seq(items) = &profiles.kind else {
+            self.diags.push(Diagnostic::error(Some(profiles.pos), "`profiles` must be a list"));
+            return;
+        };
+
+        if items.is_empty() {
+            self.diags.push(Diagnostic::warning(Some(profiles.pos), "`profiles` is empty; no profile EXEs will be generated"));
+        }
+
+        // Keyed by the case-folded name: NTFS and Toolhelp treat `Red` and `red` as one EXE.
+        let mut seen: HashMap<String, (String, Pos)> = HashMap::new();
+        for item in items {
+            let Some((name, pos)) = self.check_profile(item, version.unwrap_or(1)) else { continue };
+            if !self.check_name(&name, pos, "profile name") {
+                continue;
+            }
+            if let Some((first, first_pos)) = seen.get(&names::fold(&name)) {
+                let d = if *first == name {
+                    Diagnostic::error(Some(pos), format!("duplicate profile name `{}`", name))
+                        .with_help(format!("first defined at line {}", first_pos.line))
+                } else {
+                    Diagnostic::error(Some(pos), format!("profile name `{}` collides with `{}`", name, first))
+                        .with_help(format!(
+                            "Windows file and process names are case-insensitive, so both map to the same EXE (first defined at line {})",
+                            first_pos.line
+                        ))
+                };
+                self.diags.push(d);
+                continue;
+            }
+            seen.insert(names::fold(&name), (name.clone(), pos));
+            if let Some((off, _)) = off.as_ref().filter(|_| off_ok) {
+                if *off == name {
+                    self.diags.push(
+                        Diagnostic::error(Some(pos), format!("profile `{}` has the same name as off_name", name))
+                            .with_help("the off EXE and a profile EXE would overwrite each other; rename one"),
+                    );
+                } else if names::fold(off) == names::fold(&name) {
+                    self.diags.push(
+                        Diagnostic::error(Some(pos), format!("profile `{}` collides with off_name `{}`", name, off))
+                            .with_help("Windows file and process names are case-insensitive, so both map to the same EXE"),
+                    );
+                }
+            }
+        }
+        self.check_double_click(map, &seen);
+    }
+
+    /// `runner.double_click` is a keyword or the name of a profile.
+    fn check_double_click(&mut self, root: &[(Node, Node)], profiles: &HashMap<String, (String, Pos)>) {
+        let Some((_, Node { kind: Kind::Map(runner), .. })) = lookup(root, "runner") else { return };
+        let Some((_, v @ Node { kind: Kind::Scalar(value), null: false, .. })) = lookup(runner, "double_click") else { return };
+        if TRAY_ACTION_KEYWORDS.contains(&value.as_str()) || profiles.contains_key(&names::fold(value)) {
+            return;
+        }
+        let mut d = Diagnostic::error(Some(v.pos), format!("`runner.double_click` names unknown profile `{}`", value));
+        let candidates = TRAY_ACTION_KEYWORDS.iter().copied().chain(profiles.values().map(|(name, _)| name.as_str()));
+        d = match suggest(value, candidates) {
+            Some(s) => d.with_help(format!("did you mean `{}`?", s)),
+            None => d.with_help("use `none`, `menu`, `off` or the name of a profile"),
+        };
+        self.diags.push(d);
+    }
+
+    /// Pattern and Windows reserved-name checks shared by profiles and `off_name`.
+    fn check_name(&mut self, name: &str, pos: Pos, what: &str) -> bool {
+        if !Regex::new(NAME_PATTERN).unwrap().is_match(name) {
+            self.diags.push(
+                Diagnostic::error(Some(pos), format!("invalid {}: {:?}", what, name))
+                    .with_help(format!("names must match {}", NAME_PATTERN)),
+            );
+            return false;
+        }
+        if names::is_reserved(name) {
+            self.diags.push(
+                Diagnostic::error(Some(pos), format!("`{}` is a reserved device name on Windows", name))
+                    .with_help("Windows cannot create or launch an EXE with this name; choose another"),
+            );
+            return false;
+        }
+        let exe = format!("{}.exe", name);
+        let shadow = names::shadowed(name, &self.denylist, self.awcc_stem.as_deref()).map(|s| match s {
+            Shadow::SystemCritical => Diagnostic::error(Some(pos), format!("`{}` is a system-critical Windows process", name))
+                .with_help(format!("the runner never terminates {}, so this profile could not be switched off; rename it", exe)),
+            Shadow::Awcc => Diagnostic::error(Some(pos), format!("`{}` is the AWCC executable from awcc.path", name))
+                .with_help("every profile switch would terminate AWCC itself; rename the profile"),
+            Shadow::WellKnownApp => Diagnostic::error(Some(pos), format!("`{}` would shadow the application {}", name, exe))
+                .with_help(format!("every profile switch terminates all processes named {}; rename the profile", exe)),
+            Shadow::UserDenylist => Diagnostic::error(Some(pos), format!("`{}` is listed in `denylist`", name))
+                .with_help(format!("every profile switch terminates all processes named {}; rename the profile", exe)),
+        });
+        if let Some(d) = shadow {
+            self.diags.push(d);
+            return false;
+        }
+        true
+    }
+
+    fn check_runner(&mut self, node: &Node) {
+        let Some(map) = self.expect_map(node, "`runner`") else { return };
+        self.check_keys(map, RUNNER_KEYS, "runner");
+        if let Some((_, v)) = lookup(map, "sibling_dirs") {
+            self.expect_str_list(v, "runner.sibling_dirs");
+        }
+        if let Some((_, v)) = lookup(map, "scope") {
+            self.expect_choice(v, "runner.scope", SCOPES);
+        }
+        for key in ["close_timeout_ms", "off_wait_ms"] {
+            if let Some((_, v)) = lookup(map, key) {
+                self.expect_u32(v, &format!("runner.{}", key));
+            }
+        }
+        if let Some((_, v)) = lookup(map, "double_click") {
+            self.expect_str(v, "runner.double_click");
+        }
+        if let Some((_, v)) = lookup(map, "handoff") {
+            let Some(handoff) = self.expect_map(v, "`runner.handoff`") else { return };
+            self.check_keys(handoff, HANDOFF_KEYS, "runner.handoff");
+            if let Some((_, v)) = lookup(handoff, "order") {
+                self.expect_choice(v, "runner.handoff.order", HANDOFF_ORDERS);
+            }
+            if let Some((_, v)) = lookup(handoff, "overlap_ms") {
+                self.expect_u32(v, "runner.handoff.overlap_ms");
+            }
+        }
+    }
+
+    fn check_awcc(&mut self, node: &Node) {
+        let Some(map) = self.expect_map(node, "`awcc`") else { return };
+        self.check_keys(map, AWCC_KEYS, "awcc");
+        match lookup(map, "path") {
+            Some((_, v)) => {
+                if let Some(p) = self.expect_str(v, "awcc.path") {
+                    if p.trim().is_empty() {
+                        self.diags.push(Diagnostic::error(Some(v.pos), "awcc.path is empty"));
+                    }
+                    // awcc.path is a Windows path; split on both separators regardless of host
+                    let file = p.trim().rsplit(['/', '\\']).next().unwrap_or("");
+                    let stem = denylist::stem_key(file);
+                    if !stem.is_empty() {
+                        self.awcc_stem = Some(stem);
+                    }
+                }
+            }
+            None => self.diags.push(Diagnostic::error(Some(node.pos), "missing required field `awcc.path`")),
+        }
+        if let Some((_, v)) = lookup(map, "args") {
+            self.expect_str_list(v, "awcc.args");
+        }
+        if let Some((_, v)) = lookup(map, "start_minimized") {
+            self.expect_bool(v, "awcc.start_minimized");
+        }
+    }
+
+    /// Check one `profiles` item; returns its name for the cross-profile checks.
+    fn check_profile(&mut self, node: &Node, version: u32) -> Option<(String, Pos)> {
+        let map = self.expect_map(node, "a profile")?;
+        self.check_keys(map, PROFILE_KEYS, "profile");
+
+        if version < 2 {
+            self.check_v2_keys(map, PROFILE_V2_KEYS);
+        }
+        for key in ["display_name", "description", "group"] {
+            if let Some((_, v)) = lookup(map, key) {
+                self.expect_str(v, key);
+            }
+        }
+        if let Some((_, v)) = lookup(map, "color") {
+            if let Some(c) = self.expect_str(v, "color") {
+                if Color::parse(&c).is_none() {
+                    let mut d = Diagnostic::error(Some(v.pos), format!("invalid color {:?}", c));
+                    d = match suggest(&c.to_ascii_lowercase(), Color::css_names()) {
+                        Some(s) => d.with_help(format!("did you mean `{}`?", s)),
+                        None => d.with_help("use #rgb, #rrggbb or a CSS color name"),
+                    };
+                    self.diags.push(d);
+                }
+            }
+        }
+        if let Some((_, v)) = lookup(map, "icon") {
+            if let Some(icon) = self.expect_str(v, "icon") {
+                if !self.config_dir.join(&icon).is_file() {
+                    self.diags.push(
+                        Diagnostic::error(Some(v.pos), format!("icon file not found: {}", icon))
+                            .with_help("icon paths are relative to the configuration file"),
+                    );
+                }
+            }
+        }
+        if let Some((_, v)) = lookup(map, "duration") {
+            if let Some(d) = self.expect_str(v, "duration") {
+                if let Err(e) = timer::parse_duration(&d) {
+                    self.diags.push(Diagnostic::error(Some(v.pos), e).with_help("for example `45m`, `1h30m` or `90s`"));
+                }
+            }
+        }
+        if let Some((_, v)) = lookup(map, "runner") {
+            if let Some(runner) = self.expect_map(v, "`runner`") {
+                self.check_keys(runner, PROFILE_RUNNER_KEYS, "profile runner");
+                for key in PROFILE_RUNNER_KEYS {
+                    if let Some((_, b)) = lookup(runner, key) {
+                        self.expect_bool(b, key);
+                    }
+                }
+            }
+        }
+
+        match lookup(map, "name") {
+            Some((_, v)) => self.expect_str(v, "name").map(|s| (s, v.pos)),
+            None => {
+                self.diags.push(Diagnostic::error(Some(node.pos), "profile is missing required field `name`"));
+                None
+            }
+        }
+    }
+
+    fn check_v2_keys(&mut self, map: &[(Node, Node)], v2_keys: &[&str]) {
+        for (k, _) in map {
+            if let Kind::Scalar(key) = &k.kind {
+                if v2_keys.contains(&key.as_str()) {
+                    self.diags.push(
+                        Diagnostic::error(Some(k.pos), format!("`{}` requires `version: 2`", key))
+                            .with_help("set `version: 2` at the top of the file"),
+                    );
+                }
+            }
+        }
+    }
+
+    /// Flag unknown and duplicated keys of a mapping.
+    fn check_keys(&mut self, map: &[(Node, Node)], known: &[&str], what: &str) {
+        let mut seen: HashMap<&str, usize> = HashMap::new();
+        for (k, _) in map {
+            let Kind::Scalar(key) = &k.kind else {
+                self.diags.push(Diagnostic::error(Some(k.pos), "mapping keys must be plain strings"));
+                continue;
+            };
+            if let Some(line) = seen.get(key.as_str()) {
+                self.diags.push(
+                    Diagnostic::error(Some(k.pos), format!("duplicate key `{}`", key))
+                        .with_help(format!("first defined at line {}", line)),
+                );
+            }
+            seen.insert(key, k.pos.line);
+            if !known.contains(&key.as_str()) {
+                let d = Diagnostic::error(Some(k.pos), format!("unknown {} field `{}`", what, key));
+                self.diags.push(match suggest(key, known.iter().copied()) {
+                    Some(s) => d.with_help(format!("did you mean `{}`?", s)),
+                    None => d.with_help(format!("expected one of: {}", known.join(", "))),
+                });
+            }
+        }
+    }
+
+    fn expect_map<'n>(&mut self, node: &'n Node, what: &str) -> Option<&'n [(Node, Node)]> {
+        match &node.kind {
+            Kind::Map(m) => Some(m),
+            _ => {
+                self.diags.push(Diagnostic::error(Some(node.pos), format!("{} must be a mapping", what)));
+                None
+            }
+        }
+    }
+
+    fn expect_str(&mut self, node: &Node, what: &str) -> Option<String> {
+        match &node.kind {
+            Kind::Scalar(s) if !node.null => Some(s.clone()),
+            _ => {
+                self.diags.push(Diagnostic::error(Some(node.pos), format!("`{}` must be a string", what)));
+                None
+            }
+        }
+    }
+
+    fn expect_choice(&mut self, node: &Node, what: &str, choices: &[&str]) -> Option<String> {
+        let value = self.expect_str(node, what)?;
+        if choices.contains(&value.as_str()) {
+            return Some(value);
+        }
+        let mut d = Diagnostic::error(
+            Some(node.pos),
+            format!("`{}` must be one of {}", what, choices.iter().map(|c| format!("`{}`", c)).collect::<Vec<_>>().join(", ")),
+        );
+        if let Some(s) = suggest(&value, choices.iter().copied()) {
+            d = d.with_help(format!("did you mean `{}`?", s));
+        }
+        self.diags.push(d);
+        None
+    }
+
+    fn expect_str_list(&mut self, node: &Node, what: &str) -> Vec<String> {
+        match &node.kind {
+            Kind::Seq(items) => items.iter().filter_map(|i| self.expect_str(i, &format!("{} item", what))).collect(),
+            _ => {
+                self.diags.push(Diagnostic::error(Some(node.pos), format!("`{}` must be a list of strings", what)));
+                Vec::new()
+            }
+        }
+    }
+
+    fn expect_u32(&mut self, node: &Node, what: &str) -> Option<u32> {
+        match &node.kind {
+            Kind::Scalar(s) if !node.quoted => match s.parse::<u32>() {
+                Ok(v) => Some(v),
+                Err(_) => {
+                    self.diags.push(Diagnostic::error(Some(node.pos), format!("`{}` must be a number", what)));
+                    None
+                }
+            },
+            _ => {
+                self.diags.push(Diagnostic::error(Some(node.pos), format!("`{}` must be a number", what)));
+                None
+            }
+        }
+    }
+
+    fn expect_bool(&mut self, node: &Node, what: &str) -> Option<bool> {
+        match &node.kind {
+            Kind::Scalar(s) if !node.quoted && (s == "true" || s == "false") => Some(s == "true"),
+            _ => {
+                self.diags.push(
+                    Diagnostic::error(Some(node.pos), format!("`{}` must be true or false", what)),
+                );
+                None
+            }
+        }
+    }
+}
+
+fn lookup<'n>(map: &'n [(Node, Node)], key: &str) -> Option<(&'n Node, &'n Node)> {
+    map.iter()
+        .find(|(k, _)| matches!(&k.kind, Kind::Scalar(s) if s == key))
+        .map(|(k, v)| (k, v))
+}
+
+/// Closest candidate by Jaro-Winkler similarity, if it is plausibly a typo.
+pub fn suggest<'c>(input: &str, candidates: impl Iterator<Item = &'c str>) -> Option<&'c str> {
+    candidates
+        .map(|c| (strsim::jaro_winkler(input, c), c))
+        .filter(|(score, _)| *score >= 0.85)
+        .max_by(|a, b| a.0.total_cmp(&b.0))
+        .map(|(_, c)| c)
+}
+
+// --- position-annotated YAML tree -----------------------------------------
+
+#[derive(Debug)]
+struct Node {
+    kind: Kind,
+    pos: Pos,
+    quoted: bool,
+    null: bool,
+}
+
+#[derive(Debug)]
+enum Kind {
+    Scalar(String),
+    Seq(Vec<Node>),
+    Map(Vec<(Node, Node)>),
+    Alias,
+}
+
+fn parse_tree(text: &str) -> Result<Option<Node>, Diagnostic> {
+    let mut builder = TreeBuilder::default();
+    let mut parser = Parser::new_from_str(text);
+    if let Err(e) = parser.load(&mut builder, false) {
+        let m = e.marker();
+        let pos = Pos { line: m.line(), col: m.col() + 1, len: 1 };
+        return Err(Diagnostic::error(Some(pos), format!("invalid YAML: {}", e.info())));
+    }
+    Ok(builder.root)
+}
+
+enum Frame {
+    Seq(Pos, Vec<Node>),
+    Map(Pos, Vec<(Node, Node)>, Option<Node>),
+}
+
+#[derive(Default)]
+struct TreeBuilder {
+    stack: Vec<Frame>,
+    root: Option<Node>,
+}
+
+impl TreeBuilder {
+    fn push_node(&mut self, node: Node) {
+        match self.stack.last_mut() {
+            Some(Frame::Seq(_, items)) => items.push(node),
+            Some(Frame::Map(_, entries, pending)) => match pending.take() {
+                Some(key) => entries.push((key, node)),
+                None => *pending = Some(node),
+            },
+            None => {
+                if self.root.is_none() {
+                    self.root = Some(node);
+                }
+            }
+        }
+    }
+}
+
+impl MarkedEventReceiver for TreeBuilder {
+    fn on_event(&mut self, ev: Event, mark: Marker) {
+        let pos = Pos { line: mark.line(), col: mark.col() + 1, len: 1 };
+        match ev {
+            Event::Scalar(value, style, _, _) => {
+                let quoted = !matches!(style, TScalarStyle::Plain);
+                let null = !quoted && matches!(value.as_str(), "" | "~" | "null" | "Null" | "NULL");
+                let len = value.chars().count() + if quoted { 2 } else { 0 };
+                let pos = Pos { len: len.max(1), ..pos };
+                self.push_node(Node { kind: Kind::Scalar(value), pos, quoted, null });
+            }
+            Event::SequenceStart(..) => self.stack.push(Frame::Seq(pos, Vec::new())),
+            Event::MappingStart(..) => self.stack.push(Frame::Map(pos, Vec::new(), None)),
+            Event::SequenceEnd | Event::MappingEnd => {
+                let node = match self.stack.pop() {
+                    Some(Frame::Seq(pos, items)) => Node { kind: Kind::Seq(items), pos, quoted: false, null: false },
+                    Some(Frame::Map(pos, entries, _)) => {
+                        Node { kind: Kind::Map(entries), pos, quoted: false, null: false }
+                    }
+                    None => return,
+                };
+                self.push_node(node);
+            }
+            Event::Alias(_) => self.push_node(Node { kind: Kind::Alias, pos, quoted: false, null: false }),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(yaml: &str) -> Vec<Diagnostic> {
+        validate(yaml, Path::new("."))
+    }
+
+    fn messages(diags: &[Diagnostic]) -> Vec<String> {
+        diags.iter().map(|d| d.message.clone()).collect()
+    }
+
+    #[test]
+    fn accepts_repository_config() {
+        let text = std::fs::read_to_string(concat!(env!("CARGO_MANIFEST_DIR"), "/../../configure.yaml")).unwrap();
+        let diags = check(&text);
+        assert!(diags.is_empty(), "{:?}", messages(&diags));
+    }
+
+    #[test]
+    fn reports_every_problem_with_positions() {
+        let yaml = "\
+version: 1
+ouput_dir: dist
+off_name: red
+profiles:
+  - name: red
+  - name: blue
+  - name: red
+  - name: bad name
+";
+        let diags = check(yaml);
+        assert_eq!(error_count(&diags), 4, "{:?}", messages(&diags));
+
+        let unknown = &diags[0];
+        assert_eq!(unknown.message, "unknown top-level field `ouput_dir`");
+        assert_eq!(unknown.pos, Some(Pos { line: 2, col: 1, len: 9 }));
+        assert_eq!(unknown.help.as_deref(), Some("did you mean `output_dir`?"));
+
+        let msgs = messages(&diags);
+        assert!(msgs.contains(&"profile `red` has the same name as off_name".to_string()));
+        let dup = diags.iter().find(|d| d.message == "duplicate profile name `red`").unwrap();
+        assert_eq!(dup.pos.map(|p| (p.line, p.col)), Some((7, 11)));
+        assert_eq!(dup.help.as_deref(), Some("first defined at line 5"));
+        assert!(msgs.contains(&"invalid profile name: \"bad name\"".to_string()));
+    }
+
+    #[test]
+    fn rejects_reserved_and_case_colliding_names() {
+        let yaml = "\
+version: 1
+off_name: Off
+profiles:
+  - name: CON
+  - name: red
+  - name: Red
+  - name: off
+  - name: lpt1
+";
+        let diags = check(yaml);
+        let msgs = messages(&diags);
+        assert_eq!(
+            msgs,
+            [
+                "`CON` is a reserved device name on Windows",
+                "profile name `Red` collides with `red`",
+                "profile `off` collides with off_name `Off`",
+                "`lpt1` is a reserved device name on Windows",
+            ]
+        );
+        assert_eq!(diags[1].pos.map(|p| p.line), Some(6));
+        assert!(diags[1].help.as_deref().unwrap().contains("first defined at line 5"));
+
+        let diags = check("version: 1\noff_name: nul\nprofiles:\n  - name: red\n");
+        assert_eq!(messages(&diags), ["`nul` is a reserved device name on Windows"]);
+    }
+
+    #[test]
+    fn rejects_names_that_shadow_real_processes() {
+        let yaml = r#"
+version: 2
+off_name: explorer
+awcc:
+  path: "C:\\Program Files\\Alienware\\AWCC\\AWCC.Exe"
+denylist: [MyGame.EXE, Tool.Exe]
+profiles:
+  - name: Chrome
+  - name: mygame
+  - name: tool
+  - name: awcc
+  - name: navy
+"#;
+        let diags = check(yaml);
+        assert_eq!(
+            messages(&diags),
+            [
+                "`explorer` is a system-critical Windows process",
+                "`Chrome` would shadow the application Chrome.exe",
+                "`mygame` is listed in `denylist`",
+                "`tool` is listed in `denylist`",
+                "`awcc` is the AWCC executable from awcc.path",
+            ]
+        );
+
+        let diags = check("version: 1\ndenylist: [foo]\nprofiles:\n  - name: red\n");
+        assert_eq!(messages(&diags), ["`denylist` requires `version: 2`"]);
+
+        let diags = check("version: 2\ndenylist: tool\nrunner:\n  sibling_dirs: [1, D:/dist]\n  sibling_dir: x\nprofiles:\n  - name: red\n");
+        assert_eq!(
+            messages(&diags),
+            [
+                "`denylist` must be a list of strings",
+                "unknown runner field `sibling_dir`",
+            ]
+        );
+
+        let diags = check("version: 2\nrunner:\n  scope: machin\n  close_timeout_ms: 2s\n  handoff:\n    order: start-first\n    overlap: 500\nprofiles:\n  - name: red\n");
+        assert_eq!(
+            messages(&diags),
+            [
+                "`runner.scope` must be one of `session`, `machine`",
+                "`runner.close_timeout_ms` must be a number",
+                "unknown runner.handoff field `overlap`",
+                "`runner.handoff.order` must be one of `stop_first`, `start_first`",
+            ]
+        );
+        assert_eq!(diags[0].help.as_deref(), Some("did you mean `machine`?"));
+
+        assert!(check("version: 2\nrunner:\n  double_click: Navy\nprofiles:\n  - name: red\n  - name: navy\n").is_empty());
+        assert!(check("version: 2\nrunner:\n  double_click: menu\nprofiles:\n  - name: red\n").is_empty());
+        let diags = check("version: 2\nrunner:\n  double_click: navvy\nprofiles:\n  - name: red\n  - name: navy\n");
+        assert_eq!(messages(&diags), ["`runner.double_click` names unknown profile `navvy`"]);
+        assert_eq!(diags[0].help.as_deref(), Some("did you mean `navy`?"));
+    }
+
+    #[test]
+    fn gates_v2_fields_and_checks_types() {
+        let diags = check("version: 1\nprofiles:\n  - name: red\n    color: red\n");
+        assert_eq!(messages(&diags), ["`color` requires `version: 2`"]);
+
+        let diags = check("version: 2\nprofiles:\n  - name: red\n    color: nvay\n    runner:\n      keep_foreground: yes\n      start_awc: true\n");
+        let msgs = messages(&diags);
+        assert!(msgs.contains(&"invalid color \"nvay\"".to_string()), "{:?}", msgs);
+        assert!(msgs.contains(&"`keep_foreground` must be true or false".to_string()));
+        assert!(msgs.contains(&"unknown profile runner field `start_awc`".to_string()));
+        assert_eq!(diags.iter().find(|d| d.message.starts_with("invalid color")).unwrap().help.as_deref(), Some("did you mean `navy`?"));
+
+        let diags = check("version: 2\nprofiles:\n  - name: red\n    duration: 60\n  - name: navy\n    duration: 1h30m\n");
+        assert_eq!(messages(&diags), ["invalid duration \"60\" (use e.g. 45m, 1h30m or 90s)"]);
+
+        let diags = check("version: 3\nprofiles: {}\n");
+        assert_eq!(messages(&diags), ["unsupported config version: 3", "`profiles` must be a list"]);
+    }
+
+    #[test]
+    fn reports_yaml_syntax_errors_and_duplicate_keys() {
+        let diags = check("version: 1\nprofiles:\n  - name: [red\n");
+        assert_eq!(diags.len(), 1);
+        assert!(diags[0].message.starts_with("invalid YAML"));
+        assert!(diags[0].pos.is_some());
+
+        let diags = check("version: 1\nversion: 2\nprofiles: [a]\n");
+        assert_eq!(messages(&diags), ["duplicate key `version`", "a profile must be a mapping"]);
+
+        let diags = check("version: 1\nprofiles: []\n");
+        assert_eq!(diags.len(), 1);
+        assert_eq!(diags[0].severity, Severity::Warning);
+        assert_eq!(error_count(&diags), 0);
+    }
+
+    #[test]
+    fn render_shows_location_snippet_and_help() {
+        let yaml = "version: 1\nouput_dir: dist\nprofiles:\n  - name: red\n";
+        let diags = check(yaml);
+        let out = render(&diags, Path::new("configure.yaml"), yaml);
+        let expected = "\
+error: unknown top-level field `ouput_dir`
+ --> configure.yaml:2:1
+  |
+2 | ouput_dir: dist
+  | ^^^^^^^^^
+  = help: did you mean `output_dir`?
+
+configure.yaml: 1 error(s), 0 warning(s)
+";
+        assert_eq!(out, expected);
+    }
+}
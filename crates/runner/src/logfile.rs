@@ -0,0 +1,19 @@
+//! Append-only diagnostics log next to the running EXE (`runner.log`).
+//!
+//! The runner has no console, so anything worth knowing after the fact goes here.
+
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const LOG_FILE: &str = "runner.log";
+
+pub fn log(msg: &str) {
+    let Ok(exe) = std::env::current_exe() else { return; };
+    let Some(dir) = exe.parent() else { return; };
+    let stem = exe.file_stem().and_then(|s| s.to_str()).unwrap_or("runner");
+    let secs = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    if let Ok(mut f) = OpenOptions::new().create(true).append(true).open(dir.join(LOG_FILE)) {
+        let _ = writeln!(f, "{} {}[{}] {}", secs, stem, std::process::id(), msg);
+    }
+}
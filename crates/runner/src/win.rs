@@ -0,0 +1,818 @@
+use crate::logfile::log;
+use family::icon;
+use family::identity::{self, Identity};
+use family::instance::{self, Refresh, Startup};
+use family::siblings::{self, OffStatus, Outcome, SiblingRules};
+use family::switch::{self, SwitchFile, Turn};
+use family::timer::{self, AutoOff, SystemClock, Tick};
+use family::tray::{self, Action, Item};
+use family::win32::{self, Win32FamilyLock, Win32Instances, Win32ProcessTable};
+use family::{AwccSettings, Manifest, Role};
+use std::mem::{size_of, zeroed};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+use windows::core::PCWSTR;
+use windows::Win32::Foundation::{CloseHandle, HANDLE, HINSTANCE, HWND, LPARAM, LRESULT, POINT, WPARAM};
+use windows::Win32::Graphics::Gdi::{
+    CreateBitmap, CreateDIBSection, DeleteObject, BITMAPINFO, BITMAPINFOHEADER, BI_RGB, DIB_RGB_COLORS, HBRUSH, HDC,
+};
+use windows::Win32::System::LibraryLoader::GetModuleHandleW;
+use windows::Win32::System::Diagnostics::ToolHelp::*;
+use windows::Win32::UI::Shell::{ShellExecuteW, Shell_NotifyIconW, NIF_ICON, NIF_MESSAGE, NIF_TIP, NIM_ADD, NIM_DELETE, NIM_MODIFY, NOTIFYICONDATAW};
+use windows::Win32::UI::WindowsAndMessaging::*;
+
+const TRAY_UID: u32 = 1;
+const WM_TRAYICON: u32 = WM_USER + 1;
+static mut MAIN_HWND: HWND = HWND(0);
+static mut FOREGROUND_HWND: HWND = HWND(0);
+static mut FORCE_FOREGROUND: bool = false;
+// Friendly name from the manifest (display_name), shown in tooltip and menu
+static PROFILE_LABEL: OnceLock<String> = OnceLock::new();
+// EXE name this runner acts as: the embedded identity's, else the file name
+static SELF_EXE: OnceLock<String> = OnceLock::new();
+// Tray and window icon: dist/icons/<name>.ico, else drawn in the profile colour
+static mut PROFILE_ICON: HICON = HICON(0);
+// Switch waiting for the handoff timer
+static PENDING_SWITCH: Mutex<Option<PendingSwitch>> = Mutex::new(None);
+const ID_TIMER_HANDOFF: usize = 1;
+// A second launch that finds no window to refresh retries, in case ours was still starting up
+const CLAIM_ATTEMPTS: u32 = 20;
+const CLAIM_RETRY: Duration = Duration::from_millis(250);
+// Auto-off deadline (profile `duration` or `--for`), polled every second
+static AUTO_OFF: Mutex<AutoOff<SystemClock>> = Mutex::new(AutoOff::new(SystemClock));
+const ID_TIMER_AUTO_OFF: usize = 2;
+// Tooltip last handed to the shell, so ticks only touch it when the text changes
+static SHOWN_TIP: Mutex<String> = Mutex::new(String::new());
+
+/// Everything needed to stop the previous profile, at startup or after the handoff overlap.
+struct PendingSwitch {
+    manifest: Manifest,
+    dir: PathBuf,
+    lock: Option<Win32FamilyLock>,
+    file: SwitchFile,
+    ticket: Option<u64>,
+    /// Creation time of the launch that asked for the switch; siblings started later are left alone.
+    since: Option<u64>,
+    off_mode: bool,
+    /// Asked for by a second launch of this profile rather than by our own startup.
+    relaunch: bool,
+}
+
+impl PendingSwitch {
+    /// Stop the siblings, unless a newer switch request has been made meanwhile.
+    fn run(&self) -> Result<Vec<Outcome>, Turn> {
+        let stop = || kill_sibling_processes(&self.manifest, &self.dir, self.off_mode, self.since);
+        match (&self.lock, self.ticket) {
+            (Some(lock), Some(ticket)) => switch::with_turn(lock, &self.file, ticket, stop),
+            _ => Ok(stop()),
+        }
+    }
+}
+
+fn to_wstr(s: &str) -> Vec<u16> {
+    use std::os::windows::ffi::OsStrExt;
+    std::ffi::OsStr::new(s).encode_wide().chain(std::iter::once(0)).collect()
+}
+
+fn current_exe_stem() -> String {
+    std::env::current_exe()
+        .ok()
+        .and_then(|p| p.file_stem().and_then(|s| s.to_str()).map(|s| s.to_string()))
+        .unwrap_or_else(|| "runner".to_string())
+}
+
+fn profile_label() -> String {
+    PROFILE_LABEL.get().cloned().unwrap_or_else(current_exe_stem)
+}
+
+fn exe_dir() -> PathBuf {
+    std::env::current_exe()
+        .ok()
+        .and_then(|p| p.parent().map(|d| d.to_path_buf()))
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+fn load_manifest(dir: &Path) -> Option<Manifest> {
+    match Manifest::load(dir) {
+        Ok(m) => m,
+        Err(e) => {
+            log(&format!("manifest: {}", e));
+            None
+        }
+    }
+}
+
+pub fn run() -> windows::core::Result<()> {
+    let dir = exe_dir();
+    let manifest = load_manifest(&dir);
+    let file_exe = file_exe_name();
+    let embedded = embedded_identity();
+    let me = manifest.as_ref().and_then(|m| identity::resolve(embedded.as_ref(), m, &file_exe));
+    if let (Some(id), Some(m)) = (&embedded, &manifest) {
+        let hash = identity::manifest_hash_in(&dir);
+        for mismatch in identity::check(id, m, hash.as_deref(), &file_exe) {
+            log(&format!("identity: {}", mismatch));
+        }
+    }
+    if let Some(e) = me {
+        let _ = PROFILE_LABEL.set(e.label().to_string());
+        let _ = SELF_EXE.set(e.exe.clone());
+    }
+    let options = me.map(|e| e.options.clone()).unwrap_or_default();
+    let off_mode = me.is_some_and(|e| e.role == Role::Off);
+
+    // Last request wins: take a ticket now, switch later only if no newer launch took one.
+    // A relaunch of a running profile is a request too, so this comes before the instance check.
+    let switch_lock = switch_lock(&dir);
+    let switch_file = SwitchFile::new(&dir);
+    let ticket = switch_lock.as_ref().and_then(|lock| {
+        switch::take_ticket(lock, &switch_file)
+            .map_err(|e| log(&format!("cannot write {}: {}", switch::SEQUENCE_FILE, e)))
+            .ok()
+    });
+    let since = win32::self_creation_time();
+    let auto_off_arg = timer::duration_arg(std::env::args().skip(1))
+        .map_err(|e| log(&format!("auto off: {}", e)))
+        .ok()
+        .flatten();
+
+    // One instance per profile: a second launch hands its ticket to the running one and exits
+    let instance_key = instance::instance_key(&dir.to_string_lossy(), &current_exe_name());
+    let _instance = if off_mode {
+        None
+    } else {
+        let request = Refresh { ticket, created: since, auto_off_secs: auto_off_arg.map(|d| d.as_secs()) };
+        let mut startup = instance::claim(&mut Win32Instances, &instance_key, &request);
+        for _ in 1..CLAIM_ATTEMPTS {
+            if !matches!(startup, Startup::Secondary { notified: false }) {
+                break;
+            }
+            // Its window is not up yet, or it is on its way out after losing to our ticket
+            std::thread::sleep(CLAIM_RETRY);
+            startup = instance::claim(&mut Win32Instances, &instance_key, &request);
+        }
+        match startup {
+            Startup::Primary(guard) => Some(guard),
+            Startup::Secondary { notified } => {
+                log(&format!("already running; refresh {}", if notified { "requested" } else { "not delivered" }));
+                return Ok(());
+            }
+        }
+    };
+    let handoff_delay = manifest.as_ref().and_then(|m| switch::handoff_delay(&m.runner.handoff, off_mode));
+    let pending = manifest.as_ref().map(|m| PendingSwitch {
+        manifest: m.clone(),
+        dir: dir.clone(),
+        lock: switch_lock,
+        file: switch_file,
+        ticket,
+        since,
+        off_mode,
+        relaunch: false,
+    });
+
+    unsafe {
+        let h_instance = GetModuleHandleW(None)?;
+        if !off_mode {
+            PROFILE_ICON = profile_icon(&dir, me);
+        }
+
+        let class_name = to_wstr(siblings::RUNNER_WINDOW_CLASS);
+        let wc = WNDCLASSW {
+            style: CS_HREDRAW | CS_VREDRAW,
+            lpfnWndProc: Some(wndproc),
+            hInstance: HINSTANCE(h_instance.0),
+            hIcon: tray_icon()?,
+            hCursor: LoadCursorW(HINSTANCE(0), IDC_ARROW)?,
+            hbrBackground: HBRUSH(0),
+            lpszClassName: PCWSTR(class_name.as_ptr()),
+            ..zeroed()
+        };
+        let atom = RegisterClassW(&wc);
+        if atom == 0 {
+            return Err(windows::core::Error::from_win32());
+        }
+
+        let hwnd = CreateWindowExW(
+            WINDOW_EX_STYLE(0),
+            PCWSTR(class_name.as_ptr()),
+            PCWSTR(to_wstr(&instance_key).as_ptr()),
+            WS_OVERLAPPEDWINDOW,
+            CW_USEDEFAULT,
+            CW_USEDEFAULT,
+            0,
+            0,
+            HWND(0),
+            HMENU(0),
+            h_instance,
+            None,
+        );
+        if hwnd.0 == 0 {
+            return Err(windows::core::Error::from_win32());
+        }
+        MAIN_HWND = hwnd;
+
+        ShowWindow(hwnd, SW_HIDE);
+
+        // Singleton: terminate sibling color EXEs listed in the manifest, now or
+        // (handoff order start_first) once our tray is up and the overlap has passed
+        let mut outcomes = Vec::new();
+        let mut deferred = None;
+        match (pending, handoff_delay) {
+            (None, _) => {}
+            (Some(p), Some(delay)) => deferred = Some((p, delay)),
+            (Some(p), None) => match p.run() {
+                Ok(o) => outcomes = o,
+                Err(turn) => {
+                    // A newer launch will do the switching (and stop us if we stayed)
+                    log(&format!("superseded by a newer switch request ({:?})", turn));
+                    let _ = DestroyWindow(hwnd);
+                    if off_mode {
+                        std::process::exit(OffStatus::Superseded.exit_code());
+                    }
+                    return Ok(());
+                }
+            },
+        }
+
+        // Off-mode: if this exe has the off role, report whether the siblings are gone and exit (no tray)
+        if off_mode {
+            let status = OffStatus::from_outcomes(&outcomes);
+            log(&format!("off: {} (exit code {})", status, status.exit_code()));
+            let _ = DestroyWindow(hwnd);
+            std::process::exit(status.exit_code());
+        }
+
+        // Ensure AWCC is running (optional, based on the manifest's awcc settings)
+        if let Some(awcc) = manifest.as_ref().and_then(|m| m.awcc.as_ref()) {
+            if options.start_awcc {
+                ensure_awcc_running(awcc);
+            }
+        }
+
+        // Experimental: keep a tiny topmost window in the foreground if enabled
+        FORCE_FOREGROUND = options.keep_foreground || should_force_foreground();
+        if FORCE_FOREGROUND {
+            if let Some(fg_hwnd) = create_foreground_window(HINSTANCE(h_instance.0)) {
+                FOREGROUND_HWND = fg_hwnd;
+                let _ = SetWindowPos(
+                    fg_hwnd,
+                    HWND_TOPMOST,
+                    0,
+                    0,
+                    1,
+                    1,
+                    SWP_NOACTIVATE,
+                );
+                ShowWindow(fg_hwnd, SW_SHOWNA);
+            }
+        }
+
+        // Auto-off: `--for 45m` wins over the profile's `duration`
+        if let Some(limit) = auto_off_arg.or(options.duration()) {
+            start_auto_off(hwnd, limit);
+        }
+
+        add_tray_icon(hwnd)?;
+
+        if let Some((p, delay)) = deferred {
+            log(&format!("handoff: stopping the previous profile in {} ms", delay.as_millis()));
+            if let Ok(mut slot) = PENDING_SWITCH.lock() {
+                *slot = Some(p);
+            }
+            SetTimer(hwnd, ID_TIMER_HANDOFF, delay.as_millis().min(u32::MAX as u128) as u32, None);
+        }
+
+        let mut msg: MSG = zeroed();
+        while GetMessageW(&mut msg, HWND(0), 0, 0).into() {
+            TranslateMessage(&msg);
+            DispatchMessageW(&msg);
+        }
+    }
+    Ok(())
+}
+
+fn should_force_foreground() -> bool {
+    let Ok(path) = std::env::current_exe() else { return false; };
+    let dir = match path.parent() { Some(d) => d, None => return false };
+    dir.join("keep_foreground.txt").exists()
+}
+
+fn ensure_awcc_running(awcc: &AwccSettings) {
+    let awcc_exe = awcc.path.trim();
+    if awcc_exe.is_empty() {
+        return;
+    }
+    let awcc_name = std::path::Path::new(awcc_exe)
+        .file_name()
+        .and_then(|s| s.to_str())
+        .map(|s| s.to_ascii_lowercase())
+        .unwrap_or_else(|| "awcc.exe".to_string());
+
+    if is_process_running(&awcc_name) {
+        return;
+    }
+
+    if !spawn_background(awcc_exe, &awcc.args, awcc.start_minimized) {
+        log(&format!("failed to start AWCC: {}", awcc_exe));
+    }
+}
+
+fn is_process_running(exe_name_lower: &str) -> bool {
+    unsafe {
+        let snapshot = match CreateToolhelp32Snapshot(TH32CS_SNAPPROCESS, 0) {
+            Ok(h) => h,
+            Err(_) => return false,
+        };
+        let mut entry: PROCESSENTRY32W = std::mem::zeroed();
+        entry.dwSize = std::mem::size_of::<PROCESSENTRY32W>() as u32;
+        let mut ok = Process32FirstW(snapshot, &mut entry).is_ok();
+        while ok {
+            let name = wchar_to_lower_string(&entry.szExeFile);
+            if name == exe_name_lower {
+                let _ = CloseHandle(snapshot);
+                return true;
+            }
+            ok = Process32NextW(snapshot, &mut entry).is_ok();
+        }
+        let _ = CloseHandle(snapshot);
+    }
+    false
+}
+
+fn spawn_background(exe_path: &str, args: &[String], start_minimized: bool) -> bool {
+    unsafe {
+        let op = to_wstr("open");
+        let file = to_wstr(exe_path);
+        let params = build_params(args);
+        let show = if start_minimized { SW_SHOWMINNOACTIVE } else { SW_SHOWNORMAL };
+        let h = if params.is_empty() {
+            ShellExecuteW(HWND(0), PCWSTR(op.as_ptr()), PCWSTR(file.as_ptr()), PCWSTR::null(), PCWSTR::null(), show)
+        } else {
+            ShellExecuteW(
+                HWND(0),
+                PCWSTR(op.as_ptr()),
+                PCWSTR(file.as_ptr()),
+                PCWSTR(params.as_ptr()),
+                PCWSTR::null(),
+                show,
+            )
+        };
+        h.0 > 32
+    }
+}
+
+fn build_params(args: &[String]) -> Vec<u16> {
+    if args.is_empty() {
+        return Vec::new();
+    }
+    let mut s = String::new();
+    for a in args {
+        if !s.is_empty() {
+            s.push(' ');
+        }
+        s.push_str(&quote_arg(a));
+    }
+    to_wstr(&s)
+}
+
+fn quote_arg(arg: &str) -> String {
+    if arg.contains(' ') || arg.contains('\t') || arg.contains('"') {
+        let escaped = arg.replace('"', "\\\"");
+        format!("\"{}\"", escaped)
+    } else {
+        arg.to_string()
+    }
+}
+
+unsafe fn create_foreground_window(h_instance: HINSTANCE) -> Option<HWND> {
+    let class_name = to_wstr("AwccCtrlRunnerForegroundWindow");
+    let wc = WNDCLASSW {
+        style: WNDCLASS_STYLES(0),
+        lpfnWndProc: Some(wndproc),
+        hInstance: HINSTANCE(h_instance.0),
+        hIcon: LoadIconW(HINSTANCE(0), IDI_APPLICATION).ok()?,
+        hCursor: LoadCursorW(HINSTANCE(0), IDC_ARROW).ok()?,
+        hbrBackground: HBRUSH(0),
+        lpszClassName: PCWSTR(class_name.as_ptr()),
+        ..zeroed()
+    };
+    let atom = RegisterClassW(&wc);
+    if atom == 0 {
+        return None;
+    }
+
+    let hwnd = CreateWindowExW(
+        WINDOW_EX_STYLE(WS_EX_TOOLWINDOW.0 | WS_EX_TOPMOST.0),
+        PCWSTR(class_name.as_ptr()),
+        PCWSTR(to_wstr("AwccCtrlRunnerForeground").as_ptr()),
+        WS_POPUP,
+        0,
+        0,
+        1,
+        1,
+        HWND(0),
+        HMENU(0),
+        h_instance,
+        None,
+    );
+    if hwnd.0 == 0 {
+        return None;
+    }
+    Some(hwnd)
+}
+
+/// The profile icon, or the stock application icon when none could be made.
+unsafe fn tray_icon() -> windows::core::Result<HICON> {
+    if PROFILE_ICON.0 != 0 {
+        return Ok(PROFILE_ICON);
+    }
+    LoadIconW(HINSTANCE(0), IDI_APPLICATION)
+}
+
+/// `dist/icons/<name>.ico` when the generator (or the user) put one there,
+/// otherwise a swatch drawn in the manifest colour, or one derived from the EXE
+/// stem for a profile the manifest does not know.
+unsafe fn profile_icon(dir: &Path, me: Option<&family::Entry>) -> HICON {
+    let name = me.map(|e| e.name.clone()).unwrap_or_else(current_exe_stem);
+    let size = GetSystemMetrics(SM_CXSMICON).max(16);
+    let ico = dir.join("icons").join(icon::ico_file(&name));
+    if ico.is_file() {
+        let path = to_wstr(&ico.to_string_lossy());
+        match LoadImageW(HINSTANCE(0), PCWSTR(path.as_ptr()), IMAGE_ICON, size, size, LR_LOADFROMFILE) {
+            Ok(h) => return HICON(h.0),
+            Err(e) => log(&format!("icon: cannot load {}: {}", ico.display(), e)),
+        }
+    }
+    let color = me.map(icon::profile_color).unwrap_or_else(|| icon::fallback_color(&name));
+    let label = me.map(|e| e.label().to_string()).unwrap_or(name);
+    match icon_from_pixels(&icon::tray_pixels(color, &label, size as u32), size) {
+        Ok(h) => h,
+        Err(e) => {
+            log(&format!("icon: cannot create the tray icon: {}", e));
+            HICON(0)
+        }
+    }
+}
+
+/// HICON from `size` × `size` BGRA pixels (top row first, straight alpha).
+unsafe fn icon_from_pixels(bgra: &[u8], size: i32) -> windows::core::Result<HICON> {
+    let info = BITMAPINFO {
+        bmiHeader: BITMAPINFOHEADER {
+            biSize: size_of::<BITMAPINFOHEADER>() as u32,
+            biWidth: size,
+            biHeight: -size, // top-down
+            biPlanes: 1,
+            biBitCount: 32,
+            biCompression: BI_RGB.0,
+            ..zeroed()
+        },
+        ..zeroed()
+    };
+    let mut bits: *mut std::ffi::c_void = std::ptr::null_mut();
+    let color = CreateDIBSection(HDC(0), &info, DIB_RGB_COLORS, &mut bits, HANDLE(0), 0)?;
+    std::ptr::copy_nonoverlapping(bgra.as_ptr(), bits as *mut u8, bgra.len());
+    // The alpha channel decides transparency; the mask only has to exist (rows are word-aligned)
+    let mask_bits = vec![0u8; (size as usize).div_ceil(16) * 2 * size as usize];
+    let mask = CreateBitmap(size, size, 1, 1, Some(mask_bits.as_ptr() as *const _));
+    let info = ICONINFO { fIcon: true.into(), xHotspot: 0, yHotspot: 0, hbmMask: mask, hbmColor: color };
+    let icon = CreateIconIndirect(&info);
+    let _ = DeleteObject(color);
+    let _ = DeleteObject(mask);
+    icon
+}
+
+unsafe fn add_tray_icon(hwnd: HWND) -> windows::core::Result<()> {
+    let mut nid: NOTIFYICONDATAW = zeroed();
+    nid.cbSize = size_of::<NOTIFYICONDATAW>() as u32;
+    nid.hWnd = hwnd;
+    nid.uID = TRAY_UID;
+    // Show tooltip with current exe name on mouseover
+    nid.uFlags = NIF_MESSAGE | NIF_ICON | NIF_TIP;
+    nid.uCallbackMessage = WM_TRAYICON;
+    nid.hIcon = tray_icon()?;
+    set_tip(&mut nid, &tip_text());
+    let ok = Shell_NotifyIconW(NIM_ADD, &mut nid);
+    if !ok.as_bool() {
+        return Err(windows::core::Error::from_win32());
+    }
+    Ok(())
+}
+
+/// Tooltip text: project name + profile label, and the time left while auto-off runs.
+fn tip_text() -> String {
+    let text = format!("awcc-ctrl-exe-moc - {}", profile_label());
+    match auto_off_remaining() {
+        Some(left) => format!("{} (off in {})", text, timer::format_remaining(left)),
+        None => text,
+    }
+}
+
+fn set_tip(nid: &mut NOTIFYICONDATAW, text: &str) {
+    if let Ok(mut shown) = SHOWN_TIP.lock() {
+        *shown = text.to_string();
+    }
+    let tip_w = to_wstr(text);
+    let max = nid.szTip.len().saturating_sub(1);
+    for (i, ch) in tip_w.iter().take(max).enumerate() {
+        nid.szTip[i] = *ch;
+    }
+}
+
+/// Refresh the tooltip if its text has changed.
+unsafe fn update_tip(hwnd: HWND) {
+    let text = tip_text();
+    if SHOWN_TIP.lock().is_ok_and(|shown| *shown == text) {
+        return;
+    }
+    let mut nid: NOTIFYICONDATAW = zeroed();
+    nid.cbSize = size_of::<NOTIFYICONDATAW>() as u32;
+    nid.hWnd = hwnd;
+    nid.uID = TRAY_UID;
+    nid.uFlags = NIF_TIP;
+    set_tip(&mut nid, &text);
+    let _ = Shell_NotifyIconW(NIM_MODIFY, &mut nid);
+}
+
+fn auto_off_remaining() -> Option<Duration> {
+    AUTO_OFF.lock().ok().and_then(|t| t.remaining())
+}
+
+unsafe fn start_auto_off(hwnd: HWND, limit: Duration) {
+    if let Ok(mut t) = AUTO_OFF.lock() {
+        t.start(limit);
+    }
+    log(&format!("auto off in {}", timer::format_remaining(limit)));
+    SetTimer(hwnd, ID_TIMER_AUTO_OFF, 1000, None);
+}
+
+unsafe extern "system" fn wndproc(hwnd: HWND, msg: u32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+    match msg {
+        WM_DESTROY => {
+            if hwnd == MAIN_HWND {
+                let mut nid: NOTIFYICONDATAW = zeroed();
+                nid.cbSize = size_of::<NOTIFYICONDATAW>() as u32;
+                nid.hWnd = hwnd;
+                nid.uID = TRAY_UID;
+                let _ = Shell_NotifyIconW(NIM_DELETE, &mut nid);
+                if FOREGROUND_HWND.0 != 0 {
+                    let _ = DestroyWindow(FOREGROUND_HWND);
+                }
+                PostQuitMessage(0);
+            }
+            return LRESULT(0);
+        }
+        WM_TIMER if wparam.0 == ID_TIMER_HANDOFF => {
+            let _ = KillTimer(hwnd, ID_TIMER_HANDOFF);
+            let pending = PENDING_SWITCH.lock().ok().and_then(|mut slot| slot.take());
+            if let Some(p) = pending {
+                if let Err(turn) = p.run() {
+                    log(&format!("superseded by a newer switch request ({:?})", turn));
+                    // After a relaunch we were already running; the newer request stops us if it must
+                    if !p.relaunch {
+                        let _ = DestroyWindow(hwnd);
+                    }
+                }
+            }
+            return LRESULT(0);
+        }
+        WM_COPYDATA => {
+            if let Some(request) = win32::refresh_request(lparam) {
+                refresh(hwnd, request);
+                return LRESULT(1);
+            }
+        }
+        WM_TIMER if wparam.0 == ID_TIMER_AUTO_OFF => {
+            let tick = AUTO_OFF.lock().map(|mut t| t.tick()).unwrap_or(Tick::Idle);
+            match tick {
+                Tick::Running(_) => update_tip(hwnd),
+                Tick::Idle => {
+                    let _ = KillTimer(hwnd, ID_TIMER_AUTO_OFF);
+                    update_tip(hwnd);
+                }
+                Tick::Expired => {
+                    let _ = KillTimer(hwnd, ID_TIMER_AUTO_OFF);
+                    log("auto off: time is up");
+                    let manifest = load_manifest(&exe_dir());
+                    perform(hwnd, tray::timer_expired(manifest.as_ref()));
+                }
+            }
+            return LRESULT(0);
+        }
+        WM_COMMAND => {
+            let id = (wparam.0 & 0xFFFF) as usize;
+            if id == tray::ID_EXIT {
+                let _ = DestroyWindow(hwnd);
+                return LRESULT(0);
+            } else if id == tray::ID_TITLE {
+                // No-op for title click
+                return LRESULT(0);
+            }
+        }
+        _ => {}
+    }
+
+    if msg == WM_TRAYICON {
+        let event = lparam.0 as u32;
+        if event == WM_CONTEXTMENU as u32 || event == WM_RBUTTONUP {
+            show_menu(hwnd);
+            return LRESULT(0);
+        }
+        if event == WM_LBUTTONDBLCLK {
+            let manifest = load_manifest(&exe_dir());
+            perform(hwnd, tray::double_click(manifest.as_ref(), &current_exe_name()));
+            return LRESULT(0);
+        }
+    }
+
+    DefWindowProcW(hwnd, msg, wparam, lparam)
+}
+
+fn current_exe_name() -> String {
+    SELF_EXE.get().cloned().unwrap_or_else(file_exe_name)
+}
+
+fn file_exe_name() -> String {
+    std::env::current_exe()
+        .ok()
+        .and_then(|p| p.file_name().and_then(|s| s.to_str()).map(|s| s.to_string()))
+        .unwrap_or_default()
+}
+
+/// Tray menu from the manifest as it is now, so a regenerated family shows up
+/// without restarting.
+unsafe fn show_menu(hwnd: HWND) {
+    let manifest = load_manifest(&exe_dir());
+    let title = format!("awcc-ctrl-exe-moc - {}", profile_label());
+    let items = tray::menu(manifest.as_ref(), &current_exe_name(), &title, auto_off_remaining());
+    let Some(hmenu) = build_menu(&items) else { return };
+
+    let mut pt = POINT::default();
+    let _ = GetCursorPos(&mut pt);
+    SetForegroundWindow(hwnd);
+    // Take the choice here rather than as WM_COMMAND, so it is resolved against the same manifest
+    let id = TrackPopupMenu(
+        hmenu,
+        TPM_LEFTALIGN | TPM_RIGHTBUTTON | TPM_RETURNCMD | TPM_NONOTIFY,
+        pt.x,
+        pt.y,
+        0,
+        hwnd,
+        None,
+    );
+    let _ = DestroyMenu(hmenu);
+    if id.0 > 0 {
+        perform(hwnd, tray::choose(manifest.as_ref(), &current_exe_name(), id.0 as usize));
+    }
+}
+
+/// Popup menu for `items`; submenus are destroyed along with it.
+unsafe fn build_menu(items: &[Item]) -> Option<HMENU> {
+    let hmenu = CreatePopupMenu().ok()?;
+    for item in items {
+        let _ = match item {
+            Item::Title(text) => {
+                let text = to_wstr(text);
+                AppendMenuW(hmenu, MF_STRING | MF_DISABLED | MF_GRAYED, tray::ID_TITLE, PCWSTR(text.as_ptr()))
+            }
+            Item::Separator => AppendMenuW(hmenu, MF_SEPARATOR, 0, PCWSTR::null()),
+            Item::Command { id, label, checked } => {
+                let flags = if *checked { MF_STRING | MF_CHECKED } else { MF_STRING };
+                let label = to_wstr(label);
+                AppendMenuW(hmenu, flags, *id, PCWSTR(label.as_ptr()))
+            }
+            Item::Submenu { label, items } => {
+                let Some(sub) = build_menu(items) else { continue };
+                let label = to_wstr(label);
+                AppendMenuW(hmenu, MF_STRING | MF_POPUP, sub.0 as usize, PCWSTR(label.as_ptr()))
+            }
+        };
+    }
+    Some(hmenu)
+}
+
+/// Carry out a tray choice. Switching starts the sibling EXE, which stops this
+/// runner through the usual handoff.
+unsafe fn perform(hwnd: HWND, action: Action) {
+    match action {
+        Action::Nothing => {}
+        Action::ShowMenu => show_menu(hwnd),
+        Action::Quit => {
+            log("tray: exit");
+            let _ = DestroyWindow(hwnd);
+        }
+        Action::ExtendTimer => {
+            if let Ok(mut t) = AUTO_OFF.lock() {
+                t.extend(timer::EXTEND_STEP);
+            }
+            log(&format!("auto off: extended by {}", timer::format_remaining(timer::EXTEND_STEP)));
+            update_tip(hwnd);
+        }
+        Action::CancelTimer => {
+            if let Ok(mut t) = AUTO_OFF.lock() {
+                t.cancel();
+            }
+            let _ = KillTimer(hwnd, ID_TIMER_AUTO_OFF);
+            log("auto off: cancelled");
+            update_tip(hwnd);
+        }
+        Action::Launch(exe) => {
+            let dir = exe_dir();
+            let path = dir.join(&exe);
+            match std::process::Command::new(&path).current_dir(&dir).spawn() {
+                Ok(_) => log(&format!("tray: started {}", exe)),
+                Err(e) => log(&format!("tray: failed to start {}: {}", path.display(), e)),
+            }
+        }
+    }
+}
+
+/// Another launch of this profile asked us to re-assert ourselves, and to restart
+/// the auto-off timer if it was given `--for`. It is also a switch request with
+/// its own ticket: a handoff still waiting now stands for it, otherwise the
+/// siblings started before it are stopped from the handoff timer, so the sender
+/// is not kept waiting.
+unsafe fn refresh(hwnd: HWND, request: Refresh) {
+    log(&format!("refresh requested by a second launch (ticket {:?})", request.ticket));
+    if FORCE_FOREGROUND && FOREGROUND_HWND.0 != 0 {
+        let _ = SetWindowPos(FOREGROUND_HWND, HWND_TOPMOST, 0, 0, 1, 1, SWP_NOACTIVATE);
+        ShowWindow(FOREGROUND_HWND, SW_SHOWNA);
+    }
+    if let Some(secs) = request.auto_off_secs {
+        log("auto off: --for from a second launch");
+        start_auto_off(hwnd, Duration::from_secs(secs));
+        update_tip(hwnd);
+    }
+    let Ok(mut slot) = PENDING_SWITCH.lock() else { return };
+    if let Some(p) = slot.as_mut() {
+        p.ticket = request.ticket.or(p.ticket);
+        p.since = request.created.or(p.since);
+        return;
+    }
+    let dir = exe_dir();
+    let Some(manifest) = load_manifest(&dir) else { return };
+    *slot = Some(PendingSwitch {
+        manifest,
+        lock: switch_lock(&dir),
+        file: SwitchFile::new(&dir),
+        dir,
+        ticket: request.ticket,
+        since: request.created,
+        off_mode: false,
+        relaunch: true,
+    });
+    SetTimer(hwnd, ID_TIMER_HANDOFF, 0, None);
+}
+
+/// The family-wide switch lock for the EXEs in `dir`.
+fn switch_lock(dir: &Path) -> Option<Win32FamilyLock> {
+    Win32FamilyLock::new(&instance::family_key(&dir.to_string_lossy()))
+        .map_err(|e| log(&format!("switch lock unavailable: {}", e)))
+        .ok()
+}
+
+/// Stop the siblings; `since` is the creation time of the launch that asked for
+/// the switch, and siblings started after it (newer requests) are left alone.
+fn kill_sibling_processes(manifest: &Manifest, dir: &Path, off_mode: bool, since: Option<u64>) -> Vec<Outcome> {
+    // The profile we act as (embedded identity), as in the instance key: a renamed
+    // copy must spare the real EXE of its own profile, not its own file name
+    let exe_name = current_exe_name();
+    if exe_name.is_empty() {
+        return Vec::new();
+    }
+
+    // Siblings must live in our own directory or one listed in runner.sibling_dirs;
+    // only our own logon session unless the manifest says `scope: machine`
+    let mut rules = SiblingRules::for_dir(manifest, &exe_name, std::process::id(), dir)
+        .with_session(win32::session_of(std::process::id()))
+        .with_created(since);
+    if off_mode {
+        // The off EXE reports its result, so make sure terminated siblings are really gone
+        rules.exit_wait = Duration::from_millis(manifest.runner.off_wait_ms as u64);
+    }
+    let outcomes = siblings::stop_siblings(&mut Win32ProcessTable, &rules);
+    for outcome in &outcomes {
+        log(&outcome.to_string());
+    }
+    outcomes
+}
+
+fn wchar_to_lower_string(buf: &[u16]) -> String {
+    // Convert up to NUL terminator
+    let len = buf.iter().position(|&c| c == 0).unwrap_or(buf.len());
+    let s = String::from_utf16_lossy(&buf[..len]);
+    s.to_ascii_lowercase()
+}
+
+/// The identity the generator appended to this EXE; `None` for older builds.
+fn embedded_identity() -> Option<Identity> {
+    let path = std::env::current_exe().ok()?;
+    match Identity::read(&path) {
+        Ok(id) => id,
+        Err(e) => {
+            log(&format!("identity: {}: {}", path.display(), e));
+            None
+        }
+    }
+}
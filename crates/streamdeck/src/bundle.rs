@@ -0,0 +1,122 @@
+//! The `.sdPlugin` folder the Stream Deck app installs: manifest, property
+//! inspector, icons, and the path of the dist folder the plugin serves.
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub const PLUGIN_UUID: &str = "com.matsuoka-ko.awcc";
+/// The one action: a key bound to a profile or off.
+pub const ACTION_UUID: &str = "com.matsuoka-ko.awcc.profile";
+/// Folder name the Stream Deck app expects under its `Plugins` folder.
+pub const FOLDER: &str = "com.matsuoka-ko.awcc.sdPlugin";
+pub const PLUGIN_EXE: &str = "awcc-streamdeck.exe";
+/// Written next to the plugin EXE; the app starts plugins without telling them where they came from.
+pub const DIST_FILE: &str = "awcc.json";
+
+const PROPERTY_INSPECTOR: &str = include_str!("pi.html");
+const PROPERTY_INSPECTOR_FILE: &str = "pi.html";
+
+/// Contents of [`DIST_FILE`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DistFile {
+    pub dist: PathBuf,
+}
+
+/// Dist folder recorded in the plugin folder `dir`.
+pub fn read_dist(dir: &Path) -> Option<PathBuf> {
+    let text = fs::read_to_string(dir.join(DIST_FILE)).ok()?;
+    serde_json::from_str::<DistFile>(&text).ok().map(|f| f.dist)
+}
+
+/// `manifest.json` of the plugin (SDK version 2).
+pub fn plugin_manifest(version: &str) -> serde_json::Value {
+    json!({
+        "Name": "AWCC lighting",
+        "Version": format!("{}.0", version),
+        "Author": "awcc-ctrl-exe-moc",
+        "Description": "Switch the AWCC lighting profile; the key of the active profile lights up.",
+        "UUID": PLUGIN_UUID,
+        "SDKVersion": 2,
+        "CodePathWin": PLUGIN_EXE,
+        "Icon": "imgs/plugin",
+        "Category": "AWCC lighting",
+        "CategoryIcon": "imgs/plugin",
+        "OS": [{ "Platform": "windows", "MinimumVersion": "10" }],
+        "Software": { "MinimumVersion": "6.0" },
+        "Actions": [{
+            "UUID": ACTION_UUID,
+            "Name": "Lighting profile",
+            "Tooltip": "Switch to a profile; lit while it is active",
+            "Icon": "imgs/action",
+            "PropertyInspectorPath": PROPERTY_INSPECTOR_FILE,
+            // The plugin sets the state from what is actually running
+            "DisableAutomaticStates": true,
+            "SupportedInMultiActions": true,
+            "Controllers": ["Keypad"],
+            "States": [{ "Image": "imgs/action" }, { "Image": "imgs/action-active" }],
+        }],
+    })
+}
+
+/// Icons as SVG, which the Stream Deck app takes in place of PNG.
+fn icons() -> [(&'static str, String); 3] {
+    let swatch = |frame: bool| {
+        let mut svg = String::from(r##"<svg xmlns="http://www.w3.org/2000/svg" width="144" height="144"><rect x="12" y="12" width="120" height="120" rx="18" fill="#7a5cff"/>"##);
+        if frame {
+            svg.push_str(r##"<rect x="5" y="5" width="134" height="134" rx="22" fill="none" stroke="#ffffff" stroke-width="8"/>"##);
+        }
+        svg.push_str("</svg>");
+        svg
+    };
+    [("plugin", swatch(false)), ("action", swatch(false)), ("action-active", swatch(true))]
+}
+
+/// Write the plugin folder `dir` (without the EXE) for the dist folder `dist`.
+pub fn write(dir: &Path, dist: &Path, version: &str) -> io::Result<Vec<PathBuf>> {
+    fs::create_dir_all(dir.join("imgs"))?;
+    let mut files = vec![
+        (dir.join("manifest.json"), serde_json::to_string_pretty(&plugin_manifest(version))?),
+        (dir.join(PROPERTY_INSPECTOR_FILE), PROPERTY_INSPECTOR.to_string()),
+        (dir.join(DIST_FILE), serde_json::to_string_pretty(&DistFile { dist: dist.to_path_buf() })?),
+    ];
+    for (name, svg) in icons() {
+        files.push((dir.join("imgs").join(format!("{}.svg", name)), svg));
+    }
+    for (path, text) in &files {
+        fs::write(path, text)?;
+    }
+    Ok(files.into_iter().map(|(p, _)| p).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    #[test]
+    fn bundle_matches_what_the_plugin_expects() {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos();
+        let root = std::env::temp_dir().join(format!("awcc-sdplugin-test-{}-{}", std::process::id(), nanos));
+        let dir = root.join(FOLDER);
+        let files = write(&dir, Path::new("C:\\tools\\dist"), "1.0.0").unwrap();
+        assert_eq!(files.len(), 6);
+
+        let manifest: serde_json::Value = serde_json::from_str(&fs::read_to_string(dir.join("manifest.json")).unwrap()).unwrap();
+        assert_eq!(manifest["Version"], "1.0.0.0");
+        assert_eq!(manifest["CodePathWin"], PLUGIN_EXE);
+        let action = &manifest["Actions"][0];
+        assert_eq!(action["UUID"], ACTION_UUID);
+        assert_eq!(action["States"].as_array().unwrap().len(), 2);
+        // Every referenced file exists (icons without extension, as the SDK wants)
+        assert!(dir.join(action["PropertyInspectorPath"].as_str().unwrap()).is_file());
+        for icon in [&manifest["Icon"], &action["Icon"], &action["States"][1]["Image"]] {
+            assert!(dir.join(format!("{}.svg", icon.as_str().unwrap())).is_file(), "{}", icon);
+        }
+        assert_eq!(read_dist(&dir), Some(PathBuf::from("C:\\tools\\dist")));
+        assert!(fs::read_to_string(dir.join("pi.html")).unwrap().contains("connectElgatoStreamDeckSocket"));
+        fs::remove_dir_all(root).unwrap();
+    }
+}
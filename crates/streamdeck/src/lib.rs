@@ -0,0 +1,9 @@
+//! Stream Deck integration: a plugin whose keys light up while their profile
+//! is active, the `.sdPlugin` folder the generator writes for it, and
+//! `.streamDeckProfile` export for plain "Open" keys.
+
+pub mod bundle;
+pub mod plugin;
+pub mod profile;
+pub mod ws;
+pub mod zip;
@@ -0,0 +1,59 @@
+#![windows_subsystem = "windows"]
+
+use anyhow::{anyhow, Context, Result};
+use daemon::controller::Controller;
+use daemon::launcher::ProcessLauncher;
+use daemon::protocol::{Command, ErrorCode, ProfileInfo, Reply};
+use daemon::transport;
+use family::Manifest;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use streamdeck::bundle;
+use streamdeck::plugin::{self, Backend, LaunchArgs, Plugin};
+use streamdeck::ws::WebSocket;
+
+/// How often keys are relit after switches made elsewhere.
+const REFRESH: Duration = Duration::from_secs(1);
+
+/// The daemon serving the dist folder if it is running, otherwise a controller
+/// of our own (the plugin lives as long as the Stream Deck app). Either way the
+/// active profile is looked up among the running EXEs once the controller's own
+/// child is gone, so switches made elsewhere show up.
+struct Switcher {
+    dir: PathBuf,
+    local: Controller<ProcessLauncher>,
+}
+
+impl Backend for Switcher {
+    fn handle(&mut self, cmd: &Command) -> Reply {
+        let Ok(conn) = transport::connect(&self.dir) else { return self.local.handle(cmd) };
+        match transport::request(&conn, cmd) {
+            Ok(reply) => reply,
+            // The daemon may have acted on it already; switching here as well could start a second copy
+            Err(e) => {
+                let message = format!("no answer from awcc-ctrld on {}: {}", transport::endpoint(&self.dir), e);
+                let mut reply = Reply::error(self.local.current(), ErrorCode::Spawn, message);
+                if let Command::List = cmd {
+                    reply.profiles = Some(self.local.manifest().profiles().map(ProfileInfo::from).collect());
+                }
+                reply
+            }
+        }
+    }
+}
+
+fn main() -> Result<()> {
+    let args = LaunchArgs::parse(std::env::args().skip(1)).map_err(|e| anyhow!("awcc-streamdeck is started by the Stream Deck app: {}", e))?;
+    let exe_dir = std::env::current_exe()?.parent().map(Path::to_path_buf).context("plugin EXE has no folder")?;
+    let dist = bundle::read_dist(&exe_dir).unwrap_or_else(|| exe_dir.clone());
+    let dir = dist.canonicalize().with_context(|| format!("dist folder not found: {}", dist.display()))?;
+    let manifest = Manifest::load(&dir)
+        .with_context(|| format!("cannot read manifest in {}", dir.display()))?
+        .with_context(|| format!("no manifest.json in {}; run the generator first", dir.display()))?;
+
+    let local = Controller::new(&dir, manifest, ProcessLauncher);
+    let mut plugin = Plugin::new(Switcher { dir, local });
+    let mut ws = WebSocket::connect(args.port).with_context(|| format!("cannot reach the Stream Deck app on port {}", args.port))?;
+    plugin::serve(&mut ws, &args, &mut plugin, REFRESH)?;
+    Ok(())
+}
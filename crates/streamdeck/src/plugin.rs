@@ -0,0 +1,324 @@
+//! The plugin side of the Stream Deck SDK: one key action bound to a profile
+//! (or off), lit while that profile is active.
+//!
+//! The Stream Deck app starts the plugin with
+//! `-port P -pluginUUID U -registerEvent E -info JSON`; the plugin connects to
+//! `ws://127.0.0.1:P`, sends `{"event":E,"uuid":U}`, and from then on receives
+//! events (`willAppear`, `keyDown`, ...) and answers with commands
+//! (`setState`, `setImage`, ...), one JSON object per text message.
+
+use crate::ws::{self, WebSocket};
+use daemon::protocol::{Command, ProfileInfo, Reply};
+use family::Color;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::io;
+use std::time::{Duration, Instant};
+
+/// Profile setting of a key that turns the lights off.
+pub const OFF: &str = "off";
+/// Key state while its profile is not active / active.
+const STATE_IDLE: u8 = 0;
+const STATE_ACTIVE: u8 = 1;
+
+/// Where switches go: the daemon, or the plugin's own controller.
+pub trait Backend {
+    fn handle(&mut self, cmd: &Command) -> Reply;
+}
+
+/// Arguments the Stream Deck app starts the plugin with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LaunchArgs {
+    pub port: u16,
+    pub uuid: String,
+    pub register_event: String,
+}
+
+impl LaunchArgs {
+    /// Parse `-port P -pluginUUID U -registerEvent E`; other pairs (`-info`) are ignored.
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let (mut port, mut uuid, mut register_event) = (None, None, None);
+        let mut args = args.into_iter();
+        while let Some(flag) = args.next() {
+            let value = args.next().ok_or_else(|| format!("{} needs a value", flag))?;
+            match flag.as_str() {
+                "-port" => port = Some(value.parse().map_err(|_| format!("bad port: {}", value))?),
+                "-pluginUUID" => uuid = Some(value),
+                "-registerEvent" => register_event = Some(value),
+                _ => {}
+            }
+        }
+        Ok(LaunchArgs {
+            port: port.ok_or("missing -port")?,
+            uuid: uuid.ok_or("missing -pluginUUID")?,
+            register_event: register_event.ok_or("missing -registerEvent")?,
+        })
+    }
+
+    pub fn register_message(&self) -> Value {
+        json!({ "event": self.register_event, "uuid": self.uuid })
+    }
+}
+
+pub struct Plugin<B> {
+    backend: B,
+    profiles: Vec<ProfileInfo>,
+    current: Option<String>,
+    /// Visible keys by context, with the profile each is set to.
+    keys: BTreeMap<String, Option<String>>,
+}
+
+impl<B: Backend> Plugin<B> {
+    pub fn new(backend: B) -> Self {
+        let mut plugin = Plugin { backend, profiles: Vec::new(), current: None, keys: BTreeMap::new() };
+        plugin.reload();
+        plugin
+    }
+
+    fn reload(&mut self) {
+        let reply = self.backend.handle(&Command::List);
+        self.profiles = reply.profiles.unwrap_or_default();
+        self.current = reply.current;
+    }
+
+    /// Commands answering one event from the Stream Deck app.
+    pub fn on_message(&mut self, text: &str) -> Vec<Value> {
+        let Ok(msg) = serde_json::from_str::<Value>(text) else { return Vec::new() };
+        let Some(context) = msg["context"].as_str() else { return Vec::new() };
+        let setting = msg["payload"]["settings"]["profile"].as_str().filter(|p| !p.is_empty()).map(str::to_string);
+        match msg["event"].as_str().unwrap_or_default() {
+            "willAppear" | "didReceiveSettings" => {
+                self.keys.insert(context.to_string(), setting);
+                self.render(context)
+            }
+            "willDisappear" => {
+                self.keys.remove(context);
+                Vec::new()
+            }
+            "keyDown" => {
+                self.keys.insert(context.to_string(), setting);
+                self.press(context)
+            }
+            "sendToPlugin" if msg["payload"]["request"] == "profiles" => {
+                self.reload();
+                vec![json!({
+                    "event": "sendToPropertyInspector",
+                    "action": msg["action"],
+                    "context": context,
+                    "payload": { "profiles": self.profiles, "off": OFF },
+                })]
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    fn press(&mut self, context: &str) -> Vec<Value> {
+        let Some(Some(name)) = self.keys.get(context) else {
+            return vec![json!({ "event": "showAlert", "context": context })];
+        };
+        let cmd = if name == OFF { Command::Off } else { Command::Switch { name: name.clone() } };
+        let reply = self.backend.handle(&cmd);
+        self.current = reply.current.clone();
+        let mut out = self.render_all();
+        if !reply.ok {
+            out.push(json!({ "event": "showAlert", "context": context }));
+        }
+        out
+    }
+
+    /// Look for switches made elsewhere (other keys, `awcc-ctl`, the tray, a
+    /// profile exiting) and relight the keys if the active profile changed. The
+    /// backend reports the newest running profile, not just the one it started.
+    pub fn refresh(&mut self) -> Vec<Value> {
+        let current = self.backend.handle(&Command::Status).current;
+        if current == self.current {
+            return Vec::new();
+        }
+        self.current = current;
+        self.render_all()
+    }
+
+    fn render_all(&self) -> Vec<Value> {
+        self.keys.keys().flat_map(|c| self.render(c)).collect()
+    }
+
+    /// `setState` and `setImage` for one key.
+    fn render(&self, context: &str) -> Vec<Value> {
+        let profile = self.keys.get(context).cloned().flatten();
+        let active = match profile.as_deref() {
+            Some(OFF) => self.current.is_none(),
+            Some(name) => self.current.as_deref() == Some(name),
+            None => false,
+        };
+        let color = match profile.as_deref() {
+            Some(OFF) => Some(Color { r: 0x3a, g: 0x3a, b: 0x3a }),
+            Some(name) => self
+                .profiles
+                .iter()
+                .find(|p| p.name == name)
+                .map(|p| p.color.as_deref().and_then(Color::parse).unwrap_or(Color { r: 0x80, g: 0x80, b: 0x80 })),
+            None => None,
+        };
+        vec![
+            json!({ "event": "setState", "context": context, "payload": { "state": if active { STATE_ACTIVE } else { STATE_IDLE } } }),
+            json!({ "event": "setImage", "context": context, "payload": { "image": key_image(color, active), "target": 0 } }),
+        ]
+    }
+}
+
+/// Key face as a data URI: the profile colour, dimmed unless active, with a
+/// white frame when active. `None` is a key without a (known) profile.
+pub fn key_image(color: Option<Color>, active: bool) -> String {
+    let mut svg = String::from(r##"<svg xmlns="http://www.w3.org/2000/svg" width="144" height="144"><rect width="144" height="144" fill="#111111"/>"##);
+    match color {
+        Some(c) => svg.push_str(&format!(
+            r#"<rect x="12" y="12" width="120" height="120" rx="18" fill="{}" opacity="{}"/>"#,
+            c.to_hex(),
+            if active { "1" } else { "0.35" }
+        )),
+        None => svg.push_str(r##"<rect x="12" y="12" width="120" height="120" rx="18" fill="none" stroke="#555555" stroke-width="4" stroke-dasharray="12 8"/>"##),
+    }
+    if active {
+        svg.push_str(r##"<rect x="5" y="5" width="134" height="134" rx="22" fill="none" stroke="#ffffff" stroke-width="8"/>"##);
+    }
+    svg.push_str("</svg>");
+    format!("data:image/svg+xml;base64,{}", ws::base64(svg.as_bytes()))
+}
+
+/// Talk to the Stream Deck app until it closes the connection.
+pub fn serve<B: Backend>(ws: &mut WebSocket, args: &LaunchArgs, plugin: &mut Plugin<B>, refresh: Duration) -> io::Result<()> {
+    ws.send_text(&args.register_message().to_string())?;
+    let mut refreshed = Instant::now();
+    loop {
+        let mut out = match ws.recv(refresh) {
+            Ok(Some(text)) => plugin.on_message(&text),
+            Ok(None) => Vec::new(),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        if refreshed.elapsed() >= refresh {
+            out.extend(plugin.refresh());
+            refreshed = Instant::now();
+        }
+        for msg in out {
+            ws.send_text(&msg.to_string())?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ws::fake::{listen, Server};
+    use daemon::protocol::ErrorCode;
+    use std::sync::{Arc, Mutex};
+
+    /// Three profiles; the active one is shared so tests can switch behind the plugin's back.
+    #[derive(Clone, Default)]
+    struct FakeBackend(Arc<Mutex<Option<String>>>);
+
+    impl Backend for FakeBackend {
+        fn handle(&mut self, cmd: &Command) -> Reply {
+            let mut current = self.0.lock().unwrap();
+            match cmd {
+                Command::Switch { name } if !["red", "navy", "broken"].contains(&name.as_str()) => {
+                    return Reply::error(current.clone(), ErrorCode::UnknownProfile, format!("no profile named {:?}", name))
+                }
+                Command::Switch { name } => *current = Some(name.clone()),
+                Command::Off => *current = None,
+                Command::Status => {}
+                Command::List => {
+                    let info = |name: &str, color: Option<&str>| ProfileInfo {
+                        name: name.into(),
+                        label: name.into(),
+                        color: color.map(String::from),
+                        group: None,
+                    };
+                    let profiles = vec![info("red", Some("#ff0000")), info("navy", Some("#000080")), info("broken", None)];
+                    return Reply { profiles: Some(profiles), ..Reply::ok(current.clone()) };
+                }
+            }
+            Reply::ok(current.clone())
+        }
+    }
+
+    fn event(event: &str, context: &str, profile: Option<&str>) -> String {
+        let settings = profile.map_or(json!({}), |p| json!({ "profile": p }));
+        json!({ "event": event, "action": "a", "context": context, "device": "d", "payload": { "settings": settings } }).to_string()
+    }
+
+    fn states(out: &[Value]) -> Vec<(String, u64)> {
+        out.iter()
+            .filter(|m| m["event"] == "setState")
+            .map(|m| (m["context"].as_str().unwrap().to_string(), m["payload"]["state"].as_u64().unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn launch_args_are_parsed() {
+        let args = ["-port", "28196", "-pluginUUID", "ABC", "-registerEvent", "registerPlugin", "-info", "{}"];
+        let args = LaunchArgs::parse(args.map(String::from)).unwrap();
+        assert_eq!(args.port, 28196);
+        assert_eq!(args.register_message(), json!({ "event": "registerPlugin", "uuid": "ABC" }));
+        assert_eq!(LaunchArgs::parse(["-port".to_string()]).unwrap_err(), "-port needs a value");
+        assert_eq!(LaunchArgs::parse(Vec::new()).unwrap_err(), "missing -port");
+    }
+
+    #[test]
+    fn keys_light_up_for_the_active_profile() {
+        let shared = FakeBackend::default();
+        let mut plugin = Plugin::new(shared.clone());
+        let out = plugin.on_message(&event("willAppear", "k-red", Some("red")));
+        assert_eq!(states(&out), [("k-red".to_string(), 0)]);
+        assert!(out[1]["payload"]["image"].as_str().unwrap().starts_with("data:image/svg+xml;base64,"));
+        plugin.on_message(&event("willAppear", "k-off", Some("off")));
+        plugin.on_message(&event("willAppear", "k-new", None));
+
+        let out = plugin.on_message(&event("keyDown", "k-red", Some("red")));
+        assert_eq!(states(&out), [("k-new".into(), 0), ("k-off".into(), 0), ("k-red".into(), 1)]);
+
+        // Unknown profile: alert, nothing lit changes
+        let out = plugin.on_message(&event("keyDown", "k-new", Some("bleu")));
+        assert_eq!(out.last().unwrap(), &json!({ "event": "showAlert", "context": "k-new" }));
+        assert!(states(&out).contains(&("k-red".into(), 1)));
+        // No profile chosen yet
+        plugin.on_message(&event("didReceiveSettings", "k-new", None));
+        assert_eq!(plugin.on_message(&event("keyDown", "k-new", None)), [json!({ "event": "showAlert", "context": "k-new" })]);
+
+        // Switched elsewhere: the next refresh relights the keys, then stays quiet
+        *shared.0.lock().unwrap() = None;
+        let out = plugin.refresh();
+        assert_eq!(states(&out), [("k-new".into(), 0), ("k-off".into(), 1), ("k-red".into(), 0)]);
+        assert!(plugin.refresh().is_empty());
+
+        // The property inspector asks for the profile list
+        let ask = json!({ "event": "sendToPlugin", "action": "a", "context": "k-new", "payload": { "request": "profiles" } });
+        let out = plugin.on_message(&ask.to_string());
+        assert_eq!(out[0]["event"], "sendToPropertyInspector");
+        let names: Vec<&str> = out[0]["payload"]["profiles"].as_array().unwrap().iter().map(|p| p["name"].as_str().unwrap()).collect();
+        assert_eq!(names, ["red", "navy", "broken"]);
+    }
+
+    #[test]
+    fn serves_the_stream_deck_socket() {
+        let (listener, port) = listen();
+        let app = std::thread::spawn(move || {
+            let mut sd = Server::accept(&listener);
+            assert_eq!(sd.recv_json(), json!({ "event": "registerPlugin", "uuid": "PLUGIN" }));
+            sd.send_text(&event("willAppear", "k-navy", Some("navy")));
+            assert_eq!(sd.recv_json()["payload"]["state"], 0);
+            assert_eq!(sd.recv_json()["event"], "setImage");
+            sd.send_text(&event("keyDown", "k-navy", Some("navy")));
+            let lit = sd.recv_json();
+            assert_eq!((lit["event"].as_str(), lit["context"].as_str(), lit["payload"]["state"].as_u64()), (Some("setState"), Some("k-navy"), Some(1)));
+            assert_eq!(sd.recv_json()["event"], "setImage");
+            sd.close();
+        });
+        let args = LaunchArgs { port, uuid: "PLUGIN".into(), register_event: "registerPlugin".into() };
+        let mut ws = WebSocket::connect(port).unwrap();
+        let mut plugin = Plugin::new(FakeBackend::default());
+        serve(&mut ws, &args, &mut plugin, Duration::from_secs(1)).unwrap();
+        app.join().unwrap();
+        assert_eq!(plugin.current.as_deref(), Some("navy"));
+    }
+}
@@ -0,0 +1,181 @@
+//! `.streamDeckProfile` export: a page of "Open" keys, one per profile EXE,
+//! plus Off, that the Stream Deck app imports with a double click.
+//!
+//! The bundle is a zip holding `<UUID>.sdProfile/manifest.json`, whose
+//! `Actions` map `"column,row"` to a key.
+
+use crate::zip;
+use family::instance::fnv1a;
+use family::Manifest;
+use serde_json::{json, Map, Value};
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Built-in "System: Open" action.
+const OPEN_ACTION: &str = "com.elgato.streamdeck.system.open";
+pub const PROFILE_NAME: &str = "AWCC lighting";
+pub const DEFAULT_FILE: &str = "awcc-lighting.streamDeckProfile";
+
+/// Stream Deck models the profile can be laid out for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Device {
+    Mini,
+    Standard,
+    Xl,
+}
+
+impl Device {
+    /// Model identifier the Stream Deck app uses in profiles.
+    fn model(self) -> &'static str {
+        match self {
+            Device::Mini => "20GAI9901",
+            Device::Standard => "20GAA9901",
+            Device::Xl => "20GAT9901",
+        }
+    }
+
+    /// Columns and rows of keys.
+    pub fn grid(self) -> (usize, usize) {
+        match self {
+            Device::Mini => (3, 2),
+            Device::Standard => (5, 3),
+            Device::Xl => (8, 4),
+        }
+    }
+}
+
+impl FromStr for Device {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s.to_ascii_lowercase().as_str() {
+            "mini" => Ok(Device::Mini),
+            "standard" => Ok(Device::Standard),
+            "xl" => Ok(Device::Xl),
+            _ => Err(format!("unknown device {:?} (mini, standard or xl)", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TooManyKeys {
+    pub needed: usize,
+    pub device: Device,
+}
+
+impl fmt::Display for TooManyKeys {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (cols, rows) = self.device.grid();
+        write!(f, "{} keys needed but the {:?} has {}; choose a larger --device", self.needed, self.device, cols * rows)
+    }
+}
+
+impl std::error::Error for TooManyKeys {}
+
+fn open_key(title: &str, exe: &Path) -> Value {
+    json!({
+        "Name": "Open",
+        "Settings": { "openInBrowser": true, "path": exe.to_string_lossy() },
+        "State": 0,
+        "States": [{ "Title": title, "TitleAlignment": "bottom", "ShowTitle": true }],
+        "UUID": OPEN_ACTION,
+    })
+}
+
+/// Folder name inside the bundle; stable for a dist folder so re-imports replace
+/// rather than pile up.
+fn profile_uuid(dist: &Path) -> String {
+    let key = dist.to_string_lossy();
+    let (a, b) = (fnv1a(key.as_bytes()), fnv1a(format!("{}#sdProfile", key).as_bytes()));
+    format!(
+        "{:08X}-{:04X}-4{:03X}-{:04X}-{:012X}",
+        a >> 32,
+        (a >> 16) & 0xffff,
+        a & 0xfff,
+        (b >> 48) & 0x3fff | 0x8000,
+        b & 0xffff_ffff_ffff
+    )
+}
+
+/// Profile manifest: profiles in order from the top-left, Off on the last key.
+pub fn profile_manifest(manifest: &Manifest, dist: &Path, device: Device) -> Result<Value, TooManyKeys> {
+    let (cols, rows) = device.grid();
+    let off = manifest.off();
+    let needed = manifest.profiles().count() + off.iter().count();
+    if needed > cols * rows {
+        return Err(TooManyKeys { needed, device });
+    }
+    let mut actions = Map::new();
+    for (i, entry) in manifest.profiles().enumerate() {
+        actions.insert(format!("{},{}", i % cols, i / cols), open_key(entry.label(), &dist.join(&entry.exe)));
+    }
+    if let Some(off) = off {
+        actions.insert(format!("{},{}", cols - 1, rows - 1), open_key("Off", &dist.join(&off.exe)));
+    }
+    Ok(json!({
+        "Actions": actions,
+        "DeviceModel": device.model(),
+        "DeviceUUID": "",
+        "Name": PROFILE_NAME,
+        "Version": "1.0",
+    }))
+}
+
+/// The `.streamDeckProfile` bundle for the EXEs in `dist` (an absolute path).
+pub fn export(manifest: &Manifest, dist: &Path, device: Device) -> Result<Vec<u8>, TooManyKeys> {
+    let folder = format!("{}.sdProfile/", profile_uuid(dist));
+    let json = serde_json::to_vec_pretty(&profile_manifest(manifest, dist, device)?).expect("profile serializes");
+    Ok(zip::write(&[(folder.clone(), Vec::new()), (format!("{}manifest.json", folder), json)]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use family::manifest::MANIFEST_VERSION;
+    use family::{Entry, Role};
+
+    fn manifest(profiles: usize) -> Manifest {
+        let mut entries: Vec<Entry> = (0..profiles).map(|i| Entry::new(&format!("p{}", i), &format!("p{}.exe", i), Role::Profile)).collect();
+        entries[0].display_name = Some("Meeting red".to_string());
+        entries.push(Entry::new("off", "off.exe", Role::Off));
+        Manifest { version: MANIFEST_VERSION, generator_version: None, profiles: entries, awcc: None, runner: Default::default() }
+    }
+
+    #[test]
+    fn bundle_unpacks_to_one_key_per_exe() {
+        let dist = Path::new("C:\\tools\\dist");
+        let bytes = export(&manifest(6), dist, Device::Standard).unwrap();
+        let entries = zip::read(&bytes).unwrap();
+        assert_eq!(entries.len(), 2);
+        let folder = &entries[0].0;
+        assert!(folder.ends_with(".sdProfile/") && folder.len() == 36 + 11, "{}", folder);
+        assert_eq!(entries[1].0, format!("{}manifest.json", folder));
+
+        let profile: Value = serde_json::from_slice(&entries[1].1).unwrap();
+        assert_eq!(profile["DeviceModel"], "20GAA9901");
+        let actions = profile["Actions"].as_object().unwrap();
+        assert_eq!(actions.len(), 7);
+        let first = &actions["0,0"];
+        assert_eq!(first["UUID"], OPEN_ACTION);
+        assert_eq!(first["States"][0]["Title"], "Meeting red");
+        assert_eq!(first["Settings"]["path"], dist.join("p0.exe").to_string_lossy().as_ref());
+        // Sixth profile wraps to the second row; Off sits bottom-right
+        assert_eq!(actions["0,1"]["States"][0]["Title"], "p5");
+        assert_eq!(actions["4,2"]["States"][0]["Title"], "Off");
+        assert_eq!(actions["4,2"]["Settings"]["path"], dist.join("off.exe").to_string_lossy().as_ref());
+
+        assert_eq!(export(&manifest(6), dist, Device::Standard).unwrap(), bytes, "deterministic");
+        assert_ne!(zip::read(&export(&manifest(6), Path::new("D:\\dist"), Device::Standard).unwrap()).unwrap()[0].0, *folder);
+    }
+
+    #[test]
+    fn layout_must_fit_the_device() {
+        let err = export(&manifest(6), Path::new("C:\\dist"), Device::Mini).unwrap_err();
+        assert_eq!(err, TooManyKeys { needed: 7, device: Device::Mini });
+        assert_eq!(err.to_string(), "7 keys needed but the Mini has 6; choose a larger --device");
+        assert!(export(&manifest(5), Path::new("C:\\dist"), Device::Mini).is_ok());
+        assert_eq!("XL".parse::<Device>(), Ok(Device::Xl));
+        assert!("plus".parse::<Device>().is_err());
+    }
+}
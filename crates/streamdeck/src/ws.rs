@@ -0,0 +1,434 @@
+//! Minimal WebSocket client (RFC 6455) for the Stream Deck app's local socket:
+//! text messages, ping/pong and close. No extensions, no TLS.
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Appended to `Sec-WebSocket-Key` before hashing, per RFC 6455.
+const ACCEPT_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+/// Largest message accepted; Stream Deck events are a few hundred bytes.
+const MAX_MESSAGE: usize = 1024 * 1024;
+
+const OP_CONTINUATION: u8 = 0x0;
+const OP_TEXT: u8 = 0x1;
+const OP_BINARY: u8 = 0x2;
+const OP_CLOSE: u8 = 0x8;
+const OP_PING: u8 = 0x9;
+const OP_PONG: u8 = 0xa;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub fin: bool,
+    pub opcode: u8,
+    pub payload: Vec<u8>,
+}
+
+impl Frame {
+    /// Frames sent by clients must be masked; servers send them in the clear.
+    pub fn encode(&self, mask: Option<[u8; 4]>) -> Vec<u8> {
+        let mut out = vec![(self.fin as u8) << 7 | self.opcode];
+        let mask_bit = if mask.is_some() { 0x80 } else { 0 };
+        let len = self.payload.len();
+        if len < 126 {
+            out.push(mask_bit | len as u8);
+        } else if len <= u16::MAX as usize {
+            out.push(mask_bit | 126);
+            out.extend_from_slice(&(len as u16).to_be_bytes());
+        } else {
+            out.push(mask_bit | 127);
+            out.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+        match mask {
+            Some(key) => {
+                out.extend_from_slice(&key);
+                out.extend(self.payload.iter().enumerate().map(|(i, b)| b ^ key[i % 4]));
+            }
+            None => out.extend_from_slice(&self.payload),
+        }
+        out
+    }
+
+    /// Decode the first frame in `buf`: the frame (unmasked) and its length, or
+    /// `None` if more bytes are needed.
+    pub fn decode(buf: &[u8]) -> io::Result<Option<(Frame, usize)>> {
+        if buf.len() < 2 {
+            return Ok(None);
+        }
+        let (fin, opcode) = (buf[0] & 0x80 != 0, buf[0] & 0x0f);
+        if buf[0] & 0x70 != 0 {
+            return Err(invalid("reserved bits set"));
+        }
+        let masked = buf[1] & 0x80 != 0;
+        let (len, mut at) = match buf[1] & 0x7f {
+            126 => match buf.get(2..4) {
+                Some(b) => (u16::from_be_bytes([b[0], b[1]]) as u64, 4),
+                None => return Ok(None),
+            },
+            127 => match buf.get(2..10) {
+                Some(b) => (u64::from_be_bytes(b.try_into().unwrap()), 10),
+                None => return Ok(None),
+            },
+            n => (n as u64, 2),
+        };
+        if len > MAX_MESSAGE as u64 {
+            return Err(invalid("frame too large"));
+        }
+        let mask = if masked {
+            let Some(key) = buf.get(at..at + 4) else { return Ok(None) };
+            at += 4;
+            Some([key[0], key[1], key[2], key[3]])
+        } else {
+            None
+        };
+        let len = len as usize;
+        let Some(body) = buf.get(at..at + len) else { return Ok(None) };
+        let payload = match mask {
+            Some(key) => body.iter().enumerate().map(|(i, b)| b ^ key[i % 4]).collect(),
+            None => body.to_vec(),
+        };
+        Ok(Some((Frame { fin, opcode, payload }, at + len)))
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("websocket: {}", msg))
+}
+
+/// `Sec-WebSocket-Accept` for a `Sec-WebSocket-Key`.
+pub fn accept_key(key: &str) -> String {
+    base64(&sha1(format!("{}{}", key, ACCEPT_GUID).as_bytes()))
+}
+
+pub struct WebSocket {
+    stream: TcpStream,
+    buf: Vec<u8>,
+    /// Fragments of a message still being received.
+    partial: Option<Vec<u8>>,
+    /// Source of mask keys; masks only need to be unpredictable to proxies, of which there are none.
+    seed: u64,
+}
+
+impl WebSocket {
+    /// Open `ws://127.0.0.1:{port}/`.
+    pub fn connect(port: u16) -> io::Result<WebSocket> {
+        let stream = TcpStream::connect(("127.0.0.1", port))?;
+        stream.set_nodelay(true)?;
+        let seed = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_nanos() as u64) ^ std::process::id() as u64 | 1;
+        let mut ws = WebSocket { stream, buf: Vec::new(), partial: None, seed };
+        let mut nonce = [0u8; 16];
+        for chunk in nonce.chunks_mut(4) {
+            chunk.copy_from_slice(&ws.next_mask());
+        }
+        let key = base64(&nonce);
+        write!(
+            ws.stream,
+            "GET / HTTP/1.1\r\nHost: 127.0.0.1:{}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Key: {}\r\nSec-WebSocket-Version: 13\r\n\r\n",
+            port, key
+        )?;
+
+        let mut reader = BufReader::new(&ws.stream);
+        let mut status = String::new();
+        reader.read_line(&mut status)?;
+        if status.split_whitespace().nth(1) != Some("101") {
+            return Err(io::Error::new(io::ErrorKind::ConnectionRefused, format!("upgrade refused: {}", status.trim())));
+        }
+        let mut accept = None;
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line)? == 0 {
+                return Err(invalid("handshake cut short"));
+            }
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                if name.trim().eq_ignore_ascii_case("sec-websocket-accept") {
+                    accept = Some(value.trim().to_string());
+                }
+            }
+        }
+        // Frames sent right after the handshake may already be buffered
+        ws.buf = reader.buffer().to_vec();
+        if accept.as_deref() != Some(accept_key(&key).as_str()) {
+            return Err(invalid("bad Sec-WebSocket-Accept"));
+        }
+        Ok(ws)
+    }
+
+    fn next_mask(&mut self) -> [u8; 4] {
+        // xorshift64
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 7;
+        self.seed ^= self.seed << 17;
+        (self.seed as u32).to_be_bytes()
+    }
+
+    fn send_frame(&mut self, opcode: u8, payload: &[u8]) -> io::Result<()> {
+        let mask = self.next_mask();
+        let frame = Frame { fin: true, opcode, payload: payload.to_vec() };
+        self.stream.write_all(&frame.encode(Some(mask)))
+    }
+
+    pub fn send_text(&mut self, text: &str) -> io::Result<()> {
+        self.send_frame(OP_TEXT, text.as_bytes())
+    }
+
+    /// Next text message, waiting up to `wait`. Answers pings on the way.
+    /// `Err(UnexpectedEof)` once the server closes the connection.
+    pub fn recv(&mut self, wait: Duration) -> io::Result<Option<String>> {
+        loop {
+            while let Some((frame, used)) = Frame::decode(&self.buf)? {
+                self.buf.drain(..used);
+                if let Some(text) = self.on_frame(frame)? {
+                    return Ok(Some(text));
+                }
+            }
+            self.stream.set_read_timeout(Some(wait.max(Duration::from_millis(1))))?;
+            let mut chunk = [0u8; 4096];
+            match self.stream.read(&mut chunk) {
+                Ok(0) => return Err(closed()),
+                Ok(n) => self.buf.extend_from_slice(&chunk[..n]),
+                Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => return Ok(None),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn on_frame(&mut self, frame: Frame) -> io::Result<Option<String>> {
+        match frame.opcode {
+            OP_PING => {
+                self.send_frame(OP_PONG, &frame.payload)?;
+                Ok(None)
+            }
+            OP_PONG => Ok(None),
+            OP_CLOSE => {
+                let _ = self.send_frame(OP_CLOSE, frame.payload.get(..2).unwrap_or_default());
+                Err(closed())
+            }
+            OP_TEXT | OP_BINARY | OP_CONTINUATION => {
+                let mut data = match (frame.opcode, self.partial.take()) {
+                    (OP_CONTINUATION, Some(data)) => data,
+                    (OP_CONTINUATION, None) => return Err(invalid("continuation without a message")),
+                    (_, Some(_)) => return Err(invalid("new message inside a fragmented one")),
+                    (_, None) => Vec::new(),
+                };
+                data.extend_from_slice(&frame.payload);
+                if data.len() > MAX_MESSAGE {
+                    return Err(invalid("message too large"));
+                }
+                if !frame.fin {
+                    self.partial = Some(data);
+                    return Ok(None);
+                }
+                String::from_utf8(data).map(Some).map_err(|_| invalid("text message is not UTF-8"))
+            }
+            op => Err(invalid(&format!("unknown opcode {}", op))),
+        }
+    }
+
+    pub fn close(mut self) {
+        let _ = self.send_frame(OP_CLOSE, &1000u16.to_be_bytes());
+    }
+}
+
+fn closed() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "websocket closed")
+}
+
+/// Standard base64 with padding (handshake keys, data URIs).
+pub fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let n = (chunk[0] as u32) << 16 | (*chunk.get(1).unwrap_or(&0) as u32) << 8 | *chunk.get(2).unwrap_or(&0) as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x6745_2301, 0xefcd_ab89, 0x98ba_dcfe, 0x1032_5476, 0xc3d2_e1f0];
+    let mut msg = data.to_vec();
+    msg.push(0x80);
+    while msg.len() % 64 != 56 {
+        msg.push(0);
+    }
+    msg.extend_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+    for block in msg.chunks(64) {
+        let mut w = [0u32; 80];
+        for (i, word) in block.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, wi) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5a82_7999),
+                20..=39 => (b ^ c ^ d, 0x6ed9_eba1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8f1b_bcdc),
+                _ => (b ^ c ^ d, 0xca62_c1d6),
+            };
+            let t = a.rotate_left(5).wrapping_add(f).wrapping_add(e).wrapping_add(k).wrapping_add(*wi);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = t;
+        }
+        for (x, y) in h.iter_mut().zip([a, b, c, d, e]) {
+            *x = x.wrapping_add(y);
+        }
+    }
+    let mut out = [0u8; 20];
+    for (i, x) in h.iter().enumerate() {
+        out[i * 4..i * 4 + 4].copy_from_slice(&x.to_be_bytes());
+    }
+    out
+}
+
+#[cfg(test)]
+pub(crate) mod fake {
+    //! Local stand-in for the Stream Deck app's WebSocket server.
+
+    use super::*;
+    use std::net::TcpListener;
+
+    pub struct Server {
+        stream: TcpStream,
+        buf: Vec<u8>,
+    }
+
+    /// Listen on a free port; `accept` completes the handshake of the one client.
+    pub fn listen() -> (TcpListener, u16) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        (listener, port)
+    }
+
+    impl Server {
+        pub fn accept(listener: &TcpListener) -> Server {
+            let (stream, _) = listener.accept().unwrap();
+            stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            let mut reader = BufReader::new(&stream);
+            let mut key = None;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let line = line.trim_end();
+                if line.is_empty() {
+                    break;
+                }
+                if let Some(v) = line.strip_prefix("Sec-WebSocket-Key: ") {
+                    key = Some(v.to_string());
+                }
+            }
+            let accept = accept_key(&key.expect("client sent a key"));
+            let mut server = Server { stream: stream.try_clone().unwrap(), buf: Vec::new() };
+            write!(
+                server.stream,
+                "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+                accept
+            )
+            .unwrap();
+            server
+        }
+
+        pub fn send(&mut self, frame: Frame) {
+            self.stream.write_all(&frame.encode(None)).unwrap();
+        }
+
+        pub fn send_text(&mut self, text: &str) {
+            self.send(Frame { fin: true, opcode: OP_TEXT, payload: text.as_bytes().to_vec() });
+        }
+
+        /// Next frame from the client, which must be masked.
+        pub fn recv(&mut self) -> Frame {
+            loop {
+                if let Some((frame, used)) = Frame::decode(&self.buf).unwrap() {
+                    assert!(self.buf[1] & 0x80 != 0, "client frames are masked");
+                    self.buf.drain(..used);
+                    return frame;
+                }
+                let mut chunk = [0u8; 4096];
+                let n = self.stream.read(&mut chunk).expect("client frame within 5s");
+                assert!(n > 0, "client hung up");
+                self.buf.extend_from_slice(&chunk[..n]);
+            }
+        }
+
+        /// Next text message from the client, parsed as JSON.
+        pub fn recv_json(&mut self) -> serde_json::Value {
+            let frame = self.recv();
+            assert_eq!(frame.opcode, OP_TEXT);
+            serde_json::from_slice(&frame.payload).unwrap()
+        }
+
+        pub fn close(&mut self) {
+            self.send(Frame { fin: true, opcode: OP_CLOSE, payload: 1000u16.to_be_bytes().to_vec() });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::fake::{listen, Server};
+    use super::*;
+
+    #[test]
+    fn hashes_match_reference_vectors() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+        let hex = |b: &[u8]| b.iter().map(|x| format!("{:02x}", x)).collect::<String>();
+        assert_eq!(hex(&sha1(b"abc")), "a9993e364706816aba3e25717850c26c9cd0d89d");
+        assert_eq!(hex(&sha1(&[b'a'; 1000])), "291e9a6c66994949b57ba5e650361e98fc36b1ba");
+        // Example from RFC 6455 section 1.3
+        assert_eq!(accept_key("dGhlIHNhbXBsZSBub25jZQ=="), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+    }
+
+    #[test]
+    fn frames_round_trip() {
+        for len in [0, 5, 125, 126, 300, 70_000] {
+            let frame = Frame { fin: len != 5, opcode: OP_TEXT, payload: vec![b'x'; len] };
+            for mask in [None, Some([1, 2, 3, 4])] {
+                let bytes = frame.encode(mask);
+                assert_eq!(Frame::decode(&bytes).unwrap(), Some((frame.clone(), bytes.len())));
+                assert_eq!(Frame::decode(&bytes[..bytes.len() - 1]).unwrap(), None);
+            }
+        }
+        assert!(Frame::decode(&[0xf1, 0x00]).is_err(), "reserved bits");
+    }
+
+    #[test]
+    fn client_talks_to_stand_in() {
+        let (listener, port) = listen();
+        let server = std::thread::spawn(move || {
+            let mut s = Server::accept(&listener);
+            assert_eq!(s.recv_json()["event"], "hello");
+            s.send(Frame { fin: true, opcode: OP_PING, payload: b"p".to_vec() });
+            // Fragmented reply
+            s.send(Frame { fin: false, opcode: OP_TEXT, payload: b"wor".to_vec() });
+            s.send(Frame { fin: true, opcode: OP_CONTINUATION, payload: b"ld".to_vec() });
+            let pong = s.recv();
+            assert_eq!((pong.opcode, pong.payload), (OP_PONG, b"p".to_vec()));
+            s.close();
+            assert_eq!(s.recv().opcode, OP_CLOSE);
+        });
+        let mut ws = WebSocket::connect(port).unwrap();
+        ws.send_text(r#"{"event":"hello"}"#).unwrap();
+        assert_eq!(ws.recv(Duration::from_secs(5)).unwrap().as_deref(), Some("world"));
+        assert_eq!(ws.recv(Duration::from_secs(5)).unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+        server.join().unwrap();
+    }
+}
@@ -0,0 +1,131 @@
+//! Stored (uncompressed) zip archives: enough for `.streamDeckProfile` bundles.
+//!
+//! Entries carry a fixed timestamp so the same input always gives the same bytes.
+
+use family::png::crc32;
+use std::io;
+
+const LOCAL_HEADER: u32 = 0x0403_4b50;
+const CENTRAL_HEADER: u32 = 0x0201_4b50;
+const END_OF_CENTRAL: u32 = 0x0605_4b50;
+/// Version 2.0: the lowest that knows folders.
+const VERSION: u16 = 20;
+/// Names are UTF-8.
+const FLAG_UTF8: u16 = 0x0800;
+/// MS-DOS date of 1980-01-01, the earliest a zip can hold.
+const DOS_DATE: u16 = 0x21;
+
+/// Archive of `entries` (name, contents) in the given order. Names ending in
+/// `/` are folders.
+pub fn write(entries: &[(String, Vec<u8>)]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut central = Vec::new();
+    for (name, data) in entries {
+        let offset = out.len() as u32;
+        let crc = crc32(data);
+        let put_common = |buf: &mut Vec<u8>| {
+            buf.extend_from_slice(&VERSION.to_le_bytes());
+            buf.extend_from_slice(&FLAG_UTF8.to_le_bytes());
+            buf.extend_from_slice(&0u16.to_le_bytes()); // stored
+            buf.extend_from_slice(&0u16.to_le_bytes()); // time
+            buf.extend_from_slice(&DOS_DATE.to_le_bytes());
+            buf.extend_from_slice(&crc.to_le_bytes());
+            buf.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            buf.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            buf.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            buf.extend_from_slice(&0u16.to_le_bytes()); // extra field
+        };
+
+        out.extend_from_slice(&LOCAL_HEADER.to_le_bytes());
+        put_common(&mut out);
+        out.extend_from_slice(name.as_bytes());
+        out.extend_from_slice(data);
+
+        central.extend_from_slice(&CENTRAL_HEADER.to_le_bytes());
+        central.extend_from_slice(&VERSION.to_le_bytes()); // made by
+        put_common(&mut central);
+        central.extend_from_slice(&[0; 6]); // comment, disk, internal attributes
+        let external: u32 = if name.ends_with('/') { 0x10 } else { 0 }; // MS-DOS folder bit
+        central.extend_from_slice(&external.to_le_bytes());
+        central.extend_from_slice(&offset.to_le_bytes());
+        central.extend_from_slice(name.as_bytes());
+    }
+    let central_offset = out.len() as u32;
+    out.extend_from_slice(&central);
+    out.extend_from_slice(&END_OF_CENTRAL.to_le_bytes());
+    out.extend_from_slice(&[0; 4]); // disk numbers
+    out.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+    out.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+    out.extend_from_slice(&(central.len() as u32).to_le_bytes());
+    out.extend_from_slice(&central_offset.to_le_bytes());
+    out.extend_from_slice(&0u16.to_le_bytes()); // comment
+    out
+}
+
+/// Entries of a stored archive without a comment, checking every CRC.
+pub fn read(bytes: &[u8]) -> io::Result<Vec<(String, Vec<u8>)>> {
+    let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, format!("zip: {}", msg));
+    let u16_at = |at: usize| bytes.get(at..at + 2).map(|b| u16::from_le_bytes([b[0], b[1]])).ok_or_else(|| invalid("truncated"));
+    let u32_at = |at: usize| {
+        bytes.get(at..at + 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]])).ok_or_else(|| invalid("truncated"))
+    };
+
+    let end = bytes.len().checked_sub(22).ok_or_else(|| invalid("too short"))?;
+    if u32_at(end)? != END_OF_CENTRAL {
+        return Err(invalid("no end of central directory"));
+    }
+    let count = u16_at(end + 10)? as usize;
+    let mut at = u32_at(end + 16)? as usize;
+    let mut entries = Vec::with_capacity(count);
+    for _ in 0..count {
+        if u32_at(at)? != CENTRAL_HEADER {
+            return Err(invalid("bad central directory entry"));
+        }
+        if u16_at(at + 10)? != 0 {
+            return Err(invalid("compressed entries are not supported"));
+        }
+        let crc = u32_at(at + 16)?;
+        let size = u32_at(at + 24)? as usize;
+        let name_len = u16_at(at + 28)? as usize;
+        let skip = u16_at(at + 30)? as usize + u16_at(at + 32)? as usize;
+        let local = u32_at(at + 42)? as usize;
+        let name = bytes.get(at + 46..at + 46 + name_len).ok_or_else(|| invalid("truncated"))?;
+        let name = String::from_utf8(name.to_vec()).map_err(|_| invalid("name is not UTF-8"))?;
+        at += 46 + name_len + skip;
+
+        if u32_at(local)? != LOCAL_HEADER {
+            return Err(invalid("bad local header"));
+        }
+        let data_at = local + 30 + u16_at(local + 26)? as usize + u16_at(local + 28)? as usize;
+        let data = bytes.get(data_at..data_at + size).ok_or_else(|| invalid("truncated"))?.to_vec();
+        if crc32(&data) != crc {
+            return Err(invalid(&format!("CRC mismatch in {}", name)));
+        }
+        entries.push((name, data));
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn archives_round_trip() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        let entries = vec![
+            ("dir/".to_string(), Vec::new()),
+            ("dir/a.json".to_string(), b"{}".to_vec()),
+            ("dir/ü.txt".to_string(), vec![7; 1000]),
+        ];
+        let bytes = write(&entries);
+        assert_eq!(&bytes[..4], b"PK\x03\x04");
+        assert_eq!(read(&bytes).unwrap(), entries);
+        assert_eq!(write(&entries), bytes, "deterministic");
+
+        let mut corrupt = bytes.clone();
+        corrupt[500] ^= 0xff; // inside the 1000-byte entry
+        assert!(read(&corrupt).unwrap_err().to_string().contains("CRC mismatch"));
+        assert!(read(b"not a zip").is_err());
+    }
+}